@@ -0,0 +1,74 @@
+use crate::database::{Monitor, MonitorPing};
+
+/// Recent pings the baseline is computed from
+pub const BASELINE_PINGS: i64 = 100;
+
+/// Fewer successful pings than this aren't enough for a baseline, nothing
+/// is degraded until then
+const MIN_BASELINE_PINGS: usize = 30;
+
+/// Scaled deviations above the median a ping may be before it's degraded
+const DEVIATIONS: f64 = 5.0;
+
+/// Scales the median absolute deviation to a standard deviation for normally
+/// distributed response times
+const MAD_SCALE: f64 = 1.4826;
+
+/// Typical response time of a monitor as the median and the median absolute
+/// deviation of its recent successful pings, both robust against the
+/// outliers the baseline is meant to find
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+    pub median_ms: i64,
+    pub mad_ms: i64,
+}
+
+fn median(sorted: &[i64]) -> i64 {
+    sorted[sorted.len() / 2]
+}
+
+impl Baseline {
+    /// Degraded pings are part of the baseline, so a lasting slowdown
+    /// becomes the new normal after a while instead of staying degraded
+    pub fn of(pings: &[MonitorPing]) -> Option<Self> {
+        let mut durations: Vec<i64> = pings
+            .iter()
+            .filter(|ping| !ping.bad && !ping.maintenance)
+            .map(|ping| ping.duration_ms)
+            .collect();
+        if durations.len() < MIN_BASELINE_PINGS {
+            return None;
+        }
+        durations.sort_unstable();
+        let median_ms = median(&durations);
+
+        let mut deviations: Vec<i64> = durations
+            .iter()
+            .map(|duration| (duration - median_ms).abs())
+            .collect();
+        deviations.sort_unstable();
+
+        Some(Baseline {
+            median_ms,
+            mad_ms: median(&deviations),
+        })
+    }
+
+    /// At least twice the median, very steady monitors would otherwise be
+    /// degraded by a few milliseconds of jitter
+    pub fn threshold_ms(&self) -> i64 {
+        let deviation = (DEVIATIONS * MAD_SCALE * self.mad_ms as f64).round() as i64;
+        (self.median_ms + deviation).max(self.median_ms * 2)
+    }
+}
+
+/// The monitor's own threshold, or the baseline of `history` when it has none
+pub fn threshold_ms(monitor: &Monitor, history: &[MonitorPing]) -> Option<i64> {
+    monitor
+        .degraded_threshold_ms
+        .or_else(|| Baseline::of(history).map(|baseline| baseline.threshold_ms()))
+}
+
+pub fn is_degraded(monitor: &Monitor, duration_ms: i64, history: &[MonitorPing]) -> bool {
+    threshold_ms(monitor, history).is_some_and(|threshold| duration_ms > threshold)
+}
@@ -0,0 +1,294 @@
+use crate::{
+    auth::{self, Role, User},
+    database::DatabaseModel,
+    time::PrettyPrint,
+    utils,
+};
+use async_trait::async_trait;
+use rocket::{FromForm, FromFormField};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use std::fmt::Display;
+
+pub const TOKEN_PREFIX: &str = "upt_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, FromFormField)]
+pub enum Scope {
+    #[field(value = "read_only")]
+    ReadOnly,
+    #[field(value = "monitor_write")]
+    MonitorWrite,
+    #[field(value = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub fn all() -> [Scope; 3] {
+        [Scope::ReadOnly, Scope::MonitorWrite, Scope::Admin]
+    }
+
+    pub fn from_str(scope: &str) -> Scope {
+        match scope {
+            "admin" => Scope::Admin,
+            "monitor_write" => Scope::MonitorWrite,
+            _ => Scope::ReadOnly,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Admin => "admin",
+            Scope::MonitorWrite => "monitor_write",
+            Scope::ReadOnly => "read_only",
+        }
+    }
+
+    /// The highest role a request authenticated with this scope can act as
+    pub fn role(&self) -> Role {
+        match self {
+            Scope::Admin => Role::Admin,
+            Scope::MonitorWrite => Role::Editor,
+            Scope::ReadOnly => Role::Viewer,
+        }
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct CreateApiTokenForm {
+    pub name: String,
+    pub scope: Scope,
+    pub expires_in_days: Option<i64>,
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scope: Scope,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+}
+
+impl ApiToken {
+    /// Generates a new token, returns the model alongside the plaintext token
+    /// which is only ever shown once to the user
+    pub fn generate(
+        user_id: i64,
+        name: String,
+        scope: Scope,
+        expires_in_days: Option<i64>,
+    ) -> (Self, String) {
+        let token = format!("{}{}", TOKEN_PREFIX, auth::gen_token(40));
+        let now = chrono::Local::now();
+        let expires_at = expires_in_days
+            .filter(|days| *days > 0)
+            .map(|days| (now + chrono::Duration::days(days)).pretty_string());
+
+        let api_token = ApiToken {
+            id: 0, // field ignored, this is an autoincrement field
+            user_id,
+            name,
+            token_hash: hash_token(&token),
+            scope,
+            created_at: now.pretty_string(),
+            expires_at,
+            last_used_at: None,
+        };
+
+        (api_token, token)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(ref expires_at) => *expires_at <= chrono::Local::now().pretty_string(),
+            None => false,
+        }
+    }
+
+    /// Resolves a plaintext bearer token to its owner and the role it grants,
+    /// which is capped by both the token scope and the owner's own role
+    pub async fn authenticate(token: &str, pool: &Pool<Sqlite>) -> Option<(User, Role)> {
+        let token_hash = hash_token(token);
+        let api_token = sqlx::query!(
+            r#"
+            SELECT * FROM api_token WHERE token_hash = ?
+            "#,
+            token_hash
+        )
+        .fetch_one(pool)
+        .await
+        .ok()?;
+
+        let api_token = ApiToken {
+            id: api_token.id,
+            user_id: api_token.user_id,
+            name: api_token.name,
+            token_hash: api_token.token_hash,
+            scope: Scope::from_str(&api_token.scope),
+            created_at: api_token.created_at,
+            expires_at: api_token.expires_at,
+            last_used_at: api_token.last_used_at,
+        };
+
+        if api_token.is_expired() {
+            return None;
+        }
+
+        let user = User::by_id(api_token.user_id, pool).await.ok()?;
+        if let Err(err) = api_token.touch(pool).await {
+            warn!("Failed to update api token last used: {}", err);
+        }
+
+        let role = user.role.min(api_token.scope.role());
+        Some((user, role))
+    }
+
+    pub async fn touch(&self, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let last_used_at = chrono::Local::now().pretty_string();
+        sqlx::query!(
+            r#"
+            UPDATE api_token SET last_used_at = ? WHERE id = ?
+            "#,
+            last_used_at,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn by_user(user_id: i64, pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM api_token WHERE user_id = ? ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .iter()
+            .map(|api_token| ApiToken {
+                id: api_token.id,
+                user_id: api_token.user_id,
+                name: api_token.name.clone(),
+                token_hash: api_token.token_hash.clone(),
+                scope: Scope::from_str(&api_token.scope),
+                created_at: api_token.created_at.clone(),
+                expires_at: api_token.expires_at.clone(),
+                last_used_at: api_token.last_used_at.clone(),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl DatabaseModel for ApiToken {
+    async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/api_token.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let scope = self.scope.as_str();
+        let query_result = sqlx::query!(
+            r#"
+            INSERT INTO api_token (user_id, name, token_hash, scope, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            self.user_id,
+            self.name,
+            self.token_hash,
+            scope,
+            self.created_at,
+            self.expires_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(ApiToken {
+            id: query_result.last_insert_rowid(),
+            ..self.clone()
+        })
+    }
+
+    async fn by_id(id: i64, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let api_token = sqlx::query!(
+            r#"
+            SELECT * FROM api_token WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ApiToken {
+            id: api_token.id,
+            user_id: api_token.user_id,
+            name: api_token.name,
+            token_hash: api_token.token_hash,
+            scope: Scope::from_str(&api_token.scope),
+            created_at: api_token.created_at,
+            expires_at: api_token.expires_at,
+            last_used_at: api_token.last_used_at,
+        })
+    }
+
+    async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM api_token
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .iter()
+            .map(|api_token| ApiToken {
+                id: api_token.id,
+                user_id: api_token.user_id,
+                name: api_token.name.clone(),
+                token_hash: api_token.token_hash.clone(),
+                scope: Scope::from_str(&api_token.scope),
+                created_at: api_token.created_at.clone(),
+                expires_at: api_token.expires_at.clone(),
+                last_used_at: api_token.last_used_at.clone(),
+            })
+            .collect())
+    }
+
+    async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM api_token WHERE id = ?
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
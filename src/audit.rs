@@ -0,0 +1,183 @@
+use crate::{auth::User, time::PrettyPrint, utils};
+use rocket::FromForm;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// None once the acting user has been deleted, `actor_name` is kept
+    pub actor_id: Option<i64>,
+    pub actor_name: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<i64>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub source_ip: Option<String>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Default, FromForm)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i64>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Only return entries newer than this id, oldest first, which lets log
+    /// shippers resume where they left off
+    pub after_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+fn to_json<T: Serialize>(value: Option<&T>) -> Option<String> {
+    value.and_then(|value| serde_json::to_string(value).ok())
+}
+
+fn from_json(value: Option<String>) -> Option<serde_json::Value> {
+    value.and_then(|value| serde_json::from_str(&value).ok())
+}
+
+/// Who made a change and where it came from
+#[derive(Debug, Clone)]
+pub struct Actor {
+    /// None for changes made by uptime-rs itself, like reconciling the monitors file
+    pub id: Option<i64>,
+    pub name: String,
+    pub source_ip: Option<IpAddr>,
+}
+
+impl Actor {
+    pub fn new(user: &User, source_ip: Option<IpAddr>) -> Self {
+        Actor {
+            id: Some(user.id),
+            name: user.username.clone(),
+            source_ip,
+        }
+    }
+
+    pub fn system(name: &str) -> Self {
+        Actor {
+            id: None,
+            name: name.to_string(),
+            source_ip: None,
+        }
+    }
+}
+
+impl AuditEntry {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/audit_log.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    /// Records a change, failing to write the log never fails the change itself
+    pub async fn record<T: Serialize>(
+        pool: &Pool<Sqlite>,
+        actor: &Actor,
+        action: &str,
+        target_type: &str,
+        target_id: Option<i64>,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) {
+        let before_json = to_json(before);
+        let after_json = to_json(after);
+        let source_ip = actor.source_ip.map(|ip| ip.to_string());
+        let timestamp = chrono::Local::now().pretty_string();
+
+        let query_result = sqlx::query!(
+            r#"
+            INSERT INTO audit_log (actor_id, actor_name, action, target_type, target_id, before_json, after_json, source_ip, timestamp)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            actor.id,
+            actor.name,
+            action,
+            target_type,
+            target_id,
+            before_json,
+            after_json,
+            source_ip,
+            timestamp
+        )
+        .execute(pool)
+        .await;
+
+        if let Err(err) = query_result {
+            warn!("Failed to write audit log entry for {}: {}", action, err);
+        }
+    }
+
+    pub async fn filter(
+        pool: &Pool<Sqlite>,
+        filter: &AuditFilter,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let limit = filter.limit.unwrap_or(100).clamp(1, 1000);
+        let after_id = filter.after_id.unwrap_or(0);
+        // the filter form sends its blank fields as empty strings
+        let given = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+        let actor = given(&filter.actor);
+        let action = given(&filter.action);
+        let target_type = given(&filter.target_type);
+        let since = given(&filter.since);
+        let until = given(&filter.until);
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM audit_log
+            WHERE (?1 IS NULL OR actor_name = ?1)
+              AND (?2 IS NULL OR action = ?2)
+              AND (?3 IS NULL OR target_type = ?3)
+              AND (?4 IS NULL OR target_id = ?4)
+              AND (?5 IS NULL OR timestamp >= ?5)
+              AND (?6 IS NULL OR timestamp <= ?6)
+              AND id > ?7
+            ORDER BY CASE WHEN ?7 > 0 THEN id ELSE -id END
+            LIMIT ?8
+            "#,
+            actor,
+            action,
+            target_type,
+            filter.target_id,
+            since,
+            until,
+            after_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|entry| AuditEntry {
+                id: entry.id,
+                actor_id: entry.actor_id,
+                actor_name: entry.actor_name,
+                action: entry.action,
+                target_type: entry.target_type,
+                target_id: entry.target_id,
+                before: from_json(entry.before_json),
+                after: from_json(entry.after_json),
+                source_ip: entry.source_ip,
+                timestamp: entry.timestamp,
+            })
+            .collect())
+    }
+
+    pub async fn actions(pool: &Pool<Sqlite>) -> Result<Vec<String>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT DISTINCT action FROM audit_log ORDER BY action
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result.into_iter().map(|row| row.action).collect())
+    }
+}
@@ -0,0 +1,632 @@
+use crate::{
+    api_token::ApiToken,
+    database::{DatabaseModel, Setting},
+    time::PrettyPrint,
+    utils,
+};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::{
+    http::{Cookie, CookieJar, SameSite, Status},
+    request::{FromRequest, Outcome, Request},
+    FromForm, FromFormField,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::fmt::Display;
+
+pub const SESSION_COOKIE: &str = "uptime_session";
+
+/// Roles are ordered by privilege, a role implies every role below it
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn all() -> [Role; 3] {
+        [Role::Viewer, Role::Editor, Role::Admin]
+    }
+
+    pub fn from_str(role: &str) -> Role {
+        match role {
+            "admin" => Role::Admin,
+            "editor" => Role::Editor,
+            _ => Role::Viewer,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn can_edit(&self) -> bool {
+        *self >= Role::Editor
+    }
+
+    pub fn is_admin(&self) -> bool {
+        *self >= Role::Admin
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct RoleForm {
+    pub role: Role,
+}
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+pub fn gen_token(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub role: Role,
+    pub created_at: String,
+    /// `<issuer>|<subject>` for accounts provisioned through single sign-on
+    pub external_id: Option<String>,
+    /// Base32 encoded secret, only set once enrollment has been confirmed
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+}
+
+impl User {
+    pub async fn by_username(username: &str, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let user = sqlx::query!(
+            r#"
+            SELECT * FROM user WHERE username = ?
+            "#,
+            username
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(User {
+            id: user.id,
+            username: user.username,
+            password_hash: user.password_hash,
+            role: Role::from_str(&user.role),
+            created_at: user.created_at,
+            external_id: user.external_id,
+            totp_secret: user.totp_secret,
+        })
+    }
+
+    pub async fn by_external_id(
+        external_id: &str,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Self, sqlx::Error> {
+        let user = sqlx::query!(
+            r#"
+            SELECT * FROM user WHERE external_id = ?
+            "#,
+            external_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(User {
+            id: user.id,
+            username: user.username,
+            password_hash: user.password_hash,
+            role: Role::from_str(&user.role),
+            created_at: user.created_at,
+            external_id: user.external_id,
+            totp_secret: user.totp_secret,
+        })
+    }
+
+    pub async fn count(pool: &Pool<Sqlite>) -> Result<i64, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS count FROM user
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(query_result.count as i64)
+    }
+
+    pub async fn username_by_id(id: Option<i64>, pool: &Pool<Sqlite>) -> Option<String> {
+        match id {
+            Some(id) => User::by_id(id, pool).await.ok().map(|user| user.username),
+            None => None,
+        }
+    }
+
+    pub async fn authenticate(username: &str, password: &str, pool: &Pool<Sqlite>) -> Option<Self> {
+        let user = User::by_username(username, pool).await.ok()?;
+        if verify_password(password, &user.password_hash) {
+            Some(user)
+        } else {
+            None
+        }
+    }
+
+    pub async fn set_role(id: i64, role: Role, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let role = role.as_str();
+        sqlx::query!(
+            r#"
+            UPDATE user SET role = ? WHERE id = ?
+            "#,
+            role,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn has_totp(&self) -> bool {
+        self.totp_secret.is_some()
+    }
+
+    /// Local accounts that still have to enroll when two-factor is mandatory,
+    /// single sign-on accounts leave this to the identity provider
+    pub async fn needs_totp_enrollment(&self, pool: &Pool<Sqlite>) -> bool {
+        self.external_id.is_none()
+            && !self.has_totp()
+            && Setting::get_bool(Setting::REQUIRE_TOTP, pool).await
+    }
+
+    pub async fn set_totp_secret(
+        id: i64,
+        totp_secret: Option<String>,
+        pool: &Pool<Sqlite>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE user SET totp_secret = ?, totp_last_step = NULL WHERE id = ?
+            "#,
+            totp_secret,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remembers the time step of an accepted code, false when a code of that
+    /// step or a later one was accepted already
+    pub async fn use_totp_step(
+        id: i64,
+        step: i64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<bool, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            UPDATE user SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)
+            "#,
+            step,
+            id,
+            step
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(query_result.rows_affected() > 0)
+    }
+
+    pub fn login(&self, cookies: &CookieJar<'_>) {
+        // lax so the session survives the redirect back from a single sign-on provider
+        cookies.add_private(
+            Cookie::build((SESSION_COOKIE, self.id.to_string()))
+                .same_site(SameSite::Lax)
+                .http_only(true),
+        );
+    }
+
+    pub fn logout(cookies: &CookieJar<'_>) {
+        cookies.remove_private(Cookie::from(SESSION_COOKIE));
+    }
+}
+
+#[async_trait]
+impl DatabaseModel for User {
+    async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/user.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let role = self.role.as_str();
+        let query_result = sqlx::query!(
+            r#"
+            INSERT INTO user (username, password_hash, role, created_at, external_id) VALUES (?, ?, ?, ?, ?)
+            "#,
+            self.username,
+            self.password_hash,
+            role,
+            self.created_at,
+            self.external_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(User {
+            id: query_result.last_insert_rowid(),
+            ..self.clone()
+        })
+    }
+
+    async fn by_id(id: i64, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let user = sqlx::query!(
+            r#"
+            SELECT * FROM user WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(User {
+            id: user.id,
+            username: user.username,
+            password_hash: user.password_hash,
+            role: Role::from_str(&user.role),
+            created_at: user.created_at,
+            external_id: user.external_id,
+            totp_secret: user.totp_secret,
+        })
+    }
+
+    async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM user ORDER BY username
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .iter()
+            .map(|user| User {
+                id: user.id,
+                username: user.username.clone(),
+                password_hash: user.password_hash.clone(),
+                role: Role::from_str(&user.role),
+                created_at: user.created_at.clone(),
+                external_id: user.external_id.clone(),
+                totp_secret: user.totp_secret.clone(),
+            })
+            .collect())
+    }
+
+    async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM user WHERE id = ?
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UserInvite {
+    pub id: i64,
+    pub token: String,
+    pub role: Role,
+    pub created_by: i64,
+    pub created_at: String,
+    pub accepted_at: Option<String>,
+}
+
+impl UserInvite {
+    pub fn new(role: Role, created_by: i64) -> Self {
+        UserInvite {
+            id: 0, // field ignored, this is an autoincrement field
+            token: gen_token(32),
+            role,
+            created_by,
+            created_at: chrono::Local::now().pretty_string(),
+            accepted_at: None,
+        }
+    }
+
+    /// Look up an invite that hasn't been accepted yet
+    pub async fn pending_by_token(token: &str, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let invite = sqlx::query!(
+            r#"
+            SELECT * FROM user_invite WHERE token = ? AND accepted_at IS NULL
+            "#,
+            token
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(UserInvite {
+            id: invite.id,
+            token: invite.token,
+            role: Role::from_str(&invite.role),
+            created_by: invite.created_by,
+            created_at: invite.created_at,
+            accepted_at: invite.accepted_at,
+        })
+    }
+
+    pub async fn pending(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM user_invite WHERE accepted_at IS NULL ORDER BY created_at DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .iter()
+            .map(|invite| UserInvite {
+                id: invite.id,
+                token: invite.token.clone(),
+                role: Role::from_str(&invite.role),
+                created_by: invite.created_by,
+                created_at: invite.created_at.clone(),
+                accepted_at: invite.accepted_at.clone(),
+            })
+            .collect())
+    }
+
+    pub async fn accept(&self, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let accepted_at = chrono::Local::now().pretty_string();
+        sqlx::query!(
+            r#"
+            UPDATE user_invite SET accepted_at = ? WHERE id = ?
+            "#,
+            accepted_at,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DatabaseModel for UserInvite {
+    async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/user_invite.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let role = self.role.as_str();
+        let query_result = sqlx::query!(
+            r#"
+            INSERT INTO user_invite (token, role, created_by, created_at) VALUES (?, ?, ?, ?)
+            "#,
+            self.token,
+            role,
+            self.created_by,
+            self.created_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(UserInvite {
+            id: query_result.last_insert_rowid(),
+            ..self.clone()
+        })
+    }
+
+    async fn by_id(id: i64, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let invite = sqlx::query!(
+            r#"
+            SELECT * FROM user_invite WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(UserInvite {
+            id: invite.id,
+            token: invite.token,
+            role: Role::from_str(&invite.role),
+            created_by: invite.created_by,
+            created_at: invite.created_at,
+            accepted_at: invite.accepted_at,
+        })
+    }
+
+    async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM user_invite
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .iter()
+            .map(|invite| UserInvite {
+                id: invite.id,
+                token: invite.token.clone(),
+                role: Role::from_str(&invite.role),
+                created_by: invite.created_by,
+                created_at: invite.created_at.clone(),
+                accepted_at: invite.accepted_at.clone(),
+            })
+            .collect())
+    }
+
+    async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM user_invite WHERE id = ?
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+//
+// Request guards
+//
+async fn session_user(request: &Request<'_>) -> Option<User> {
+    let pool = request.rocket().state::<Pool<Sqlite>>()?;
+    let cookie = request.cookies().get_private(SESSION_COOKIE)?;
+    let id = cookie.value().parse::<i64>().ok()?;
+
+    User::by_id(id, pool).await.ok()
+}
+
+fn bearer_token<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    if !request.uri().path().starts_with("/api") {
+        return None;
+    }
+
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Resolves the user behind a request along with the role it may act as,
+/// routes under `/api` also accept a bearer token in place of a session
+async fn request_user(request: &Request<'_>) -> Option<(User, Role)> {
+    match bearer_token(request) {
+        Some(token) => {
+            let pool = request.rocket().state::<Pool<Sqlite>>()?;
+            ApiToken::authenticate(token, pool).await
+        }
+        None => session_user(request).await.map(|user| {
+            let role = user.role;
+            (user, role)
+        }),
+    }
+}
+
+async fn require_role(request: &Request<'_>, role: Role) -> Outcome<User, ()> {
+    let pool = match request.rocket().state::<Pool<Sqlite>>() {
+        Some(pool) => pool,
+        None => return Outcome::Error((Status::InternalServerError, ())),
+    };
+
+    let (user, granted) = match request_user(request).await {
+        Some(found) => found,
+        None => return Outcome::Error((Status::Unauthorized, ())),
+    };
+
+    if bearer_token(request).is_none() && user.needs_totp_enrollment(pool).await {
+        return Outcome::Error((Status::PreconditionRequired, ()));
+    }
+
+    if granted >= role {
+        Outcome::Success(user)
+    } else {
+        Outcome::Error((Status::Forbidden, ()))
+    }
+}
+
+/// Any logged in user, even one that still has to enroll in two-factor
+/// authentication, only used by the routes that handle enrollment
+pub struct SessionUser(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SessionUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match session_user(request).await {
+            Some(user) => Outcome::Success(SessionUser(user)),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Any logged in user, viewers may only look at dashboards
+pub struct Viewer(pub User);
+
+/// Users allowed to create, edit and pause monitors
+pub struct Editor(pub User);
+
+/// Users allowed to manage other users
+pub struct Admin(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Viewer {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        require_role(request, Role::Viewer).await.map(Viewer)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Editor {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        require_role(request, Role::Editor).await.map(Editor)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        require_role(request, Role::Admin).await.map(Admin)
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{database, time::PrettyPrint};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use sqlx::{Pool, Row, Sqlite, SqlitePool};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FILE_PREFIX: &str = "uptime-rs-";
+const FILE_SUFFIX: &str = ".db";
+
+/// Read from `BACKUP_DIR`, `BACKUP_INTERVAL_HOURS` and `BACKUP_KEEP`, scheduled
+/// backups only run when an interval is set
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    pub interval: Option<Duration>,
+    /// Number of backups kept, older ones are removed after every backup
+    pub keep: usize,
+}
+
+impl BackupConfig {
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+
+        BackupConfig {
+            dir: PathBuf::from(var("BACKUP_DIR").unwrap_or_else(|| "backups".to_string())),
+            interval: var("BACKUP_INTERVAL_HOURS")
+                .and_then(|hours| hours.parse::<u64>().ok())
+                .filter(|hours| *hours > 0)
+                .map(|hours| Duration::from_secs(hours * 60 * 60)),
+            keep: var("BACKUP_KEEP")
+                .and_then(|keep| keep.parse().ok())
+                .unwrap_or(7)
+                .max(1),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Backup {
+    pub name: String,
+    pub size_bytes: u64,
+    pub created_at: String,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Copies the live database with `VACUUM INTO`, which is consistent even
+/// while the pingers keep writing
+pub async fn create(config: &BackupConfig, pool: &Pool<Sqlite>) -> Result<Backup, Error> {
+    std::fs::create_dir_all(&config.dir)?;
+
+    let name = format!(
+        "{}{}{}",
+        FILE_PREFIX,
+        Local::now().format("%Y%m%d-%H%M%S"),
+        FILE_SUFFIX
+    );
+    let path = config.dir.join(&name);
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().to_string())
+        .execute(pool)
+        .await
+        .map_err(Error::other)?;
+
+    rotate(config)?;
+    info!("Created backup {}", path.display());
+
+    to_backup(&path)
+}
+
+fn to_backup(path: &Path) -> Result<Backup, Error> {
+    let metadata = std::fs::metadata(path)?;
+    let created_at: DateTime<Local> = metadata.modified()?.into();
+
+    Ok(Backup {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        size_bytes: metadata.len(),
+        created_at: created_at.pretty_string(),
+    })
+}
+
+/// Backups in the backup directory, newest first
+pub fn list(config: &BackupConfig) -> Result<Vec<Backup>, Error> {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(&config.dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX)
+            })
+            .collect(),
+        Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+
+    // the timestamp in the name sorts chronologically
+    paths.sort();
+    paths.reverse();
+
+    paths.iter().map(|path| to_backup(path)).collect()
+}
+
+fn rotate(config: &BackupConfig) -> Result<(), Error> {
+    for backup in list(config)?.iter().skip(config.keep) {
+        std::fs::remove_file(config.dir.join(&backup.name))?;
+        info!("Removed old backup {}", backup.name);
+    }
+
+    Ok(())
+}
+
+/// Backs up every `config.interval` until the process exits
+pub fn schedule(config: BackupConfig, pool: Pool<Sqlite>) {
+    let Some(interval) = config.interval else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(err) = create(&config, &pool).await {
+                warn!("Scheduled backup failed: {}", err);
+            }
+        }
+    });
+}
+
+/// Replaces the database file with `file` after checking that the backup is
+/// intact and not newer than this version, the server must not be running.
+/// Backups of older versions are migrated before they replace anything. The
+/// replaced database is kept next to it, returns its path
+pub async fn restore(file: &str) -> Result<PathBuf, Error> {
+    if !Path::new(file).is_file() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} not found", file),
+        ));
+    }
+
+    let backup = SqlitePool::connect(&format!("sqlite:{}?mode=ro", file))
+        .await
+        .map_err(Error::other)?;
+
+    let integrity: Vec<String> = sqlx::query("PRAGMA integrity_check")
+        .fetch_all(&backup)
+        .await
+        .map_err(Error::other)?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    let version: i64 = sqlx::query("PRAGMA user_version")
+        .fetch_one(&backup)
+        .await
+        .map_err(Error::other)?
+        .get(0);
+    backup.close().await;
+
+    if integrity != ["ok"] {
+        return Err(invalid(format!(
+            "{} failed the integrity check: {}",
+            file,
+            integrity.join(", ")
+        )));
+    }
+    if version > database::SCHEMA_VERSION {
+        return Err(invalid(format!(
+            "{} has schema version {}, this version of uptime-rs supports up to {}",
+            file,
+            version,
+            database::SCHEMA_VERSION
+        )));
+    }
+
+    let target = database::file_path();
+    let staged = PathBuf::from(format!("{}.restoring", target.display()));
+    std::fs::copy(file, &staged)?;
+    if let Err(err) = migrate(&staged).await {
+        std::fs::remove_file(&staged)?;
+        return Err(invalid(format!("{} could not be migrated: {}", file, err)));
+    }
+
+    let suffix = format!(".before-restore-{}", Local::now().format("%Y%m%d-%H%M%S"));
+    let previous = PathBuf::from(format!("{}{}", target.display(), suffix));
+    if target.exists() {
+        std::fs::rename(&target, &previous)?;
+    }
+    // a leftover write-ahead log would be replayed into the restored file
+    for sidecar in ["-wal", "-shm"] {
+        let path = PathBuf::from(format!("{}{}", target.display(), sidecar));
+        if path.exists() {
+            std::fs::rename(&path, format!("{}{}", previous.display(), sidecar))?;
+        }
+    }
+
+    std::fs::rename(&staged, &target)?;
+    Ok(previous)
+}
+
+/// Adds the columns introduced since the backup was made, missing tables are
+/// created on the next start
+async fn migrate(path: &Path) -> Result<(), sqlx::Error> {
+    let pool = SqlitePool::connect(&format!("sqlite:{}", path.display())).await?;
+    let migrated = database::migrate(&pool).await;
+    pool.close().await;
+
+    migrated
+}
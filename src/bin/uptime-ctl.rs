@@ -0,0 +1,384 @@
+//! Command line client for managing a running uptime-rs instance over its REST api
+//!
+//! Authenticates with an api token, see the "API tokens" section of the readme
+
+use chrono::{Duration, Local};
+use clap::{Args, Parser, Subcommand};
+use reqwest::{Client, Method, RequestBuilder};
+use serde_json::Value;
+use std::process::exit;
+use uptime_rs::CreateMonitor;
+
+#[derive(Debug, Parser)]
+#[command(name = "uptime-ctl", about = "Manage a running uptime-rs instance")]
+struct Cli {
+    /// Base url of the instance
+    #[arg(long, env = "UPTIME_URL", default_value = "http://localhost:8000")]
+    url: String,
+    /// Api token, created on the "API tokens" page
+    #[arg(long, env = "UPTIME_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Print raw JSON instead of a table
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage monitors
+    #[command(subcommand)]
+    Monitor(MonitorCommand),
+    /// Inspect incidents
+    #[command(subcommand)]
+    Incident(IncidentCommand),
+    /// Print a versioned export of monitors and settings
+    Export {
+        /// Include the ping history
+        #[arg(long)]
+        history: bool,
+    },
+    /// Import a file made by `export` or an Uptime Kuma backup
+    Import {
+        file: String,
+        /// What to do with monitors and settings that already exist
+        #[arg(long, value_parser = ["merge", "replace"], default_value = "merge")]
+        mode: String,
+        /// Read the file as an Uptime Kuma JSON backup
+        #[arg(long)]
+        uptime_kuma: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum MonitorCommand {
+    List {
+        /// Only monitors with this tag, `name` or `name:value`, repeatable
+        #[arg(long)]
+        tag: Vec<String>,
+        #[arg(long)]
+        search: Option<String>,
+    },
+    Add(Box<AddMonitor>),
+    Pause {
+        id: i64,
+    },
+    Resume {
+        id: i64,
+    },
+    Delete {
+        id: i64,
+    },
+    History {
+        id: i64,
+        /// How far back to go, like 30m, 24h or 7d
+        #[arg(long, default_value = "24h")]
+        since: String,
+    },
+}
+
+#[derive(Debug, Args)]
+struct AddMonitor {
+    #[arg(long)]
+    name: String,
+    #[arg(long)]
+    ip: String,
+    #[arg(long)]
+    port: Option<i64>,
+    /// http, https, ws, wss or icmp
+    #[arg(long)]
+    protocol: Option<String>,
+    /// Seconds between pings
+    #[arg(long, default_value_t = 60)]
+    interval: i64,
+    #[arg(long)]
+    group: Option<i64>,
+    /// Pings slower than this count as degraded, defaults to a baseline
+    #[arg(long)]
+    degraded_ms: Option<i64>,
+    /// Sent after the handshake of a WebSocket monitor
+    #[arg(long)]
+    ws_message: Option<String>,
+    /// Regex the WebSocket reply has to match
+    #[arg(long)]
+    ws_expect: Option<String>,
+    /// How long to wait for the WebSocket reply
+    #[arg(long)]
+    ws_timeout_ms: Option<i64>,
+    /// Echo requests per check of an ICMP monitor
+    #[arg(long)]
+    icmp_count: Option<i64>,
+    /// Packet loss in percent above which an ICMP check is bad
+    #[arg(long)]
+    icmp_max_loss: Option<i64>,
+    /// Comma separated, like `env:prod,db`
+    #[arg(long)]
+    tags: Option<String>,
+    /// Id of a monitor this one depends on, repeatable
+    #[arg(long = "parent")]
+    parents: Vec<i64>,
+}
+
+#[derive(Debug, Subcommand)]
+enum IncidentCommand {
+    List {
+        /// How far back to go, like 30m, 24h or 7d
+        #[arg(long, default_value = "7d")]
+        since: String,
+    },
+}
+
+struct Api {
+    client: Client,
+    url: String,
+    token: String,
+}
+
+impl Api {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(
+                method,
+                format!("{}{}", self.url.trim_end_matches('/'), path),
+            )
+            .bearer_auth(&self.token)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Value {
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(err) => fail(&format!("Request failed: {}", err)),
+        };
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            fail(&format!("{}: {}", status, body));
+        }
+
+        serde_json::from_str(&body).unwrap_or(Value::Null)
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    exit(1);
+}
+
+/// Turns a relative duration like `24h` into the timestamp format used by the api
+fn since_timestamp(since: &str) -> String {
+    let unit_start = since
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(since.len());
+    let (amount, unit) = since.split_at(unit_start);
+    let amount: i64 = amount
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("Invalid duration `{}`", since)));
+
+    let duration = match unit {
+        "s" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        _ => fail(&format!(
+            "Invalid duration unit in `{}`, use s, m, h, d or w",
+            since
+        )),
+    };
+
+    (Local::now() - duration)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn print_table(rows: &Value, columns: &[&str]) {
+    let rows: Vec<Vec<String>> = rows
+        .as_array()
+        .map(|rows| {
+            rows.iter()
+                .map(|row| columns.iter().map(|column| cell(&row[column])).collect())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain([column.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let header: Vec<String> = columns.iter().map(|column| column.to_uppercase()).collect();
+    for row in [header].iter().chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn print(value: &Value, json: bool, columns: &[&str]) {
+    if json || columns.is_empty() {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        );
+    } else {
+        print_table(value, columns);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let api = Api {
+        client: Client::new(),
+        url: cli.url,
+        token: cli.token,
+    };
+
+    match cli.command {
+        Command::Monitor(MonitorCommand::List { tag, search }) => {
+            let mut query: Vec<(&str, String)> = tag.into_iter().map(|tag| ("tag", tag)).collect();
+            if let Some(search) = search {
+                query.push(("search", search));
+            }
+            let monitors = api
+                .send(api.request(Method::GET, "/api/monitors").query(&query))
+                .await;
+            print(
+                &monitors,
+                cli.json,
+                &["id", "name", "ip", "port", "interval", "paused"],
+            );
+        }
+        Command::Monitor(MonitorCommand::Add(add)) => {
+            let AddMonitor {
+                name,
+                ip,
+                port,
+                protocol,
+                interval,
+                group,
+                degraded_ms,
+                ws_message,
+                ws_expect,
+                ws_timeout_ms,
+                icmp_count,
+                icmp_max_loss,
+                tags,
+                parents,
+            } = *add;
+            let data = CreateMonitor {
+                name,
+                ip,
+                port,
+                protocol,
+                interval,
+                group_id: group,
+                degraded_threshold_ms: degraded_ms,
+                ws_message,
+                ws_expect,
+                ws_timeout_ms,
+                icmp_count,
+                icmp_max_loss,
+                tags,
+                parents,
+            };
+            let monitor = api
+                .send(api.request(Method::POST, "/api/monitor").json(&data))
+                .await;
+            print(
+                &Value::Array(vec![monitor]),
+                cli.json,
+                &["id", "name", "ip", "port"],
+            );
+        }
+        Command::Monitor(MonitorCommand::Pause { id }) => {
+            api.send(api.request(Method::POST, &format!("/api/monitor/{}/pause", id)))
+                .await;
+            println!("Paused monitor {}", id);
+        }
+        Command::Monitor(MonitorCommand::Resume { id }) => {
+            api.send(api.request(Method::POST, &format!("/api/monitor/{}/resume", id)))
+                .await;
+            println!("Resumed monitor {}", id);
+        }
+        Command::Monitor(MonitorCommand::Delete { id }) => {
+            api.send(api.request(Method::DELETE, &format!("/api/monitor/{}", id)))
+                .await;
+            println!("Deleted monitor {}", id);
+        }
+        Command::Monitor(MonitorCommand::History { id, since }) => {
+            let request = api
+                .request(Method::GET, &format!("/api/monitor/{}/pings", id))
+                .query(&[("since", since_timestamp(&since))]);
+            let pings = api.send(request).await;
+            print(
+                &pings,
+                cli.json,
+                &["timestamp", "status", "duration_ms", "bad"],
+            );
+        }
+        Command::Incident(IncidentCommand::List { since }) => {
+            let request = api
+                .request(Method::GET, "/api/incidents")
+                .query(&[("since", since_timestamp(&since))]);
+            let incidents = api.send(request).await;
+            print(
+                &incidents,
+                cli.json,
+                &[
+                    "monitor_id",
+                    "monitor_name",
+                    "started_at",
+                    "resolved_at",
+                    "failed_pings",
+                ],
+            );
+        }
+        Command::Export { history } => {
+            let request = api
+                .request(Method::GET, "/api/export")
+                .query(&[("history", history)]);
+            let document = api.send(request).await;
+            print(&document, true, &[]);
+        }
+        Command::Import {
+            file,
+            mode,
+            uptime_kuma,
+        } => {
+            let content = std::fs::read_to_string(&file)
+                .unwrap_or_else(|err| fail(&format!("Failed to read {}: {}", file, err)));
+            let request = match uptime_kuma {
+                true => api.request(Method::POST, "/api/import/uptime-kuma"),
+                false => api
+                    .request(Method::POST, "/api/import")
+                    .query(&[("mode", mode)]),
+            };
+            let request = request
+                .header("Content-Type", "application/json")
+                .body(content);
+            let summary = api.send(request).await;
+            print(&summary, true, &[]);
+        }
+    }
+}
@@ -1,13 +1,266 @@
 use crate::{
+    api_token::ApiToken,
+    audit::AuditEntry,
+    auth::{User, UserInvite},
+    dependency::Dependency,
+    flapping::Flap,
+    group::Group,
+    maintenance::MaintenanceWindow,
     ping::{self, PingerManager},
+    probe::{EchoStats, Timings},
+    rollup::DailyUptime,
+    slo::Slo,
+    tag::{MonitorTag, Tag},
     time::DateOffset,
+    totp::RecoveryCode,
     utils::{self, json_response, serde_response},
 };
 use async_trait::async_trait;
 use dotenv::dotenv;
-use rocket::{http::Status, State};
+use rocket::{http::Status, FromForm};
 use serde::{Deserialize, Serialize};
 use sqlx::{migrate::MigrateDatabase, Pool, Sqlite, SqlitePool};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Stored as `PRAGMA user_version`, bump it with every migration
+pub const SCHEMA_VERSION: i64 = 8;
+
+/// A column added to a table that older databases already have, the schema
+/// files only create missing tables
+struct Migration {
+    /// Schema version that added the column
+    version: i64,
+    table: &'static str,
+    column: &'static str,
+    /// Run in order when the column is missing
+    statements: &'static [&'static str],
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        table: "monitor",
+        column: "created_by",
+        statements: &["ALTER TABLE monitor ADD COLUMN created_by INTEGER REFERENCES user(id) ON DELETE SET NULL"],
+    },
+    Migration {
+        version: 1,
+        table: "monitor",
+        column: "updated_by",
+        statements: &["ALTER TABLE monitor ADD COLUMN updated_by INTEGER REFERENCES user(id) ON DELETE SET NULL"],
+    },
+    Migration {
+        version: 1,
+        table: "user",
+        column: "external_id",
+        statements: &[
+            "ALTER TABLE user ADD COLUMN external_id TEXT",
+            "CREATE UNIQUE INDEX IF NOT EXISTS user_external_id ON user (external_id)",
+        ],
+    },
+    Migration {
+        version: 1,
+        table: "user",
+        column: "totp_secret",
+        statements: &["ALTER TABLE user ADD COLUMN totp_secret TEXT"],
+    },
+    Migration {
+        version: 1,
+        table: "user",
+        column: "totp_last_step",
+        statements: &["ALTER TABLE user ADD COLUMN totp_last_step INTEGER"],
+    },
+    Migration {
+        version: 1,
+        table: "monitor",
+        column: "managed_key",
+        statements: &[
+            "ALTER TABLE monitor ADD COLUMN managed_key TEXT",
+            "CREATE UNIQUE INDEX IF NOT EXISTS monitor_managed_key ON monitor (managed_key)",
+        ],
+    },
+    Migration {
+        version: 2,
+        table: "monitor",
+        column: "group_id",
+        statements: &["ALTER TABLE monitor ADD COLUMN group_id INTEGER REFERENCES monitor_group(id) ON DELETE SET NULL"],
+    },
+    Migration {
+        version: 3,
+        table: "monitor_ping",
+        column: "maintenance",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN maintenance INTEGER NOT NULL DEFAULT 0"],
+    },
+    Migration {
+        version: 4,
+        table: "monitor_ping",
+        column: "unreachable",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN unreachable INTEGER NOT NULL DEFAULT 0"],
+    },
+    Migration {
+        version: 5,
+        table: "monitor_ping",
+        column: "dns_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN dns_ms INTEGER"],
+    },
+    Migration {
+        version: 5,
+        table: "monitor_ping",
+        column: "connect_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN connect_ms INTEGER"],
+    },
+    Migration {
+        version: 5,
+        table: "monitor_ping",
+        column: "tls_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN tls_ms INTEGER"],
+    },
+    Migration {
+        version: 5,
+        table: "monitor_ping",
+        column: "ttfb_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN ttfb_ms INTEGER"],
+    },
+    Migration {
+        version: 5,
+        table: "monitor_ping",
+        column: "download_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN download_ms INTEGER"],
+    },
+    Migration {
+        version: 6,
+        table: "monitor",
+        column: "degraded_threshold_ms",
+        statements: &["ALTER TABLE monitor ADD COLUMN degraded_threshold_ms INTEGER"],
+    },
+    Migration {
+        version: 6,
+        table: "monitor_ping",
+        column: "degraded",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN degraded INTEGER NOT NULL DEFAULT 0"],
+    },
+    Migration {
+        version: 7,
+        table: "monitor",
+        column: "protocol",
+        statements: &["ALTER TABLE monitor ADD COLUMN protocol TEXT NOT NULL DEFAULT 'http'"],
+    },
+    Migration {
+        version: 7,
+        table: "monitor",
+        column: "ws_message",
+        statements: &["ALTER TABLE monitor ADD COLUMN ws_message TEXT"],
+    },
+    Migration {
+        version: 7,
+        table: "monitor",
+        column: "ws_expect",
+        statements: &["ALTER TABLE monitor ADD COLUMN ws_expect TEXT"],
+    },
+    Migration {
+        version: 7,
+        table: "monitor",
+        column: "ws_timeout_ms",
+        statements: &["ALTER TABLE monitor ADD COLUMN ws_timeout_ms INTEGER"],
+    },
+    Migration {
+        version: 7,
+        table: "monitor_ping",
+        column: "round_trip_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN round_trip_ms INTEGER"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor",
+        column: "icmp_count",
+        statements: &["ALTER TABLE monitor ADD COLUMN icmp_count INTEGER"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor",
+        column: "icmp_max_loss",
+        statements: &["ALTER TABLE monitor ADD COLUMN icmp_max_loss INTEGER"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor_ping",
+        column: "icmp_sent",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN icmp_sent INTEGER"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor_ping",
+        column: "icmp_received",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN icmp_received INTEGER"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor_ping",
+        column: "rtt_min_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN rtt_min_ms REAL"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor_ping",
+        column: "rtt_avg_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN rtt_avg_ms REAL"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor_ping",
+        column: "rtt_max_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN rtt_max_ms REAL"],
+    },
+    Migration {
+        version: 8,
+        table: "monitor_ping",
+        column: "jitter_ms",
+        statements: &["ALTER TABLE monitor_ping ADD COLUMN jitter_ms REAL"],
+    },
+];
+
+/// Adds the columns of every migration newer than the database. A table
+/// created from the current schema file already has them and is skipped,
+/// as is a table the database doesn't have yet
+pub async fn migrate(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await?;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+            .bind(migration.table)
+            .fetch_all(pool)
+            .await?;
+        if columns.is_empty() || columns.iter().any(|column| column == migration.column) {
+            continue;
+        }
+
+        let mut tx = pool.begin().await?;
+        for statement in migration.statements {
+            sqlx::query(statement).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        info!(
+            "Added {}.{} to the database",
+            migration.table, migration.column
+        );
+    }
+
+    Ok(())
+}
+
+/// Path of the sqlite file in `DATABASE_URL`
+pub fn file_path() -> PathBuf {
+    dotenv().ok();
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let path = database_url
+        .trim_start_matches("sqlite://")
+        .trim_start_matches("sqlite:");
+
+    PathBuf::from(path.split('?').next().unwrap_or_default())
+}
 
 pub async fn initialize() -> Pool<Sqlite> {
     dotenv().ok();
@@ -34,11 +287,57 @@ pub async fn initialize() -> Pool<Sqlite> {
     MonitorPing::initialize(&pool)
         .await
         .expect("Failed to initialize monitor_ping table");
+    User::initialize(&pool)
+        .await
+        .expect("Failed to initialize user table");
+    UserInvite::initialize(&pool)
+        .await
+        .expect("Failed to initialize user_invite table");
+    ApiToken::initialize(&pool)
+        .await
+        .expect("Failed to initialize api_token table");
+    RecoveryCode::initialize(&pool)
+        .await
+        .expect("Failed to initialize recovery_code table");
+    Setting::initialize(&pool)
+        .await
+        .expect("Failed to initialize setting table");
+    AuditEntry::initialize(&pool)
+        .await
+        .expect("Failed to initialize audit_log table");
+    Group::initialize(&pool)
+        .await
+        .expect("Failed to initialize monitor_group table");
+    Tag::initialize(&pool)
+        .await
+        .expect("Failed to initialize tag tables");
+    MaintenanceWindow::initialize(&pool)
+        .await
+        .expect("Failed to initialize maintenance_window table");
+    Dependency::initialize(&pool)
+        .await
+        .expect("Failed to initialize monitor_dependency table");
+    DailyUptime::initialize(&pool)
+        .await
+        .expect("Failed to initialize monitor_daily table");
+    Slo::initialize(&pool)
+        .await
+        .expect("Failed to initialize slo table");
+    Flap::initialize(&pool)
+        .await
+        .expect("Failed to initialize monitor_flap table");
+
+    migrate(&pool).await.expect("Failed to migrate database");
+
+    sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+        .execute(&pool)
+        .await
+        .expect("Failed to set schema version");
 
     pool
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Monitor {
     pub id: i64,
     pub name: String,
@@ -47,6 +346,30 @@ pub struct Monitor {
     pub protocol: ping::Protocol,
     pub interval: i64,
     pub paused: bool,
+    pub created_by: Option<i64>,
+    pub updated_by: Option<i64>,
+    /// Key of the entry in the monitors file, managed monitors are read-only in the UI
+    pub managed_key: Option<String>,
+    pub group_id: Option<i64>,
+    /// Slower successful pings count as degraded, without it a baseline of
+    /// the recent response times is used, see `anomaly`
+    #[serde(default)]
+    pub degraded_threshold_ms: Option<i64>,
+    /// Sent after the handshake of a WebSocket monitor
+    #[serde(default)]
+    pub ws_message: Option<String>,
+    /// Regex the reply of a WebSocket monitor has to match
+    #[serde(default)]
+    pub ws_expect: Option<String>,
+    /// How long a WebSocket monitor waits for the reply
+    #[serde(default)]
+    pub ws_timeout_ms: Option<i64>,
+    /// Echo requests an ICMP monitor sends per check
+    #[serde(default)]
+    pub icmp_count: Option<i64>,
+    /// Packet loss in percent above which an ICMP check is bad
+    #[serde(default)]
+    pub icmp_max_loss: Option<i64>,
 }
 
 impl Monitor {
@@ -57,8 +380,10 @@ impl Monitor {
         pings.iter().fold(0, |acc, ping| acc + ping.duration_ms) / pings.len() as i64
     }
 
+    /// Pings during maintenance don't count either way
     pub async fn get_uptime_percentage(&self, pool: &Pool<Sqlite>) -> i64 {
-        let pings = MonitorPing::last_n(pool, self.id, 30).await;
+        let mut pings = MonitorPing::last_n(pool, self.id, 30).await;
+        pings.retain(|ping| !ping.maintenance);
         let total_pings = pings.len() as i64;
         let bad_pings = pings.iter().filter(|ping| ping.bad).count() as i64;
 
@@ -85,6 +410,10 @@ impl Monitor {
         }
     }
 
+    pub fn is_managed(&self) -> bool {
+        self.managed_key.is_some()
+    }
+
     pub fn hostname(&self) -> String {
         match self.port {
             Some(port) => format!("{}:{}", self.ip, port),
@@ -99,14 +428,25 @@ impl Monitor {
     }
 
     pub async fn update(&self, pool: &Pool<Sqlite>) -> Result<&Self, sqlx::Error> {
+        let protocol = self.protocol.as_str();
         sqlx::query!(
             r#"
-            UPDATE monitor SET name = ?, ip = ?, port = ?, interval = ? WHERE id = ?
+            UPDATE monitor SET name = ?, ip = ?, port = ?, protocol = ?, interval = ?, updated_by = ?, managed_key = ?, group_id = ?, degraded_threshold_ms = ?, ws_message = ?, ws_expect = ?, ws_timeout_ms = ?, icmp_count = ?, icmp_max_loss = ? WHERE id = ?
             "#,
             self.name,
             self.ip,
             self.port,
+            protocol,
             self.interval,
+            self.updated_by,
+            self.managed_key,
+            self.group_id,
+            self.degraded_threshold_ms,
+            self.ws_message,
+            self.ws_expect,
+            self.ws_timeout_ms,
+            self.icmp_count,
+            self.icmp_max_loss,
             self.id
         )
         .execute(pool)
@@ -123,18 +463,19 @@ impl Monitor {
     //         .unwrap_or(false)
     // }
 
-    pub async fn toggle_paused(
+    pub async fn set_paused(
         id: i64,
+        paused: bool,
+        updated_by: Option<i64>,
         pool: &Pool<Sqlite>,
-        pinger_manager: &State<PingerManager>,
+        pinger_manager: &PingerManager,
     ) -> Result<bool, sqlx::Error> {
-        let monitor = Monitor::by_id(id, pool).await.unwrap();
-        let paused = !monitor.paused;
         sqlx::query!(
             r#"
-            UPDATE monitor SET paused = ? WHERE id = ?
+            UPDATE monitor SET paused = ?, updated_by = ? WHERE id = ?
             "#,
             paused,
+            updated_by,
             id
         )
         .execute(pool)
@@ -151,6 +492,93 @@ impl Monitor {
     }
 }
 
+/// Current state of a monitor, ordered from best to worst so a group can
+/// roll up to the worst state of its children with `max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MonitorState {
+    Paused,
+    Maintenance,
+    Up,
+    Degraded,
+    Unreachable,
+    Unknown,
+    Down,
+}
+
+impl MonitorState {
+    pub fn of(monitor: &Monitor, last_ping: Option<&MonitorPing>) -> Self {
+        if monitor.paused {
+            return MonitorState::Paused;
+        }
+
+        match last_ping {
+            Some(ping) if ping.maintenance => MonitorState::Maintenance,
+            Some(ping) if ping.unreachable => MonitorState::Unreachable,
+            Some(ping) if !ping.bad && ping.status.code <= 400 && ping.degraded => {
+                MonitorState::Degraded
+            }
+            Some(ping) if !ping.bad && ping.status.code <= 400 => MonitorState::Up,
+            Some(_) => MonitorState::Down,
+            None => MonitorState::Unknown,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MonitorState::Paused => "paused",
+            MonitorState::Maintenance => "maintenance",
+            MonitorState::Unreachable => "unreachable",
+            MonitorState::Up => "up",
+            MonitorState::Degraded => "degraded",
+            MonitorState::Unknown => "unknown",
+            MonitorState::Down => "down",
+        }
+    }
+}
+
+impl std::fmt::Display for MonitorState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, FromForm)]
+pub struct MonitorFilter {
+    /// Matched against the name, host and tags
+    pub search: Option<String>,
+    /// `name` or `name:value`, a monitor needs every listed tag
+    pub tag: Vec<String>,
+}
+
+impl MonitorFilter {
+    pub fn is_empty(&self) -> bool {
+        self.search.as_deref().unwrap_or_default().is_empty() && self.tag.is_empty()
+    }
+
+    pub fn matches(&self, monitor: &Monitor, tags: &[MonitorTag]) -> bool {
+        let tags_match = self
+            .tag
+            .iter()
+            .all(|filter| tags.iter().any(|tag| tag.matches(filter)));
+
+        let search = self
+            .search
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        let search_match = search.is_empty()
+            || monitor.name.to_lowercase().contains(&search)
+            || monitor.hostname().to_lowercase().contains(&search)
+            || tags
+                .iter()
+                .any(|tag| tag.key().to_lowercase().contains(&search));
+
+        tags_match && search_match
+    }
+}
+
 #[async_trait]
 impl DatabaseModel for Monitor {
     async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
@@ -161,26 +589,49 @@ impl DatabaseModel for Monitor {
     }
 
     async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let protocol = self.protocol.as_str();
         let query_result = sqlx::query!(
             r#"
-            INSERT INTO monitor (name, ip, port, interval) VALUES (?, ?, ?, ?)
+            INSERT INTO monitor (name, ip, port, protocol, interval, paused, created_by, updated_by, managed_key, group_id, degraded_threshold_ms, ws_message, ws_expect, ws_timeout_ms, icmp_count, icmp_max_loss) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
             self.name,
             self.ip,
             self.port,
+            protocol,
             self.interval,
+            self.paused,
+            self.created_by,
+            self.updated_by,
+            self.managed_key,
+            self.group_id,
+            self.degraded_threshold_ms,
+            self.ws_message,
+            self.ws_expect,
+            self.ws_timeout_ms,
+            self.icmp_count,
+            self.icmp_max_loss,
         )
         .execute(pool)
         .await?;
 
         Ok(Monitor {
-            protocol: ping::Protocol::HTTP,
+            protocol: self.protocol.clone(),
             id: query_result.last_insert_rowid(),
             name: self.name.clone(),
             ip: self.ip.clone(),
             port: self.port,
             interval: self.interval,
             paused: self.paused,
+            created_by: self.created_by,
+            updated_by: self.updated_by,
+            managed_key: self.managed_key.clone(),
+            group_id: self.group_id,
+            degraded_threshold_ms: self.degraded_threshold_ms,
+            ws_message: self.ws_message.clone(),
+            ws_expect: self.ws_expect.clone(),
+            ws_timeout_ms: self.ws_timeout_ms,
+            icmp_count: self.icmp_count,
+            icmp_max_loss: self.icmp_max_loss,
         })
     }
 
@@ -195,13 +646,23 @@ impl DatabaseModel for Monitor {
         .await?;
 
         Ok(Monitor {
-            protocol: ping::Protocol::HTTP,
+            protocol: ping::Protocol::parse(&monitor.protocol).unwrap_or(ping::Protocol::HTTP),
             id: monitor.id,
             name: monitor.name,
             ip: monitor.ip,
             port: monitor.port,
             interval: monitor.interval,
             paused: monitor.paused.to_bool(),
+            created_by: monitor.created_by,
+            updated_by: monitor.updated_by,
+            managed_key: monitor.managed_key,
+            group_id: monitor.group_id,
+            degraded_threshold_ms: monitor.degraded_threshold_ms,
+            ws_message: monitor.ws_message,
+            ws_expect: monitor.ws_expect,
+            ws_timeout_ms: monitor.ws_timeout_ms,
+            icmp_count: monitor.icmp_count,
+            icmp_max_loss: monitor.icmp_max_loss,
         })
     }
 
@@ -217,13 +678,23 @@ impl DatabaseModel for Monitor {
         Ok(query_result
             .iter()
             .map(|monitor| Monitor {
-                protocol: ping::Protocol::HTTP,
+                protocol: ping::Protocol::parse(&monitor.protocol).unwrap_or(ping::Protocol::HTTP),
                 id: monitor.id,
                 name: monitor.name.clone(),
                 ip: monitor.ip.clone(),
                 port: monitor.port,
                 interval: monitor.interval,
                 paused: monitor.paused.to_bool(),
+                created_by: monitor.created_by,
+                updated_by: monitor.updated_by,
+                managed_key: monitor.managed_key.clone(),
+                group_id: monitor.group_id,
+                degraded_threshold_ms: monitor.degraded_threshold_ms,
+                ws_message: monitor.ws_message.clone(),
+                ws_expect: monitor.ws_expect.clone(),
+                ws_timeout_ms: monitor.ws_timeout_ms,
+                icmp_count: monitor.icmp_count,
+                icmp_max_loss: monitor.icmp_max_loss,
             })
             .collect())
     }
@@ -261,6 +732,17 @@ pub struct MonitorPing {
     pub status: Status,
     pub duration_ms: i64,
     pub bad: bool,
+    /// Recorded during a maintenance window, left out of uptime and incidents
+    pub maintenance: bool,
+    /// Failed while a parent monitor was down, see `Dependency`
+    pub unreachable: bool,
+    /// Succeeded but slower than the monitor's threshold or baseline
+    pub degraded: bool,
+    /// From sending the message of a WebSocket monitor until the reply
+    pub round_trip_ms: Option<i64>,
+    /// Packet loss and round trip times of an ICMP monitor
+    pub icmp: Option<EchoStats>,
+    pub timings: Option<Timings>,
 }
 
 impl MonitorPing {
@@ -288,21 +770,114 @@ impl MonitorPing {
     // }
     // pub async fn between_dates(pool: &Pool<Sqlite>, dates: (String,String))
 
+    /// Pings inside `offset`, newest first. Graphs `downsample` them after
+    /// taking their stats
     pub async fn between(
         pool: &Pool<Sqlite>,
         monitor_id: i64,
         offset: DateOffset,
-        limit: i64,
     ) -> Result<Vec<Self>, sqlx::Error> {
-        let (start, end) = offset.normalize().pretty_strings();
+        let (start, end) = offset.pretty_strings();
         let query_result = sqlx::query!(
             r#"
-            SELECT * FROM monitor_ping WHERE monitor_id=? AND timestamp BETWEEN ? AND ? LIMIT ?;
+            SELECT * FROM monitor_ping WHERE monitor_id=? AND timestamp BETWEEN ? AND ? ORDER BY timestamp DESC;
             "#,
             monitor_id,
             start,
-            end,
-            limit
+            end
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .iter()
+            .map(|monitor_ping| MonitorPing {
+                id: monitor_ping.id,
+                status: Status::from_code(monitor_ping.status as u16).expect("Invalid status code"),
+                timestamp: monitor_ping.timestamp.clone(),
+                monitor_id: monitor_ping.monitor_id,
+                duration_ms: monitor_ping.duration_ms,
+                bad: monitor_ping.bad.to_bool(),
+                maintenance: monitor_ping.maintenance.to_bool(),
+                unreachable: monitor_ping.unreachable.to_bool(),
+                degraded: monitor_ping.degraded.to_bool(),
+                round_trip_ms: monitor_ping.round_trip_ms,
+                icmp: EchoStats::from_columns(
+                    monitor_ping.icmp_sent,
+                    monitor_ping.icmp_received,
+                    monitor_ping.rtt_min_ms,
+                    monitor_ping.rtt_avg_ms,
+                    monitor_ping.rtt_max_ms,
+                    monitor_ping.jitter_ms,
+                ),
+                timings: Timings::from_columns(
+                    monitor_ping.dns_ms,
+                    monitor_ping.connect_ms,
+                    monitor_ping.tls_ms,
+                    monitor_ping.ttfb_ms,
+                    monitor_ping.download_ms,
+                ),
+            })
+            .collect())
+    }
+
+    /// Merges runs of neighbouring pings until at most `points` are left. A
+    /// merged ping keeps the newest timestamp and the average duration, and
+    /// stays bad when any of its pings failed
+    pub fn downsample(pings: Vec<Self>, points: usize) -> Vec<Self> {
+        if points == 0 || pings.len() <= points {
+            return pings;
+        }
+
+        let size = pings.len().div_ceil(points);
+        pings
+            .chunks(size)
+            .map(|chunk| {
+                let worst = chunk.iter().find(|ping| ping.bad).unwrap_or(&chunk[0]);
+                let round_trips: Vec<i64> =
+                    chunk.iter().filter_map(|ping| ping.round_trip_ms).collect();
+                MonitorPing {
+                    id: chunk[0].id,
+                    monitor_id: chunk[0].monitor_id,
+                    timestamp: chunk[0].timestamp.clone(),
+                    status: worst.status,
+                    duration_ms: chunk.iter().map(|ping| ping.duration_ms).sum::<i64>()
+                        / chunk.len() as i64,
+                    bad: worst.bad,
+                    maintenance: worst.maintenance,
+                    unreachable: worst.unreachable,
+                    degraded: chunk.iter().any(|ping| ping.degraded),
+                    round_trip_ms: (!round_trips.is_empty())
+                        .then(|| round_trips.iter().sum::<i64>() / round_trips.len() as i64),
+                    icmp: EchoStats::merge(
+                        &chunk
+                            .iter()
+                            .filter_map(|ping| ping.icmp)
+                            .collect::<Vec<_>>(),
+                    ),
+                    timings: Timings::average(
+                        &chunk
+                            .iter()
+                            .filter_map(|ping| ping.timings)
+                            .collect::<Vec<_>>(),
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    /// Pings recorded at or after `since`, oldest first
+    pub async fn since(
+        pool: &Pool<Sqlite>,
+        monitor_id: i64,
+        since: &str,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM monitor_ping WHERE monitor_id=? AND timestamp >= ? ORDER BY timestamp;
+            "#,
+            monitor_id,
+            since
         )
         .fetch_all(pool)
         .await?;
@@ -316,6 +891,25 @@ impl MonitorPing {
                 monitor_id: monitor_ping.monitor_id,
                 duration_ms: monitor_ping.duration_ms,
                 bad: monitor_ping.bad.to_bool(),
+                maintenance: monitor_ping.maintenance.to_bool(),
+                unreachable: monitor_ping.unreachable.to_bool(),
+                degraded: monitor_ping.degraded.to_bool(),
+                round_trip_ms: monitor_ping.round_trip_ms,
+                icmp: EchoStats::from_columns(
+                    monitor_ping.icmp_sent,
+                    monitor_ping.icmp_received,
+                    monitor_ping.rtt_min_ms,
+                    monitor_ping.rtt_avg_ms,
+                    monitor_ping.rtt_max_ms,
+                    monitor_ping.jitter_ms,
+                ),
+                timings: Timings::from_columns(
+                    monitor_ping.dns_ms,
+                    monitor_ping.connect_ms,
+                    monitor_ping.tls_ms,
+                    monitor_ping.ttfb_ms,
+                    monitor_ping.download_ms,
+                ),
             })
             .collect())
     }
@@ -341,6 +935,25 @@ impl MonitorPing {
                     monitor_id: monitor_ping.monitor_id,
                     duration_ms: monitor_ping.duration_ms,
                     bad: monitor_ping.bad.to_bool(),
+                    maintenance: monitor_ping.maintenance.to_bool(),
+                    unreachable: monitor_ping.unreachable.to_bool(),
+                    degraded: monitor_ping.degraded.to_bool(),
+                    round_trip_ms: monitor_ping.round_trip_ms,
+                    icmp: EchoStats::from_columns(
+                        monitor_ping.icmp_sent,
+                        monitor_ping.icmp_received,
+                        monitor_ping.rtt_min_ms,
+                        monitor_ping.rtt_avg_ms,
+                        monitor_ping.rtt_max_ms,
+                        monitor_ping.jitter_ms,
+                    ),
+                    timings: Timings::from_columns(
+                        monitor_ping.dns_ms,
+                        monitor_ping.connect_ms,
+                        monitor_ping.tls_ms,
+                        monitor_ping.ttfb_ms,
+                        monitor_ping.download_ms,
+                    ),
                 })
                 .collect()
         } else {
@@ -349,6 +962,61 @@ impl MonitorPing {
     }
 }
 
+/// Response times of the successful pings in a range, failed pings are left
+/// out since their duration is mostly the timeout
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseStats {
+    pub count: usize,
+    pub min_ms: i64,
+    pub max_ms: i64,
+    pub average_ms: i64,
+    pub p50_ms: i64,
+    pub p90_ms: i64,
+    pub p95_ms: i64,
+    pub p99_ms: i64,
+}
+
+/// Stats of the pings between `start` and `end`
+#[derive(Debug, Serialize)]
+pub struct RangeStats {
+    pub start: String,
+    pub end: String,
+    pub pings: usize,
+    pub response: Option<ResponseStats>,
+}
+
+/// Nearest-rank percentile of an ascending slice
+fn percentile(sorted: &[i64], percent: usize) -> i64 {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+impl ResponseStats {
+    /// `None` when there isn't a single successful ping
+    pub fn of(pings: &[MonitorPing]) -> Option<Self> {
+        let mut durations: Vec<i64> = pings
+            .iter()
+            .filter(|ping| !ping.bad)
+            .map(|ping| ping.duration_ms)
+            .collect();
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort_unstable();
+
+        Some(ResponseStats {
+            count: durations.len(),
+            min_ms: durations[0],
+            max_ms: durations[durations.len() - 1],
+            average_ms: durations.iter().sum::<i64>() / durations.len() as i64,
+            p50_ms: percentile(&durations, 50),
+            p90_ms: percentile(&durations, 90),
+            p95_ms: percentile(&durations, 95),
+            p99_ms: percentile(&durations, 99),
+        })
+    }
+}
+
 pub trait ToBool {
     fn to_bool(&self) -> bool;
 }
@@ -373,15 +1041,41 @@ impl DatabaseModel for MonitorPing {
     }
 
     async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let dns_ms = self.timings.map(|timings| timings.dns_ms);
+        let connect_ms = self.timings.map(|timings| timings.connect_ms);
+        let tls_ms = self.timings.map(|timings| timings.tls_ms);
+        let ttfb_ms = self.timings.map(|timings| timings.ttfb_ms);
+        let download_ms = self.timings.map(|timings| timings.download_ms);
+        let icmp_sent = self.icmp.map(|icmp| icmp.sent);
+        let icmp_received = self.icmp.map(|icmp| icmp.received);
+        let rtt_min_ms = self.icmp.map(|icmp| icmp.min_ms);
+        let rtt_avg_ms = self.icmp.map(|icmp| icmp.avg_ms);
+        let rtt_max_ms = self.icmp.map(|icmp| icmp.max_ms);
+        let jitter_ms = self.icmp.map(|icmp| icmp.jitter_ms);
         let query_result = sqlx::query!(
             r#"
-            INSERT INTO monitor_ping (monitor_id, timestamp, status, duration_ms, bad) VALUES (?, ?, ?, ?, ?)
+            INSERT INTO monitor_ping (monitor_id, timestamp, status, duration_ms, bad, maintenance, unreachable, degraded, round_trip_ms, icmp_sent, icmp_received, rtt_min_ms, rtt_avg_ms, rtt_max_ms, jitter_ms, dns_ms, connect_ms, tls_ms, ttfb_ms, download_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
             self.monitor_id,
             self.timestamp,
             self.status.code,
             self.duration_ms,
-            self.bad
+            self.bad,
+            self.maintenance,
+            self.unreachable,
+            self.degraded,
+            self.round_trip_ms,
+            icmp_sent,
+            icmp_received,
+            rtt_min_ms,
+            rtt_avg_ms,
+            rtt_max_ms,
+            jitter_ms,
+            dns_ms,
+            connect_ms,
+            tls_ms,
+            ttfb_ms,
+            download_ms
         )
         .execute(pool)
         .await;
@@ -394,6 +1088,12 @@ impl DatabaseModel for MonitorPing {
                 monitor_id: self.monitor_id,
                 duration_ms: self.duration_ms,
                 bad: self.bad,
+                maintenance: self.maintenance,
+                unreachable: self.unreachable,
+                degraded: self.degraded,
+                round_trip_ms: self.round_trip_ms,
+                icmp: self.icmp,
+                timings: self.timings,
             }),
             Err(err) => Err(err),
         }
@@ -416,6 +1116,25 @@ impl DatabaseModel for MonitorPing {
             monitor_id: query_result.monitor_id,
             duration_ms: query_result.duration_ms,
             bad: query_result.bad.to_bool(),
+            maintenance: query_result.maintenance.to_bool(),
+            unreachable: query_result.unreachable.to_bool(),
+            degraded: query_result.degraded.to_bool(),
+            round_trip_ms: query_result.round_trip_ms,
+            icmp: EchoStats::from_columns(
+                query_result.icmp_sent,
+                query_result.icmp_received,
+                query_result.rtt_min_ms,
+                query_result.rtt_avg_ms,
+                query_result.rtt_max_ms,
+                query_result.jitter_ms,
+            ),
+            timings: Timings::from_columns(
+                query_result.dns_ms,
+                query_result.connect_ms,
+                query_result.tls_ms,
+                query_result.ttfb_ms,
+                query_result.download_ms,
+            ),
         })
     }
 
@@ -437,6 +1156,25 @@ impl DatabaseModel for MonitorPing {
                 monitor_id: monitor_ping.monitor_id,
                 duration_ms: monitor_ping.duration_ms,
                 bad: monitor_ping.bad.to_bool(),
+                maintenance: monitor_ping.maintenance.to_bool(),
+                unreachable: monitor_ping.unreachable.to_bool(),
+                degraded: monitor_ping.degraded.to_bool(),
+                round_trip_ms: monitor_ping.round_trip_ms,
+                icmp: EchoStats::from_columns(
+                    monitor_ping.icmp_sent,
+                    monitor_ping.icmp_received,
+                    monitor_ping.rtt_min_ms,
+                    monitor_ping.rtt_avg_ms,
+                    monitor_ping.rtt_max_ms,
+                    monitor_ping.jitter_ms,
+                ),
+                timings: Timings::from_columns(
+                    monitor_ping.dns_ms,
+                    monitor_ping.connect_ms,
+                    monitor_ping.tls_ms,
+                    monitor_ping.ttfb_ms,
+                    monitor_ping.download_ms,
+                ),
             })
             .collect())
     }
@@ -547,6 +1285,67 @@ impl DatabaseModel for MonitorStats {
     }
 }
 
+/// Instance wide key value settings, managed by admins
+pub struct Setting;
+
+impl Setting {
+    pub const REQUIRE_TOTP: &'static str = "require_totp";
+
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/setting.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn get(key: &str, pool: &Pool<Sqlite>) -> Option<String> {
+        sqlx::query!(
+            r#"
+            SELECT value FROM setting WHERE key = ?
+            "#,
+            key
+        )
+        .fetch_one(pool)
+        .await
+        .ok()
+        .map(|setting| setting.value)
+    }
+
+    pub async fn all(pool: &Pool<Sqlite>) -> Result<HashMap<String, String>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT key, value FROM setting
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|setting| (setting.key, setting.value))
+            .collect())
+    }
+
+    pub async fn get_bool(key: &str, pool: &Pool<Sqlite>) -> bool {
+        Setting::get(key, pool).await.as_deref() == Some("true")
+    }
+
+    pub async fn set(key: &str, value: &str, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO setting (key, value) VALUES (?, ?)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value
+            "#,
+            key,
+            value
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 pub trait DatabaseModel {
     async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error>;
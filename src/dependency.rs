@@ -0,0 +1,205 @@
+use crate::{
+    database::{Monitor, MonitorPing, MonitorState},
+    templates::DependencyNode,
+    utils,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashSet;
+
+/// `monitor_id` can only be reached through `parent_id`, failures of the
+/// monitor while the parent is down aren't its own
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Dependency {
+    pub monitor_id: i64,
+    pub parent_id: i64,
+}
+
+impl Dependency {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/monitor_dependency.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT monitor_id, parent_id FROM monitor_dependency ORDER BY monitor_id, parent_id
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|dependency| Dependency {
+                monitor_id: dependency.monitor_id,
+                parent_id: dependency.parent_id,
+            })
+            .collect())
+    }
+
+    pub async fn parents(monitor_id: i64, pool: &Pool<Sqlite>) -> Result<Vec<i64>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT parent_id FROM monitor_dependency WHERE monitor_id = ?
+            "#,
+            monitor_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result.into_iter().map(|row| row.parent_id).collect())
+    }
+
+    /// Every monitor `monitor_id` depends on, directly or through other parents
+    fn ancestors(monitor_id: i64, dependencies: &[Dependency]) -> HashSet<i64> {
+        let mut ancestors = HashSet::new();
+        let mut queue = vec![monitor_id];
+
+        while let Some(id) = queue.pop() {
+            for dependency in dependencies.iter().filter(|d| d.monitor_id == id) {
+                if ancestors.insert(dependency.parent_id) {
+                    queue.push(dependency.parent_id);
+                }
+            }
+        }
+
+        ancestors
+    }
+
+    /// Replaces the parents of a monitor, parents that would create a cycle
+    /// are rejected with their id
+    pub async fn set_parents(
+        monitor_id: i64,
+        parents: &[i64],
+        pool: &Pool<Sqlite>,
+    ) -> Result<Result<(), i64>, sqlx::Error> {
+        let dependencies: Vec<Dependency> = Dependency::all(pool)
+            .await?
+            .into_iter()
+            .filter(|dependency| dependency.monitor_id != monitor_id)
+            .collect();
+
+        for parent_id in parents.iter() {
+            if *parent_id == monitor_id
+                || Dependency::ancestors(*parent_id, &dependencies).contains(&monitor_id)
+            {
+                return Ok(Err(*parent_id));
+            }
+        }
+
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM monitor_dependency WHERE monitor_id = ?
+            "#,
+            monitor_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for parent_id in parents.iter() {
+            sqlx::query!(
+                r#"
+                INSERT INTO monitor_dependency (monitor_id, parent_id) VALUES (?, ?)
+                ON CONFLICT DO NOTHING
+                "#,
+                monitor_id,
+                parent_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(Ok(()))
+    }
+
+    /// Whether a parent's last ping failed, a failure of the monitor is then
+    /// recorded as unreachable
+    pub async fn is_parent_down(monitor_id: i64, pool: &Pool<Sqlite>) -> Result<bool, sqlx::Error> {
+        for parent_id in Dependency::parents(monitor_id, pool).await? {
+            let pings = MonitorPing::last_n(pool, parent_id, 1).await;
+            if pings.first().is_some_and(|ping| ping.bad) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Monitors `monitor_id` depends on and monitors depending on it, directly
+    /// or through others
+    pub fn related(monitor_id: i64, dependencies: &[Dependency]) -> HashSet<i64> {
+        let mut related = Dependency::ancestors(monitor_id, dependencies);
+        let mut queue = vec![monitor_id];
+        while let Some(id) = queue.pop() {
+            for child in Dependency::neighbours(id, false, dependencies) {
+                if related.insert(child) {
+                    queue.push(child);
+                }
+            }
+        }
+
+        related
+    }
+
+    /// The graph around a monitor, walking `upstream` to its parents or
+    /// downstream to the monitors depending on it
+    pub fn graph(
+        monitor_id: i64,
+        upstream: bool,
+        monitors: &[(Monitor, MonitorState)],
+        dependencies: &[Dependency],
+    ) -> Vec<DependencyNode> {
+        let mut visited = HashSet::from([monitor_id]);
+
+        Dependency::neighbours(monitor_id, upstream, dependencies)
+            .into_iter()
+            .filter_map(|id| Dependency::node(id, upstream, monitors, dependencies, &mut visited))
+            .collect()
+    }
+
+    fn neighbours(monitor_id: i64, upstream: bool, dependencies: &[Dependency]) -> Vec<i64> {
+        dependencies
+            .iter()
+            .filter_map(|dependency| {
+                if upstream && dependency.monitor_id == monitor_id {
+                    Some(dependency.parent_id)
+                } else if !upstream && dependency.parent_id == monitor_id {
+                    Some(dependency.monitor_id)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn node(
+        monitor_id: i64,
+        upstream: bool,
+        monitors: &[(Monitor, MonitorState)],
+        dependencies: &[Dependency],
+        visited: &mut HashSet<i64>,
+    ) -> Option<DependencyNode> {
+        // a monitor reachable on two paths is only shown on the first
+        if !visited.insert(monitor_id) {
+            return None;
+        }
+        let (monitor, state) = monitors
+            .iter()
+            .find(|(monitor, _)| monitor.id == monitor_id)?;
+
+        Some(DependencyNode {
+            monitor: monitor.clone(),
+            state: *state,
+            children: Dependency::neighbours(monitor_id, upstream, dependencies)
+                .into_iter()
+                .filter_map(|id| Dependency::node(id, upstream, monitors, dependencies, visited))
+                .collect(),
+        })
+    }
+}
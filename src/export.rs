@@ -0,0 +1,444 @@
+use crate::{
+    database::{DatabaseModel, Monitor, MonitorPing, Setting},
+    dependency::Dependency,
+    group::Group,
+    probe::{EchoStats, Timings},
+    tag::MonitorTag,
+    time::PrettyPrint,
+};
+use rocket::{fs::TempFile, http::Status, FromForm, FromFormField};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::{HashMap, HashSet};
+use uptime_rs::AppError;
+
+/// Bumped whenever the document layout changes, older documents keep importing
+pub const EXPORT_VERSION: i64 = 3;
+
+/// Everything needed to move an instance to another host, users and api
+/// tokens are deliberately left out
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Export {
+    pub version: i64,
+    pub exported_at: String,
+    pub monitors: Vec<Monitor>,
+    /// Added in version 2
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    /// Added in version 2, tags are matched by name on import
+    #[serde(default)]
+    pub tags: Vec<MonitorTag>,
+    /// Added in version 3
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    #[serde(default)]
+    pub settings: HashMap<String, String>,
+    /// Only present when the export was made with ping history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pings: Option<Vec<ExportedPing>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExportedPing {
+    pub monitor_id: i64,
+    pub timestamp: String,
+    pub status: u16,
+    pub duration_ms: i64,
+    pub bad: bool,
+    #[serde(default)]
+    pub maintenance: bool,
+    #[serde(default)]
+    pub unreachable: bool,
+    #[serde(default)]
+    pub degraded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub round_trip_ms: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icmp: Option<EchoStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+}
+
+/// What happens to a monitor or setting that already exists with the same id or key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, FromFormField)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Keep the existing one and skip the imported one
+    #[field(value = "merge")]
+    Merge,
+    /// Overwrite the existing one, including its ping history when the import has history
+    #[field(value = "replace")]
+    Replace,
+}
+
+#[derive(FromForm)]
+pub struct ImportForm<'r> {
+    pub file: TempFile<'r>,
+    pub mode: ImportMode,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub created: Vec<i64>,
+    pub replaced: Vec<i64>,
+    pub skipped: Vec<i64>,
+    pub settings: usize,
+    pub pings: usize,
+}
+
+pub async fn export(history: bool, pool: &Pool<Sqlite>) -> Result<Export, sqlx::Error> {
+    let pings = if history {
+        Some(
+            MonitorPing::all(pool)
+                .await?
+                .into_iter()
+                .map(|ping| ExportedPing {
+                    monitor_id: ping.monitor_id,
+                    timestamp: ping.timestamp,
+                    status: ping.status.code,
+                    duration_ms: ping.duration_ms,
+                    bad: ping.bad,
+                    maintenance: ping.maintenance,
+                    unreachable: ping.unreachable,
+                    degraded: ping.degraded,
+                    round_trip_ms: ping.round_trip_ms,
+                    icmp: ping.icmp,
+                    timings: ping.timings,
+                })
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    Ok(Export {
+        version: EXPORT_VERSION,
+        exported_at: chrono::Local::now().pretty_string(),
+        monitors: Monitor::all(pool).await?,
+        groups: Group::all(pool).await?,
+        tags: MonitorTag::all(pool).await?,
+        dependencies: Dependency::all(pool).await?,
+        settings: Setting::all(pool).await?,
+        pings,
+    })
+}
+
+/// Imports a document made by `export` in a single transaction, the caller
+/// is expected to reload the pingers afterwards
+pub async fn import(
+    export: Export,
+    mode: ImportMode,
+    user_id: i64,
+    pool: &Pool<Sqlite>,
+) -> Result<ImportSummary, AppError> {
+    if export.version > EXPORT_VERSION {
+        return Err(AppError {
+            status: Status::UnprocessableEntity,
+            message: format!(
+                "Export version {} is newer than the supported version {}",
+                export.version, EXPORT_VERSION
+            ),
+        });
+    }
+
+    let existing: HashMap<i64, Monitor> = Monitor::all(pool)
+        .await?
+        .into_iter()
+        .map(|monitor| (monitor.id, monitor))
+        .collect();
+    let managed_keys: HashMap<String, i64> = existing
+        .values()
+        .filter_map(|monitor| Some((monitor.managed_key.clone()?, monitor.id)))
+        .collect();
+
+    let mut summary = ImportSummary::default();
+    let mut imported: HashSet<i64> = HashSet::new();
+    let mut group_ids: HashSet<i64> = Group::all(pool)
+        .await?
+        .into_iter()
+        .map(|group| group.id)
+        .collect();
+    let mut tx = pool.begin().await?;
+
+    // parents are linked in a second pass, they may come later in the list
+    for group in export.groups.iter() {
+        let query_result = match mode {
+            ImportMode::Merge => {
+                sqlx::query!(
+                    r#"
+                INSERT INTO monitor_group (id, name) VALUES (?, ?) ON CONFLICT(id) DO NOTHING
+                "#,
+                    group.id,
+                    group.name
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+            ImportMode::Replace => {
+                sqlx::query!(
+                    r#"
+                INSERT INTO monitor_group (id, name) VALUES (?, ?)
+                ON CONFLICT(id) DO UPDATE SET name = excluded.name
+                "#,
+                    group.id,
+                    group.name
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+        };
+        if query_result.rows_affected() > 0 {
+            group_ids.insert(group.id);
+        }
+    }
+    for group in export.groups.iter() {
+        let parent_id = group.parent_id.filter(|id| group_ids.contains(id));
+        sqlx::query!(
+            r#"
+            UPDATE monitor_group SET parent_id = ? WHERE id = ?
+            "#,
+            parent_id,
+            group.id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for monitor in export.monitors {
+        // managed keys are unique, drop the key when another monitor already owns it
+        let managed_key = monitor
+            .managed_key
+            .filter(|key| match managed_keys.get(key) {
+                Some(id) => *id == monitor.id,
+                None => true,
+            });
+        let group_id = monitor.group_id.filter(|id| group_ids.contains(id));
+        let protocol = monitor.protocol.as_str();
+
+        match existing.get(&monitor.id) {
+            Some(_) if mode == ImportMode::Merge => {
+                summary.skipped.push(monitor.id);
+                continue;
+            }
+            Some(_) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE monitor SET name = ?, ip = ?, port = ?, protocol = ?, interval = ?, paused = ?, updated_by = ?, managed_key = ?, group_id = ?, degraded_threshold_ms = ?, ws_message = ?, ws_expect = ?, ws_timeout_ms = ?, icmp_count = ?, icmp_max_loss = ? WHERE id = ?
+                    "#,
+                    monitor.name,
+                    monitor.ip,
+                    monitor.port,
+                    protocol,
+                    monitor.interval,
+                    monitor.paused,
+                    user_id,
+                    managed_key,
+                    group_id,
+                    monitor.degraded_threshold_ms,
+                    monitor.ws_message,
+                    monitor.ws_expect,
+                    monitor.ws_timeout_ms,
+                    monitor.icmp_count,
+                    monitor.icmp_max_loss,
+                    monitor.id
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                sqlx::query!(
+                    r#"
+                    DELETE FROM monitor_tag WHERE monitor_id = ?
+                    "#,
+                    monitor.id
+                )
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query!(
+                    r#"
+                    DELETE FROM monitor_dependency WHERE monitor_id = ?
+                    "#,
+                    monitor.id
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                if export.pings.is_some() {
+                    sqlx::query!(
+                        r#"
+                        DELETE FROM monitor_ping WHERE monitor_id = ?
+                        "#,
+                        monitor.id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                    // rolled up again from the imported pings
+                    sqlx::query!(
+                        r#"
+                        DELETE FROM monitor_daily WHERE monitor_id = ?
+                        "#,
+                        monitor.id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                }
+
+                summary.replaced.push(monitor.id);
+            }
+            None => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO monitor (id, name, ip, port, protocol, interval, paused, created_by, managed_key, group_id, degraded_threshold_ms, ws_message, ws_expect, ws_timeout_ms, icmp_count, icmp_max_loss) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                    monitor.id,
+                    monitor.name,
+                    monitor.ip,
+                    monitor.port,
+                    protocol,
+                    monitor.interval,
+                    monitor.paused,
+                    user_id,
+                    managed_key,
+                    group_id,
+                    monitor.degraded_threshold_ms,
+                    monitor.ws_message,
+                    monitor.ws_expect,
+                    monitor.ws_timeout_ms,
+                    monitor.icmp_count,
+                    monitor.icmp_max_loss
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                summary.created.push(monitor.id);
+            }
+        }
+
+        imported.insert(monitor.id);
+    }
+
+    for tag in export.tags.iter() {
+        // tags of skipped monitors are left as they are
+        if !imported.contains(&tag.monitor_id) {
+            continue;
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO tag (name, color) VALUES (?, ?) ON CONFLICT(name) DO NOTHING
+            "#,
+            tag.name,
+            tag.color
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO monitor_tag (monitor_id, tag_id, value)
+            SELECT ?, id, ? FROM tag WHERE name = ?
+            ON CONFLICT DO NOTHING
+            "#,
+            tag.monitor_id,
+            tag.value,
+            tag.name
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for dependency in export.dependencies.iter() {
+        // parents are kept as long as they exist after the import
+        let exists = |id: i64| imported.contains(&id) || existing.contains_key(&id);
+        if !imported.contains(&dependency.monitor_id) || !exists(dependency.parent_id) {
+            continue;
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO monitor_dependency (monitor_id, parent_id) VALUES (?, ?)
+            ON CONFLICT DO NOTHING
+            "#,
+            dependency.monitor_id,
+            dependency.parent_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for (key, value) in export.settings.iter() {
+        let query_result = match mode {
+            ImportMode::Merge => {
+                sqlx::query!(
+                    r#"
+                INSERT INTO setting (key, value) VALUES (?, ?) ON CONFLICT(key) DO NOTHING
+                "#,
+                    key,
+                    value
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+            ImportMode::Replace => {
+                sqlx::query!(
+                    r#"
+                INSERT INTO setting (key, value) VALUES (?, ?)
+                ON CONFLICT(key) DO UPDATE SET value = excluded.value
+                "#,
+                    key,
+                    value
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+        };
+        summary.settings += query_result.rows_affected() as usize;
+    }
+
+    for ping in export.pings.unwrap_or_default() {
+        if !imported.contains(&ping.monitor_id) {
+            continue;
+        }
+
+        let dns_ms = ping.timings.map(|timings| timings.dns_ms);
+        let connect_ms = ping.timings.map(|timings| timings.connect_ms);
+        let tls_ms = ping.timings.map(|timings| timings.tls_ms);
+        let ttfb_ms = ping.timings.map(|timings| timings.ttfb_ms);
+        let download_ms = ping.timings.map(|timings| timings.download_ms);
+        let icmp_sent = ping.icmp.map(|icmp| icmp.sent);
+        let icmp_received = ping.icmp.map(|icmp| icmp.received);
+        let rtt_min_ms = ping.icmp.map(|icmp| icmp.min_ms);
+        let rtt_avg_ms = ping.icmp.map(|icmp| icmp.avg_ms);
+        let rtt_max_ms = ping.icmp.map(|icmp| icmp.max_ms);
+        let jitter_ms = ping.icmp.map(|icmp| icmp.jitter_ms);
+        sqlx::query!(
+            r#"
+            INSERT INTO monitor_ping (monitor_id, timestamp, status, duration_ms, bad, maintenance, unreachable, degraded, round_trip_ms, icmp_sent, icmp_received, rtt_min_ms, rtt_avg_ms, rtt_max_ms, jitter_ms, dns_ms, connect_ms, tls_ms, ttfb_ms, download_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            ping.monitor_id,
+            ping.timestamp,
+            ping.status,
+            ping.duration_ms,
+            ping.bad,
+            ping.maintenance,
+            ping.unreachable,
+            ping.degraded,
+            ping.round_trip_ms,
+            icmp_sent,
+            icmp_received,
+            rtt_min_ms,
+            rtt_avg_ms,
+            rtt_max_ms,
+            jitter_ms,
+            dns_ms,
+            connect_ms,
+            tls_ms,
+            ttfb_ms,
+            download_ms
+        )
+        .execute(&mut *tx)
+        .await?;
+        summary.pings += 1;
+    }
+
+    tx.commit().await?;
+    Ok(summary)
+}
@@ -0,0 +1,162 @@
+use crate::{
+    database::{Monitor, MonitorPing, MonitorState},
+    time::PrettyPrint,
+    utils,
+};
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use std::collections::HashSet;
+
+/// Checks the state change rate is measured over, like Nagios
+pub const WINDOW: usize = 21;
+
+/// A monitor starts flapping once its state change rate reaches this
+const HIGH_THRESHOLD: f64 = 0.5;
+
+/// and stops once the rate drops below this, the gap keeps a monitor close
+/// to the threshold from starting and stopping all the time
+const LOW_THRESHOLD: f64 = 0.25;
+
+/// A period a monitor spent flapping, `ended_at` is None while it still is
+#[derive(Debug, Clone, Serialize)]
+pub struct Flap {
+    pub id: i64,
+    pub monitor_id: i64,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+}
+
+/// What a new check changed about the flapping of a monitor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlapChange {
+    Started,
+    Stopped,
+    /// Still flapping, state changes are held back
+    Ongoing,
+    Stable,
+}
+
+/// Share of the possible state changes in `states`, newest first. Like in
+/// Nagios newer changes weigh more, from 0.8 for the oldest to 1.2 for the
+/// newest. 0 until there are `WINDOW` states
+pub fn state_change_rate(states: &[MonitorState]) -> f64 {
+    if states.len() < WINDOW {
+        return 0.0;
+    }
+
+    let changes = WINDOW - 1;
+    let mut changed = 0.0;
+    let mut total = 0.0;
+    for (i, pair) in states[..WINDOW].windows(2).enumerate() {
+        let weight = 1.2 - 0.4 * i as f64 / (changes - 1) as f64;
+        if pair[0] != pair[1] {
+            changed += weight;
+        }
+        total += weight;
+    }
+
+    changed / total
+}
+
+/// States of `pings` that take part in flap detection, newest first. Pings
+/// during maintenance or while a parent was down are left out
+pub fn states(monitor: &Monitor, pings: &[MonitorPing]) -> Vec<MonitorState> {
+    pings
+        .iter()
+        .map(|ping| MonitorState::of(monitor, Some(ping)))
+        .filter(|state| {
+            matches!(
+                state,
+                MonitorState::Up | MonitorState::Degraded | MonitorState::Down
+            )
+        })
+        .take(WINDOW)
+        .collect()
+}
+
+impl Flap {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/monitor_flap.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn current(
+        monitor_id: i64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT id, monitor_id, started_at, ended_at FROM monitor_flap WHERE monitor_id = ? AND ended_at IS NULL LIMIT 1
+            "#,
+            monitor_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(query_result.map(|flap| Flap {
+            id: flap.id,
+            monitor_id: flap.monitor_id,
+            started_at: flap.started_at,
+            ended_at: flap.ended_at,
+        }))
+    }
+
+    /// Monitors that are flapping right now
+    pub async fn flapping(pool: &Pool<Sqlite>) -> Result<HashSet<i64>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT monitor_id FROM monitor_flap WHERE ended_at IS NULL
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|flap| flap.monitor_id)
+            .collect())
+    }
+
+    /// Starts or ends the flap of a monitor depending on its state change
+    /// `rate`
+    pub async fn update(
+        monitor_id: i64,
+        rate: f64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<FlapChange, sqlx::Error> {
+        let now = chrono::Local::now().pretty_string();
+
+        match Flap::current(monitor_id, pool).await? {
+            Some(flap) if rate < LOW_THRESHOLD => {
+                sqlx::query!(
+                    r#"
+                    UPDATE monitor_flap SET ended_at = ? WHERE id = ?
+                    "#,
+                    now,
+                    flap.id
+                )
+                .execute(pool)
+                .await?;
+
+                Ok(FlapChange::Stopped)
+            }
+            Some(_) => Ok(FlapChange::Ongoing),
+            None if rate >= HIGH_THRESHOLD => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO monitor_flap (monitor_id, started_at) VALUES (?, ?)
+                    "#,
+                    monitor_id,
+                    now
+                )
+                .execute(pool)
+                .await?;
+
+                Ok(FlapChange::Started)
+            }
+            None => Ok(FlapChange::Stable),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::utils;
+use rocket::FromForm;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashSet;
+
+/// A folder of monitors, groups can be nested through `parent_id`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Group {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct CreateGroupForm {
+    pub name: String,
+    pub parent_id: Option<i64>,
+}
+
+impl Group {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/monitor_group.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM monitor_group ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|group| Group {
+                id: group.id,
+                name: group.name,
+                parent_id: group.parent_id,
+            })
+            .collect())
+    }
+
+    /// The parent has to exist already, which keeps groups free of cycles
+    pub async fn create(
+        name: &str,
+        parent_id: Option<i64>,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Self, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            INSERT INTO monitor_group (name, parent_id) VALUES (?, ?)
+            "#,
+            name,
+            parent_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(Group {
+            id: query_result.last_insert_rowid(),
+            name: name.to_string(),
+            parent_id,
+        })
+    }
+
+    /// Monitors and subgroups of a deleted group move up to the top level
+    pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM monitor_group WHERE id = ?
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The group and every group nested in it
+    pub fn subtree(id: i64, groups: &[Group]) -> HashSet<i64> {
+        let mut subtree = HashSet::from([id]);
+        let mut queue = vec![id];
+        while let Some(id) = queue.pop() {
+            for group in groups.iter().filter(|group| group.parent_id == Some(id)) {
+                if subtree.insert(group.id) {
+                    queue.push(group.id);
+                }
+            }
+        }
+
+        subtree
+    }
+
+    /// Names including their parents like `Production / Web`, used in selects
+    pub fn paths(groups: &[Group]) -> Vec<(i64, String)> {
+        let mut paths: Vec<(i64, String)> = groups
+            .iter()
+            .map(|group| {
+                let mut path = group.name.clone();
+                let mut parent_id = group.parent_id;
+                // bounded so a cycle from an import can't hang the page
+                for _ in 0..groups.len() {
+                    let Some(parent) = groups.iter().find(|g| Some(g.id) == parent_id) else {
+                        break;
+                    };
+                    path = format!("{} / {}", parent.name, path);
+                    parent_id = parent.parent_id;
+                }
+                (group.id, path)
+            })
+            .collect();
+
+        paths.sort_by(|a, b| a.1.cmp(&b.1));
+        paths
+    }
+}
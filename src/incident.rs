@@ -0,0 +1,68 @@
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+
+/// A run of consecutive bad pings of one monitor outside maintenance and
+/// not caused by a parent, incidents aren't stored and are derived from the
+/// ping history instead
+#[derive(Debug, Clone, Serialize)]
+pub struct Incident {
+    pub monitor_id: i64,
+    pub monitor_name: String,
+    pub started_at: String,
+    /// Timestamp of the first good ping after the outage, None while ongoing
+    pub resolved_at: Option<String>,
+    pub failed_pings: i64,
+}
+
+impl Incident {
+    /// Incidents that started at or after `since`, newest first
+    pub async fn since(since: Option<&str>, pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT monitor_ping.monitor_id, monitor.name, monitor_ping.timestamp, monitor_ping.bad,
+                monitor_ping.maintenance, monitor_ping.unreachable
+            FROM monitor_ping JOIN monitor ON monitor.id = monitor_ping.monitor_id
+            WHERE ?1 IS NULL OR monitor_ping.timestamp >= ?1
+            ORDER BY monitor_ping.monitor_id, monitor_ping.timestamp
+            "#,
+            since
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut incidents: Vec<Incident> = Vec::new();
+        let mut current: Option<Incident> = None;
+
+        for ping in query_result {
+            // failures during maintenance are expected and failures behind a
+            // down parent belong to the parent's incident
+            let bad = ping.bad != 0 && ping.maintenance == 0 && ping.unreachable == 0;
+            if let Some(ref mut incident) = current {
+                if incident.monitor_id != ping.monitor_id {
+                    incidents.extend(current.take());
+                } else if !bad {
+                    incident.resolved_at = Some(ping.timestamp.clone());
+                    incidents.extend(current.take());
+                    continue;
+                } else {
+                    incident.failed_pings += 1;
+                    continue;
+                }
+            }
+
+            if bad {
+                current = Some(Incident {
+                    monitor_id: ping.monitor_id,
+                    monitor_name: ping.name,
+                    started_at: ping.timestamp,
+                    resolved_at: None,
+                    failed_pings: 1,
+                });
+            }
+        }
+        incidents.extend(current);
+
+        incidents.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        Ok(incidents)
+    }
+}
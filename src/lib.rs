@@ -14,7 +14,49 @@ pub struct CreateMonitor {
     pub name: String,
     pub ip: String,
     pub port: Option<i64>,
+    /// `http`, `https`, `ws`, `wss` or `icmp`, defaults to http
+    pub protocol: Option<String>,
     pub interval: i64,
+    pub group_id: Option<i64>,
+    /// Left empty the threshold comes from the recent response times
+    pub degraded_threshold_ms: Option<i64>,
+    /// Sent after the handshake of a WebSocket monitor
+    pub ws_message: Option<String>,
+    /// Regex the reply of a WebSocket monitor has to match
+    pub ws_expect: Option<String>,
+    pub ws_timeout_ms: Option<i64>,
+    /// Echo requests per check of an ICMP monitor
+    pub icmp_count: Option<i64>,
+    /// Packet loss in percent above which an ICMP check is bad
+    pub icmp_max_loss: Option<i64>,
+    /// Comma separated, like `env:prod, db`
+    pub tags: Option<String>,
+    /// Ids of the monitors this one can only be reached through
+    #[serde(default)]
+    pub parents: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize, FromForm, Serialize)]
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, FromForm, Serialize)]
+pub struct CreateUserForm {
+    pub username: String,
+    pub password: String,
+    pub password_confirm: String,
+}
+
+#[derive(Debug, Deserialize, FromForm, Serialize)]
+pub struct TotpCodeForm {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, FromForm, Serialize)]
+pub struct ToggleSettingForm {
+    pub enabled: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -29,6 +71,13 @@ pub struct RedirectResponder {
     pub redirect_uri: Option<Origin<'static>>,
 }
 
+/// Served as a file download
+pub struct DownloadResponder {
+    pub filename: String,
+    pub content_type: ContentType,
+    pub content: String,
+}
+
 pub type RedirectResult = Result<RedirectResponder, AppError>;
 pub type TemplateResult<'a> = Result<utils::TemplateResponse<'a>, AppError>;
 pub type JsonResult<'a> = Result<utils::JsonResponse<'a>, AppError>;
@@ -100,3 +149,15 @@ impl<'r> rocket::response::Responder<'r, 'static> for RedirectResponder {
             .ok()
     }
 }
+
+impl<'r> rocket::response::Responder<'r, 'static> for DownloadResponder {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let disposition = format!("attachment; filename=\"{}\"", self.filename);
+
+        Response::build()
+            .header(self.content_type)
+            .header(Header::new("Content-Disposition", disposition))
+            .sized_body(self.content.len(), Cursor::new(self.content))
+            .ok()
+    }
+}
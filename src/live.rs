@@ -0,0 +1,77 @@
+use crate::database::MonitorState;
+use rocket::tokio::sync::broadcast;
+use serde::Serialize;
+
+/// Events a slow client may fall behind on before it misses some
+const CAPACITY: usize = 256;
+
+/// Sent after every ping, with everything the monitor list and status badge
+/// show
+#[derive(Debug, Clone, Serialize)]
+pub struct PingEvent {
+    pub monitor_id: i64,
+    pub timestamp: String,
+    pub duration_ms: i64,
+    pub bad: bool,
+    pub degraded: bool,
+    /// Like the status badge, a successful ping that wasn't bad
+    pub up: bool,
+    pub state: MonitorState,
+    pub uptime_percentage: i64,
+}
+
+/// Sent when a monitor changes state or starts or stops flapping
+#[derive(Debug, Clone, Serialize)]
+pub struct StateEvent {
+    pub monitor_id: i64,
+    pub previous: MonitorState,
+    pub current: MonitorState,
+    pub flapping: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum LiveEvent {
+    Ping(PingEvent),
+    State(StateEvent),
+}
+
+impl LiveEvent {
+    /// Name of the server-sent event
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::Ping(_) => "ping",
+            LiveEvent::State(_) => "state",
+        }
+    }
+
+    pub fn monitor_id(&self) -> i64 {
+        match self {
+            LiveEvent::Ping(event) => event.monitor_id,
+            LiveEvent::State(event) => event.monitor_id,
+        }
+    }
+}
+
+/// Fans the events of the pingers out to every open `/events` stream.
+/// Clones publish to the same subscribers
+#[derive(Debug, Clone)]
+pub struct LiveEvents {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl LiveEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        LiveEvents { sender }
+    }
+
+    /// Dropped when nobody is listening
+    pub fn publish(&self, event: LiveEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -1,36 +1,76 @@
+mod anomaly;
+mod api_token;
+mod audit;
+mod auth;
+mod backup;
 pub mod database;
+mod dependency;
+mod export;
+mod flapping;
+mod group;
+mod incident;
+mod live;
+mod maintenance;
+mod monitors_file;
+mod notification;
+mod oidc;
 mod ping;
+mod probe;
+mod report;
+mod rollup;
 mod routes;
+mod slo;
+mod tag;
 mod templates;
 mod time;
+mod totp;
+mod uptime_kuma;
 mod utils;
 
 use database::DatabaseModel;
+use rocket::figment::providers::Env;
 use rocket::fs::FileServer;
 use rocket_async_compression::CachedCompression;
 
 use std::process::exit;
-use time::DateOffset;
+use tokio::signal::unix::{signal, SignalKind};
 
 #[macro_use]
 extern crate rocket;
 
 #[launch]
 async fn rocket() -> _ {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(file) = args.iter().skip_while(|arg| *arg != "--restore").nth(1) {
+        match backup::restore(file).await {
+            Ok(previous) => {
+                println!(
+                    "Restored {}, the old database was moved to {}",
+                    file,
+                    previous.display()
+                );
+                exit(0);
+            }
+            Err(err) => {
+                eprintln!("Restore failed: {}", err);
+                exit(1);
+            }
+        }
+    }
+
     let db_pool = database::initialize().await;
     let mut monitor_pool = ping::PingerManager::new();
 
-    let offset = DateOffset::new(chrono::Duration::days(1));
-    dbg!(&offset);
-    let normalized = offset.normalize();
-    dbg!(&normalized);
+    if let Some(path) = monitors_file::path() {
+        let dry_run = std::env::args().any(|arg| arg == "--dry-run");
+        monitors_file::reconcile(&path, dry_run, &db_pool, &monitor_pool)
+            .await
+            .expect("Failed to reconcile monitors file");
 
-    // let result = database::MonitorPing::between(&db_pool, 10, offset, 1000)
-    //     .await
-    //     .unwrap();
-    //
-    // dbg!(&result, result.len());
-    // exit(0);
+        if dry_run {
+            exit(0);
+        }
+    }
 
     for monitor in database::Monitor::all(&db_pool).await.unwrap() {
         let pinger = ping::Pinger::new(monitor, 3, || {});
@@ -38,7 +78,45 @@ async fn rocket() -> _ {
     }
 
     monitor_pool.start().await;
-    rocket::build()
+
+    let backup_config = backup::BackupConfig::from_env();
+    backup::schedule(backup_config.clone(), db_pool.clone());
+    rollup::schedule(db_pool.clone());
+    let notify_config = notification::NotifyConfig::from_env();
+    slo::schedule(notify_config.clone(), db_pool.clone());
+    report::schedule(
+        report::ReportConfig::from_env(),
+        notify_config,
+        db_pool.clone(),
+    );
+
+    let reload_manager = monitor_pool.clone();
+    let reload_pool = db_pool.clone();
+    tokio::spawn(async move {
+        let mut hangup = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+        while hangup.recv().await.is_some() {
+            if let Err(err) = monitors_file::reload(&reload_pool, &reload_manager).await {
+                warn!("Failed to reload monitors: {}", err);
+            }
+        }
+    });
+
+    let figment = rocket::Config::figment()
+        .merge(Env::prefixed("OIDC_").map(|key| format!("oidc.{}", key).into()));
+    let oidc = match figment.extract_inner::<oidc::OidcConfig>("oidc") {
+        Ok(config) => Some(oidc::Oidc::new(config)),
+        Err(err) if err.missing() => None,
+        Err(err) => panic!("Invalid oidc configuration: {}", err),
+    };
+
+    let mut rocket = rocket::custom(figment);
+    if let Some(oidc) = oidc {
+        rocket = rocket
+            .mount("/auth", routes![routes::oidc_login, routes::oidc_callback])
+            .manage(oidc);
+    }
+
+    rocket
         .mount(
             "/", //
             routes![routes::index],
@@ -48,6 +126,7 @@ async fn rocket() -> _ {
             routes![
                 routes::monitor_view,
                 routes::uptime_graph,
+                routes::uptime_calendar,
                 routes::pause_monitor,
                 routes::create_monitor,
                 routes::edit_monitor_view,
@@ -58,25 +137,159 @@ async fn rocket() -> _ {
             ],
         )
         .mount(
-            "/monitors", //
-            routes![routes::monitor_list],
+            "/auth",
+            routes![
+                routes::login_view,
+                routes::login,
+                routes::totp_login_view,
+                routes::totp_login,
+                routes::setup,
+                routes::logout,
+                routes::accept_invite_view,
+                routes::accept_invite
+            ],
+        )
+        .mount(
+            "/users",
+            routes![
+                routes::users_view,
+                routes::create_invite,
+                routes::delete_invite,
+                routes::update_user_role,
+                routes::delete_user,
+                routes::update_require_totp,
+                routes::reset_user_totp
+            ],
+        )
+        .mount(
+            "/account",
+            routes![
+                routes::totp_view,
+                routes::enable_totp,
+                routes::regenerate_recovery_codes,
+                routes::disable_totp
+            ],
+        )
+        .mount(
+            "/monitors",
+            routes![routes::monitor_list, routes::monitor_list_items],
         )
         .mount(
             "/api/monitor",
             routes![
-                // routes::get_monitor, //
-                routes::last_pings
+                routes::get_monitor,
+                routes::last_pings,
+                routes::monitor_history,
+                routes::monitor_stats,
+                routes::monitor_daily,
+                routes::api_create_monitor,
+                routes::api_pause_monitor,
+                routes::api_resume_monitor,
+                routes::api_delete_monitor
+            ],
+        )
+        .mount(
+            "/api/monitors", //
+            routes![routes::all_monitors],
+        )
+        .mount(
+            "/tags",
+            routes![
+                routes::tags_view,
+                routes::update_tag_color,
+                routes::delete_tag,
+                routes::create_group,
+                routes::delete_group
+            ],
+        )
+        .mount(
+            "/maintenance",
+            routes![
+                routes::maintenance_view,
+                routes::create_maintenance,
+                routes::delete_maintenance
+            ],
+        )
+        .mount(
+            "/api/maintenance",
+            routes![
+                routes::api_maintenance,
+                routes::api_create_maintenance,
+                routes::api_delete_maintenance
+            ],
+        )
+        .mount(
+            "/slo",
+            routes![routes::slo_view, routes::create_slo, routes::delete_slo],
+        )
+        .mount(
+            "/api/slo",
+            routes![
+                routes::api_slos,
+                routes::api_create_slo,
+                routes::api_delete_slo
+            ],
+        )
+        .mount(
+            "/reports",
+            routes![routes::reports_view, routes::generate_report],
+        )
+        .mount("/api/reports", routes![routes::api_report])
+        .mount("/api/tags", routes![routes::api_tags])
+        .mount("/api/groups", routes![routes::api_groups])
+        .mount("/audit", routes![routes::audit_view])
+        .mount("/api/audit", routes![routes::api_audit_log])
+        .mount(
+            "/export",
+            routes![
+                routes::export_view,
+                routes::create_backup,
+                routes::import_file,
+                routes::import_uptime_kuma_file
+            ],
+        )
+        .mount("/api/export", routes![routes::api_export])
+        .mount(
+            "/api/import",
+            routes![routes::api_import, routes::api_import_uptime_kuma],
+        )
+        .mount(
+            "/api/backup",
+            routes![routes::api_backups, routes::api_create_backup],
+        )
+        .mount("/api/incidents", routes![routes::api_incidents])
+        .mount("/events", routes![routes::live_events])
+        .mount(
+            "/api/config",
+            routes![routes::reconcile_monitors_file, routes::reload_config],
+        )
+        .mount(
+            "/tokens",
+            routes![
+                routes::tokens_view,
+                routes::create_token,
+                routes::revoke_token
             ],
         )
-        // .mount(
-        //     "/api/monitors", //
-        //     routes![routes::all_monitors],
-        // )
         .mount("/public", FileServer::from("./static"))
+        .register(
+            "/",
+            catchers![
+                routes::unauthorized,
+                routes::forbidden,
+                routes::totp_required
+            ],
+        )
+        .register(
+            "/api",
+            catchers![routes::api_unauthorized, routes::api_forbidden],
+        )
         .attach(CachedCompression::path_suffix_fairing(vec![
             ".js".into(),
             ".css".into(),
         ]))
+        .manage(backup_config)
+        .manage(totp::PendingLogins::default())
         .manage(monitor_pool)
         .manage(db_pool)
 }
@@ -0,0 +1,506 @@
+use crate::{tag::MonitorTag, utils};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use rocket::FromForm;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How far ahead upcoming windows are looked up
+const HORIZON_DAYS: i64 = 366;
+
+/// A planned period in which failed pings don't count, for a single monitor
+/// or every monitor with a tag
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MaintenanceWindow {
+    pub id: i64,
+    pub name: String,
+    pub monitor_id: Option<i64>,
+    /// `name` or `name:value`, matched like the tag filter of the monitor list
+    pub tag: Option<String>,
+    /// First start, later occurrences keep its time of day
+    pub starts_at: String,
+    pub duration_minutes: i64,
+    /// A cron expression or an RRULE, `None` for a one-off window
+    pub recurrence: Option<String>,
+    pub created_by: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, FromForm)]
+pub struct MaintenanceForm {
+    pub name: String,
+    pub monitor_id: Option<i64>,
+    pub tag: Option<String>,
+    pub starts_at: String,
+    pub duration_minutes: i64,
+    pub recurrence: Option<String>,
+}
+
+/// A single occurrence of a window
+#[derive(Debug, Clone, Serialize)]
+pub struct Occurrence {
+    pub window_id: i64,
+    pub name: String,
+    pub starts_at: String,
+    pub ends_at: String,
+}
+
+/// A window with its running or next occurrence, as returned by the api
+#[derive(Debug, Serialize)]
+pub struct ScheduledWindow {
+    #[serde(flatten)]
+    pub window: MaintenanceWindow,
+    pub next: Option<Occurrence>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug)]
+enum Recurrence {
+    Once,
+    /// `minute hour day-of-month month day-of-week`
+    Cron {
+        minutes: Vec<u32>,
+        hours: Vec<u32>,
+        days: Vec<u32>,
+        months: Vec<u32>,
+        weekdays: Vec<u32>,
+        any_day: bool,
+        any_weekday: bool,
+    },
+    /// The `FREQ`, `INTERVAL` and `BYDAY` parts of an RRULE
+    Rule {
+        frequency: Frequency,
+        interval: i64,
+        weekdays: Vec<Weekday>,
+    },
+}
+
+/// Accepts what a `datetime-local` input sends as well as stored timestamps
+pub fn parse_timestamp(value: &str) -> Option<NaiveDateTime> {
+    [
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        TIMESTAMP_FORMAT,
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value.trim(), format).ok())
+}
+
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut values = Vec::new();
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or(format!("invalid step in `{}`", part))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (
+                start
+                    .parse()
+                    .map_err(|_| format!("invalid range `{}`", part))?,
+                end.parse()
+                    .map_err(|_| format!("invalid range `{}`", part))?,
+            ),
+            None => {
+                let value = range
+                    .parse()
+                    .map_err(|_| format!("invalid value `{}`", part))?;
+                // `5/15` means every 15 starting at 5
+                (value, if part.contains('/') { max } else { value })
+            }
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("`{}` is outside {}-{}", part, min, max));
+        }
+
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+fn parse_weekday(day: &str) -> Result<Weekday, String> {
+    match day {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        day => Err(format!("unknown day `{}`", day)),
+    }
+}
+
+fn months_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    (to.year() as i64 - from.year() as i64) * 12 + to.month() as i64 - from.month() as i64
+}
+
+impl Recurrence {
+    fn parse(value: Option<&str>) -> Result<Self, String> {
+        let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+            return Ok(Recurrence::Once);
+        };
+
+        let rule = value.strip_prefix("RRULE:").unwrap_or(value);
+        if rule.starts_with("FREQ=") {
+            let mut frequency = None;
+            let mut interval = 1;
+            let mut weekdays = Vec::new();
+
+            for part in rule.split(';').filter(|part| !part.is_empty()) {
+                match part.split_once('=') {
+                    Some(("FREQ", "DAILY")) => frequency = Some(Frequency::Daily),
+                    Some(("FREQ", "WEEKLY")) => frequency = Some(Frequency::Weekly),
+                    Some(("FREQ", "MONTHLY")) => frequency = Some(Frequency::Monthly),
+                    Some(("INTERVAL", value)) => {
+                        interval = value
+                            .parse()
+                            .ok()
+                            .filter(|interval| *interval > 0)
+                            .ok_or(format!("invalid interval `{}`", value))?;
+                    }
+                    Some(("BYDAY", days)) => {
+                        weekdays = days
+                            .split(',')
+                            .map(parse_weekday)
+                            .collect::<Result<_, _>>()?;
+                    }
+                    _ => return Err(format!("unsupported RRULE part `{}`", part)),
+                }
+            }
+
+            return Ok(Recurrence::Rule {
+                frequency: frequency.ok_or("the RRULE needs a DAILY, WEEKLY or MONTHLY FREQ")?,
+                interval,
+                weekdays,
+            });
+        }
+
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err("a cron expression has five fields".to_string());
+        };
+
+        Ok(Recurrence::Cron {
+            minutes: parse_cron_field(minute, 0, 59)?,
+            hours: parse_cron_field(hour, 0, 23)?,
+            days: parse_cron_field(day, 1, 31)?,
+            months: parse_cron_field(month, 1, 12)?,
+            // 0 and 7 are both sunday
+            weekdays: parse_cron_field(weekday, 0, 7)?
+                .into_iter()
+                .map(|day| day % 7)
+                .collect(),
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Times of day an occurrence starts on `date`
+    fn starts_on(&self, date: NaiveDate, first: NaiveDateTime) -> Vec<NaiveTime> {
+        if date < first.date() {
+            return Vec::new();
+        }
+
+        match self {
+            Recurrence::Once if date == first.date() => vec![first.time()],
+            Recurrence::Once => Vec::new(),
+            Recurrence::Cron {
+                minutes,
+                hours,
+                days,
+                months,
+                weekdays,
+                any_day,
+                any_weekday,
+            } => {
+                let day_matches = days.contains(&date.day());
+                let weekday_matches = weekdays.contains(&date.weekday().num_days_from_sunday());
+                // like cron, a day matches on either field when both are restricted
+                let matches = match (any_day, any_weekday) {
+                    (true, true) => true,
+                    (false, true) => day_matches,
+                    (true, false) => weekday_matches,
+                    (false, false) => day_matches || weekday_matches,
+                };
+                if !matches || !months.contains(&date.month()) {
+                    return Vec::new();
+                }
+
+                hours
+                    .iter()
+                    .flat_map(|hour| {
+                        minutes
+                            .iter()
+                            .filter_map(move |minute| NaiveTime::from_hms_opt(*hour, *minute, 0))
+                    })
+                    .collect()
+            }
+            Recurrence::Rule {
+                frequency,
+                interval,
+                weekdays,
+            } => {
+                let start = first.date();
+                let matches = match frequency {
+                    Frequency::Daily => (date - start).num_days() % interval == 0,
+                    Frequency::Weekly => {
+                        let week = |date: NaiveDate| {
+                            date - Duration::days(date.weekday().num_days_from_monday() as i64)
+                        };
+                        let weeks = (week(date) - week(start)).num_days() / 7;
+                        let day_matches = if weekdays.is_empty() {
+                            date.weekday() == start.weekday()
+                        } else {
+                            weekdays.contains(&date.weekday())
+                        };
+                        weeks % interval == 0 && day_matches
+                    }
+                    Frequency::Monthly => {
+                        months_between(start, date) % interval == 0 && date.day() == start.day()
+                    }
+                };
+
+                if matches {
+                    vec![first.time()]
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
+}
+
+impl MaintenanceWindow {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/maintenance_window.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    /// Checks the form and turns it into a window, the error is shown to the user
+    pub fn from_form(form: &MaintenanceForm, created_by: Option<i64>) -> Result<Self, String> {
+        let name = form.name.trim();
+        if name.is_empty() {
+            return Err("The window needs a name".to_string());
+        }
+        let tag = form
+            .tag
+            .as_deref()
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string);
+        if form.monitor_id.is_none() && tag.is_none() {
+            return Err("Pick a monitor or a tag".to_string());
+        }
+        let starts_at = parse_timestamp(&form.starts_at).ok_or("Invalid start time")?;
+        if form.duration_minutes <= 0 {
+            return Err("The duration has to be at least a minute".to_string());
+        }
+        let recurrence = form
+            .recurrence
+            .as_deref()
+            .map(str::trim)
+            .filter(|recurrence| !recurrence.is_empty())
+            .map(str::to_string);
+        Recurrence::parse(recurrence.as_deref())
+            .map_err(|err| format!("Invalid recurrence: {}", err))?;
+
+        Ok(MaintenanceWindow {
+            id: 0, // field ignored, this is an autoincrement field
+            name: name.to_string(),
+            monitor_id: form.monitor_id,
+            tag,
+            starts_at: starts_at.format(TIMESTAMP_FORMAT).to_string(),
+            duration_minutes: form.duration_minutes,
+            recurrence,
+            created_by,
+        })
+    }
+
+    pub fn applies_to(&self, monitor_id: i64, tags: &[MonitorTag]) -> bool {
+        self.monitor_id == Some(monitor_id)
+            || match self.tag.as_deref() {
+                Some(filter) => tags.iter().any(|tag| tag.matches(filter)),
+                None => false,
+            }
+    }
+
+    fn occurrence(&self, start: NaiveDateTime) -> Occurrence {
+        Occurrence {
+            window_id: self.id,
+            name: self.name.clone(),
+            starts_at: start.format(TIMESTAMP_FORMAT).to_string(),
+            ends_at: (start + Duration::minutes(self.duration_minutes))
+                .format(TIMESTAMP_FORMAT)
+                .to_string(),
+        }
+    }
+
+    /// The occurrence that hasn't ended yet at `now` and starts first, which
+    /// is the running one during maintenance
+    pub fn next_occurrence(&self, now: NaiveDateTime) -> Option<Occurrence> {
+        let first = parse_timestamp(&self.starts_at)?;
+        let recurrence = Recurrence::parse(self.recurrence.as_deref()).ok()?;
+        let duration = Duration::minutes(self.duration_minutes);
+        // an occurrence from a few days ago can still be running
+        let from = now.date() - Duration::days(duration.num_days() + 1);
+
+        (0..HORIZON_DAYS + duration.num_days() + 1)
+            .map(|day| from + Duration::days(day))
+            .flat_map(|date| {
+                recurrence
+                    .starts_on(date, first)
+                    .into_iter()
+                    .map(move |time| date.and_time(time))
+            })
+            .find(|start| *start >= first && *start + duration > now)
+            .map(|start| self.occurrence(start))
+    }
+
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        match self.next_occurrence(now) {
+            Some(occurrence) => parse_timestamp(&occurrence.starts_at) <= Some(now),
+            None => false,
+        }
+    }
+
+    pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM maintenance_window ORDER BY starts_at
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|window| MaintenanceWindow {
+                id: window.id,
+                name: window.name,
+                monitor_id: window.monitor_id,
+                tag: window.tag,
+                starts_at: window.starts_at,
+                duration_minutes: window.duration_minutes,
+                recurrence: window.recurrence,
+                created_by: window.created_by,
+            })
+            .collect())
+    }
+
+    /// Windows that apply to a monitor, directly or through one of its tags
+    pub async fn for_monitor(
+        monitor_id: i64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let tags = MonitorTag::by_monitor(monitor_id, pool).await?;
+
+        Ok(MaintenanceWindow::all(pool)
+            .await?
+            .into_iter()
+            .filter(|window| window.applies_to(monitor_id, &tags))
+            .collect())
+    }
+
+    /// Whether a monitor is in maintenance right now
+    pub async fn is_monitor_in_maintenance(
+        monitor_id: i64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<bool, sqlx::Error> {
+        let now = Local::now().naive_local();
+
+        Ok(MaintenanceWindow::for_monitor(monitor_id, pool)
+            .await?
+            .iter()
+            .any(|window| window.is_active(now)))
+    }
+
+    /// Running and upcoming occurrences of the windows, soonest first
+    pub fn upcoming(windows: &[MaintenanceWindow]) -> Vec<Occurrence> {
+        let now = Local::now().naive_local();
+        let mut occurrences: Vec<Occurrence> = windows
+            .iter()
+            .filter_map(|window| window.next_occurrence(now))
+            .collect();
+
+        occurrences.sort_by(|a, b| a.starts_at.cmp(&b.starts_at));
+        occurrences
+    }
+
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            INSERT INTO maintenance_window (name, monitor_id, tag, starts_at, duration_minutes, recurrence, created_by) VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            self.name,
+            self.monitor_id,
+            self.tag,
+            self.starts_at,
+            self.duration_minutes,
+            self.recurrence,
+            self.created_by
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(MaintenanceWindow {
+            id: query_result.last_insert_rowid(),
+            ..self.clone()
+        })
+    }
+
+    pub async fn by_id(id: i64, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let window = sqlx::query!(
+            r#"
+            SELECT * FROM maintenance_window WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(MaintenanceWindow {
+            id: window.id,
+            name: window.name,
+            monitor_id: window.monitor_id,
+            tag: window.tag,
+            starts_at: window.starts_at,
+            duration_minutes: window.duration_minutes,
+            recurrence: window.recurrence,
+            created_by: window.created_by,
+        })
+    }
+
+    pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM maintenance_window WHERE id = ?
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,511 @@
+use crate::{
+    audit::{Actor, AuditEntry},
+    database::{DatabaseModel, Monitor},
+    ping::{self, PingerManager, Protocol, ReloadSummary},
+    tag::{self, MonitorTag},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+/// Path of the monitors file, reconciling is disabled when unset
+pub fn path() -> Option<String> {
+    std::env::var("MONITORS_FILE").ok()
+}
+
+fn default_protocol() -> Protocol {
+    Protocol::HTTP
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MonitorsFile {
+    #[serde(default)]
+    pub monitors: Vec<MonitorDefinition>,
+    /// Sections this version doesn't know how to reconcile yet
+    #[serde(flatten)]
+    pub unsupported: HashMap<String, toml::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MonitorDefinition {
+    /// Stable identifier, renaming a monitor keeps its history as long as the key stays
+    pub key: String,
+    pub name: String,
+    pub ip: String,
+    pub port: Option<i64>,
+    #[serde(default = "default_protocol")]
+    pub protocol: Protocol,
+    pub interval: i64,
+    #[serde(default)]
+    pub paused: bool,
+    pub degraded_threshold_ms: Option<i64>,
+    pub ws_message: Option<String>,
+    pub ws_expect: Option<String>,
+    pub ws_timeout_ms: Option<i64>,
+    pub icmp_count: Option<i64>,
+    pub icmp_max_loss: Option<i64>,
+    /// `name` or `name:value`, the tags of the monitor are left alone without it
+    pub tags: Option<Vec<String>>,
+}
+
+impl MonitorDefinition {
+    /// Parsed and sorted to compare them with the tags in the database
+    fn tags(&self) -> Option<Vec<(String, String)>> {
+        let mut tags: Vec<(String, String)> = self
+            .tags
+            .as_ref()?
+            .iter()
+            .flat_map(|tag| tag::parse_tags(tag))
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        Some(tags)
+    }
+
+    /// `paused` only applies to new monitors, managed monitors can still be
+    /// paused in the UI
+    fn matches(&self, monitor: &Monitor, tags: &[(String, String)]) -> bool {
+        self.name == monitor.name
+            && self.ip == monitor.ip
+            && self.port == monitor.port
+            && self.interval == monitor.interval
+            && self.protocol == monitor.protocol
+            && self.degraded_threshold_ms == monitor.degraded_threshold_ms
+            && self.ws_message == monitor.ws_message
+            && self.ws_expect == monitor.ws_expect
+            && self.ws_timeout_ms == monitor.ws_timeout_ms
+            && self.icmp_count == monitor.icmp_count
+            && self.icmp_max_loss == monitor.icmp_max_loss
+            && self.tags().is_none_or(|defined| defined == tags)
+    }
+
+    fn to_monitor(&self, id: i64) -> Monitor {
+        Monitor {
+            id,
+            name: self.name.clone(),
+            ip: self.ip.clone(),
+            port: self.port,
+            protocol: self.protocol.clone(),
+            interval: self.interval,
+            paused: self.paused,
+            created_by: None,
+            updated_by: None,
+            managed_key: Some(self.key.clone()),
+            group_id: None,
+            degraded_threshold_ms: self.degraded_threshold_ms,
+            ws_message: self.ws_message.clone(),
+            ws_expect: self.ws_expect.clone(),
+            ws_timeout_ms: self.ws_timeout_ms,
+            icmp_count: self.icmp_count,
+            icmp_max_loss: self.icmp_max_loss,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum PlannedChange {
+    Create {
+        definition: MonitorDefinition,
+    },
+    Update {
+        before: Box<Monitor>,
+        definition: MonitorDefinition,
+    },
+    Delete {
+        before: Box<Monitor>,
+    },
+}
+
+impl Display for PlannedChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlannedChange::Create { definition } => {
+                write!(f, "+ create {} ({})", definition.key, definition.name)
+            }
+            PlannedChange::Update { before, definition } => {
+                write!(
+                    f,
+                    "~ update {} (#{} {})",
+                    definition.key, before.id, before.name
+                )
+            }
+            PlannedChange::Delete { before } => write!(
+                f,
+                "- delete {} (#{} {})",
+                before.managed_key.as_deref().unwrap_or_default(),
+                before.id,
+                before.name
+            ),
+        }
+    }
+}
+
+pub fn load(path: &str) -> Result<MonitorsFile, std::io::Error> {
+    let content = std::fs::read_to_string(path)?;
+    let file: MonitorsFile = toml::from_str(&content)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+    for section in file.unsupported.keys() {
+        warn!(
+            "Section `{}` in {} is not supported yet, skipping",
+            section, path
+        );
+    }
+
+    Ok(file)
+}
+
+/// Rejects files that would fail halfway through `apply`
+fn check(file: &MonitorsFile) -> Result<(), std::io::Error> {
+    let mut keys = HashSet::new();
+    for definition in file.monitors.iter() {
+        if !keys.insert(definition.key.as_str()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Monitor key `{}` is used more than once", definition.key),
+            ));
+        }
+
+        ping::check_settings(
+            definition.ws_expect.as_deref(),
+            definition.icmp_count,
+            definition.icmp_max_loss,
+        )
+        .map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Monitor `{}`: {}", definition.key, err),
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Compares the file against the database, only monitors with a `managed_key`
+/// are ever touched so monitors created in the UI are left alone
+pub async fn plan(
+    file: &MonitorsFile,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<PlannedChange>, std::io::Error> {
+    check(file)?;
+
+    let mut managed: HashMap<String, Monitor> = Monitor::all(pool)
+        .await
+        .map_err(std::io::Error::other)?
+        .into_iter()
+        .filter_map(|monitor| Some((monitor.managed_key.clone()?, monitor)))
+        .collect();
+    let mut tags: HashMap<i64, Vec<(String, String)>> = HashMap::new();
+    for tag in MonitorTag::all(pool).await.map_err(std::io::Error::other)? {
+        tags.entry(tag.monitor_id)
+            .or_default()
+            .push((tag.name, tag.value));
+    }
+    for monitor_tags in tags.values_mut() {
+        monitor_tags.sort();
+    }
+
+    let mut changes = Vec::new();
+    for definition in file.monitors.iter() {
+        match managed.remove(&definition.key) {
+            Some(before)
+                if definition.matches(
+                    &before,
+                    tags.get(&before.id).map(Vec::as_slice).unwrap_or_default(),
+                ) => {}
+            Some(before) => changes.push(PlannedChange::Update {
+                before: Box::new(before),
+                definition: definition.clone(),
+            }),
+            None => changes.push(PlannedChange::Create {
+                definition: definition.clone(),
+            }),
+        }
+    }
+
+    let mut removed: Vec<Monitor> = managed.into_values().collect();
+    removed.sort_by_key(|monitor| monitor.id);
+    changes.extend(removed.into_iter().map(|before| PlannedChange::Delete {
+        before: Box::new(before),
+    }));
+
+    Ok(changes)
+}
+
+async fn insert_monitor(
+    monitor: &Monitor,
+    conn: &mut SqliteConnection,
+) -> Result<i64, sqlx::Error> {
+    let protocol = monitor.protocol.as_str();
+    let query_result = sqlx::query!(
+        r#"
+        INSERT INTO monitor (name, ip, port, protocol, interval, paused, managed_key, degraded_threshold_ms, ws_message, ws_expect, ws_timeout_ms, icmp_count, icmp_max_loss) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        monitor.name,
+        monitor.ip,
+        monitor.port,
+        protocol,
+        monitor.interval,
+        monitor.paused,
+        monitor.managed_key,
+        monitor.degraded_threshold_ms,
+        monitor.ws_message,
+        monitor.ws_expect,
+        monitor.ws_timeout_ms,
+        monitor.icmp_count,
+        monitor.icmp_max_loss
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(query_result.last_insert_rowid())
+}
+
+async fn update_monitor(monitor: &Monitor, conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    let protocol = monitor.protocol.as_str();
+    sqlx::query!(
+        r#"
+        UPDATE monitor SET name = ?, ip = ?, port = ?, protocol = ?, interval = ?, updated_by = ?, degraded_threshold_ms = ?, ws_message = ?, ws_expect = ?, ws_timeout_ms = ?, icmp_count = ?, icmp_max_loss = ? WHERE id = ?
+        "#,
+        monitor.name,
+        monitor.ip,
+        monitor.port,
+        protocol,
+        monitor.interval,
+        monitor.updated_by,
+        monitor.degraded_threshold_ms,
+        monitor.ws_message,
+        monitor.ws_expect,
+        monitor.ws_timeout_ms,
+        monitor.icmp_count,
+        monitor.icmp_max_loss,
+        monitor.id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Like `MonitorTag::set_for_monitor`, on the connection of the transaction
+async fn set_tags(
+    monitor_id: i64,
+    tags: &[(String, String)],
+    conn: &mut SqliteConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM monitor_tag WHERE monitor_id = ?
+        "#,
+        monitor_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    for (name, value) in tags.iter() {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS count FROM tag
+            "#
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        let color = tag::COLORS[query_result.count as usize % tag::COLORS.len()];
+
+        sqlx::query!(
+            r#"
+            INSERT INTO tag (name, color) VALUES (?, ?) ON CONFLICT(name) DO NOTHING
+            "#,
+            name,
+            color
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO monitor_tag (monitor_id, tag_id, value)
+            SELECT ?, id, ? FROM tag WHERE name = ?
+            ON CONFLICT DO NOTHING
+            "#,
+            monitor_id,
+            value,
+            name
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Writes every change in one transaction, the pingers are only touched once
+/// it is committed
+pub async fn apply(
+    changes: &[PlannedChange],
+    pool: &Pool<Sqlite>,
+    manager: &PingerManager,
+) -> Result<(), sqlx::Error> {
+    let actor = Actor::system("monitors-file");
+
+    let mut tx = pool.begin().await?;
+    let mut written = Vec::new();
+    for change in changes {
+        match change {
+            PlannedChange::Create { definition } => {
+                let mut monitor = definition.to_monitor(0);
+                monitor.id = insert_monitor(&monitor, &mut tx).await?;
+                if let Some(tags) = definition.tags() {
+                    set_tags(monitor.id, &tags, &mut tx).await?;
+                }
+                written.push(monitor);
+            }
+            PlannedChange::Update { before, definition } => {
+                let mut monitor = definition.to_monitor(before.id);
+                monitor.paused = before.paused;
+                monitor.created_by = before.created_by;
+                // groups are assigned in the UI, the file doesn't know about them
+                monitor.group_id = before.group_id;
+                update_monitor(&monitor, &mut tx).await?;
+                if let Some(tags) = definition.tags() {
+                    set_tags(monitor.id, &tags, &mut tx).await?;
+                }
+                written.push(monitor);
+            }
+            PlannedChange::Delete { before } => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM monitor_ping WHERE monitor_id = ?
+                    "#,
+                    before.id
+                )
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query!(
+                    r#"
+                    DELETE FROM monitor WHERE id = ?
+                    "#,
+                    before.id
+                )
+                .execute(&mut *tx)
+                .await?;
+                written.push(before.as_ref().clone());
+            }
+        }
+    }
+    tx.commit().await?;
+
+    for (change, monitor) in changes.iter().zip(written) {
+        match change {
+            PlannedChange::Create { .. } => {
+                let interval = monitor.interval;
+                manager
+                    .add_pinger(ping::Pinger::new(monitor.clone(), interval, || {}))
+                    .await;
+
+                AuditEntry::record(
+                    pool,
+                    &actor,
+                    "monitor.create",
+                    "monitor",
+                    Some(monitor.id),
+                    None,
+                    Some(&monitor),
+                )
+                .await;
+            }
+            PlannedChange::Update { before, .. } => {
+                if manager.update_pinger(monitor.clone()).await.is_err() {
+                    let interval = monitor.interval;
+                    manager
+                        .add_pinger(ping::Pinger::new(monitor.clone(), interval, || {}))
+                        .await;
+                }
+
+                AuditEntry::record(
+                    pool,
+                    &actor,
+                    "monitor.update",
+                    "monitor",
+                    Some(monitor.id),
+                    Some(before.as_ref()),
+                    Some(&monitor),
+                )
+                .await;
+            }
+            PlannedChange::Delete { before } => {
+                manager.remove_pinger(before.id).await;
+
+                AuditEntry::record(
+                    pool,
+                    &actor,
+                    "monitor.delete",
+                    "monitor",
+                    Some(before.id),
+                    Some(before.as_ref()),
+                    None,
+                )
+                .await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads the file at `path` and either prints or applies the resulting changes
+pub async fn reconcile(
+    path: &str,
+    dry_run: bool,
+    pool: &Pool<Sqlite>,
+    manager: &PingerManager,
+) -> Result<Vec<PlannedChange>, std::io::Error> {
+    let file = load(path)?;
+    let changes = plan(&file, pool).await?;
+
+    for change in changes.iter() {
+        println!("{}{}", if dry_run { "[dry-run] " } else { "" }, change);
+    }
+
+    if !dry_run {
+        apply(&changes, pool, manager)
+            .await
+            .map_err(std::io::Error::other)?;
+    }
+
+    Ok(changes)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigReload {
+    /// Changes applied from the monitors file, `None` when no file is configured
+    pub monitors_file: Option<Vec<PlannedChange>>,
+    pub pingers: ReloadSummary,
+}
+
+/// Picks up changes made outside the UI, triggered by SIGHUP or the reload endpoint
+pub async fn reload(
+    pool: &Pool<Sqlite>,
+    manager: &PingerManager,
+) -> Result<ConfigReload, std::io::Error> {
+    let monitors_file = match path() {
+        Some(path) => Some(reconcile(&path, false, pool, manager).await?),
+        None => None,
+    };
+    let pingers = manager.reload(pool).await.map_err(std::io::Error::other)?;
+
+    info!(
+        "Reloaded monitors: {} added, {} updated, {} removed",
+        pingers.added.len(),
+        pingers.updated.len(),
+        pingers.removed.len()
+    );
+
+    Ok(ConfigReload {
+        monitors_file,
+        pingers,
+    })
+}
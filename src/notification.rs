@@ -0,0 +1,170 @@
+use crate::time::PrettyPrint;
+use chrono::Local;
+use lettre::{
+    message::{header::ContentType, Attachment, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Serialize;
+
+/// Read from `NOTIFY_WEBHOOK_URL` and the `SMTP_*` variables, without a
+/// webhook notifications are only logged
+#[derive(Debug, Clone)]
+pub struct NotifyConfig {
+    pub webhook_url: Option<String>,
+    pub smtp: Option<SmtpConfig>,
+}
+
+/// Read from `SMTP_HOST`, `SMTP_PORT`, `SMTP_USERNAME`, `SMTP_PASSWORD` and
+/// `SMTP_FROM`. Port 465 uses implicit TLS, any other port STARTTLS
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+}
+
+impl NotifyConfig {
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+
+        NotifyConfig {
+            webhook_url: var("NOTIFY_WEBHOOK_URL"),
+            smtp: var("SMTP_HOST").map(|host| SmtpConfig {
+                port: var("SMTP_PORT")
+                    .and_then(|port| port.parse().ok())
+                    .unwrap_or(587),
+                username: var("SMTP_USERNAME"),
+                password: var("SMTP_PASSWORD"),
+                from: var("SMTP_FROM").unwrap_or_else(|| format!("uptime-rs@{}", host)),
+                host,
+            }),
+        }
+    }
+}
+
+/// A file attached to an email
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: Vec<String>,
+    pub subject: String,
+    pub text: String,
+    /// Sent as an alternative to `text` for clients that show html
+    pub html: Option<String>,
+    pub attachment: Option<EmailAttachment>,
+}
+
+/// Posted as json to the webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    /// Like `slo.burning` or `slo.recovered`
+    pub event: String,
+    pub title: String,
+    pub message: String,
+    pub timestamp: String,
+}
+
+impl Notification {
+    pub fn new(event: &str, title: String, message: String) -> Self {
+        Notification {
+            event: event.to_string(),
+            title,
+            message,
+            timestamp: Local::now().pretty_string(),
+        }
+    }
+
+    /// Failures are logged, a broken webhook shouldn't stop the caller
+    pub async fn send(&self, config: &NotifyConfig) {
+        info!("{}: {}", self.title, self.message);
+
+        let Some(url) = config.webhook_url.as_deref() else {
+            return;
+        };
+        let result = reqwest::Client::new()
+            .post(url)
+            .json(self)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(err) = result {
+            warn!("Failed to send {} notification: {}", self.event, err);
+        }
+    }
+
+    /// Sends without waiting, so a slow webhook doesn't hold up the caller
+    pub fn send_in_background(self, config: NotifyConfig) {
+        tokio::spawn(async move { self.send(&config).await });
+    }
+}
+
+impl Email {
+    fn message(&self, from: &str) -> Result<Message, String> {
+        let mut builder = Message::builder()
+            .from(
+                from.parse()
+                    .map_err(|err| format!("Invalid sender {}: {}", from, err))?,
+            )
+            .subject(self.subject.clone());
+        for to in self.to.iter() {
+            let mailbox = to
+                .parse()
+                .map_err(|err| format!("Invalid recipient {}: {}", to, err))?;
+            builder = builder.to(mailbox);
+        }
+
+        let body = match self.html.clone() {
+            Some(html) => MultiPart::alternative_plain_html(self.text.clone(), html),
+            None => MultiPart::mixed().singlepart(SinglePart::plain(self.text.clone())),
+        };
+        let body = match self.attachment.as_ref() {
+            Some(attachment) => {
+                let content_type = ContentType::parse(&attachment.content_type)
+                    .map_err(|err| format!("Invalid attachment type: {}", err))?;
+                MultiPart::mixed().multipart(body).singlepart(
+                    Attachment::new(attachment.filename.clone())
+                        .body(attachment.content.clone(), content_type),
+                )
+            }
+            None => body,
+        };
+
+        builder.multipart(body).map_err(|err| err.to_string())
+    }
+
+    pub async fn send(&self, config: &NotifyConfig) -> Result<(), String> {
+        let smtp = config.smtp.as_ref().ok_or("SMTP_HOST isn't set")?;
+        let message = self.message(&smtp.from)?;
+
+        let transport = match smtp.port {
+            465 => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host),
+            _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host),
+        }
+        .map_err(|err| err.to_string())?
+        .port(smtp.port);
+        let transport = match (smtp.username.clone(), smtp.password.clone()) {
+            (Some(username), Some(password)) => {
+                transport.credentials(Credentials::new(username, password))
+            }
+            _ => transport,
+        };
+
+        transport
+            .build()
+            .send(message)
+            .await
+            .map_err(|err| err.to_string())?;
+        info!("Sent {} to {}", self.subject, self.to.join(", "));
+
+        Ok(())
+    }
+}
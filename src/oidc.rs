@@ -0,0 +1,543 @@
+use crate::{
+    auth::{self, Role, User},
+    database::DatabaseModel,
+    time::PrettyPrint,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use rocket::{
+    http::{Cookie, CookieJar, SameSite, Status},
+    request::{FromRequest, Outcome, Request},
+    tokio::sync::RwLock,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use uptime_rs::AppError;
+
+const FLOW_COOKIE: &str = "oidc_flow";
+
+fn default_scopes() -> String {
+    "openid profile email".to_string()
+}
+
+fn default_groups_claim() -> String {
+    "groups".to_string()
+}
+
+/// Read from the `oidc` table in `Rocket.toml` or from `OIDC_*` environment variables
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: String,
+    #[serde(default = "default_groups_claim")]
+    pub groups_claim: String,
+    /// Maps identity provider groups to roles, the highest matching role wins
+    #[serde(default)]
+    pub role_mapping: HashMap<String, Role>,
+    /// Role for users without any mapped group, these users are rejected when unset
+    pub default_role: Option<Role>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// State for a pending login, kept in a private cookie between the redirect
+/// to the identity provider and the callback
+#[derive(Debug, Deserialize, Serialize)]
+struct AuthFlow {
+    state: String,
+    nonce: String,
+    verifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    preferred_username: Option<String>,
+    email: Option<String>,
+    #[serde(flatten)]
+    extra: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub struct Identity {
+    pub external_id: String,
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+fn oidc_error(message: impl std::fmt::Display) -> AppError {
+    AppError {
+        status: Status::BadGateway,
+        message: format!("Single sign-on error: {}", message),
+    }
+}
+
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+pub struct Oidc {
+    pub config: OidcConfig,
+    metadata: RwLock<Option<ProviderMetadata>>,
+    client: reqwest::Client,
+}
+
+/// Whether single sign-on is configured. Unlike `Option<&State<Oidc>>` it
+/// doesn't abort the launch when `Oidc` isn't managed
+pub struct Sso(pub bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Sso {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Sso(request.rocket().state::<Oidc>().is_some()))
+    }
+}
+
+impl Oidc {
+    pub fn new(config: OidcConfig) -> Self {
+        Oidc {
+            config,
+            metadata: RwLock::new(None),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetches the discovery document once and caches it for later logins
+    pub async fn metadata(&self) -> Result<ProviderMetadata, AppError> {
+        if let Some(metadata) = self.metadata.read().await.as_ref() {
+            return Ok(metadata.clone());
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer_url.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(oidc_error)?
+            .error_for_status()
+            .map_err(oidc_error)?
+            .json()
+            .await
+            .map_err(oidc_error)?;
+
+        *self.metadata.write().await = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Starts the authorization code flow, returns the url to redirect the user to
+    pub async fn authorization_url(&self, cookies: &CookieJar<'_>) -> Result<String, AppError> {
+        let metadata = self.metadata().await?;
+        let flow = AuthFlow {
+            state: auth::gen_token(32),
+            nonce: auth::gen_token(32),
+            verifier: auth::gen_token(64),
+        };
+
+        let challenge = pkce_challenge(&flow.verifier);
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", self.config.scopes.as_str()),
+                ("state", flow.state.as_str()),
+                ("nonce", flow.nonce.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(oidc_error)?;
+
+        let flow = serde_json::to_string(&flow).map_err(oidc_error)?;
+        cookies.add_private(
+            Cookie::build((FLOW_COOKIE, flow))
+                .path("/auth/oidc")
+                .same_site(SameSite::Lax)
+                .http_only(true),
+        );
+
+        Ok(url.to_string())
+    }
+
+    /// Finishes the flow started by `authorization_url`
+    pub async fn exchange(
+        &self,
+        code: &str,
+        state: &str,
+        cookies: &CookieJar<'_>,
+    ) -> Result<Identity, AppError> {
+        let flow: AuthFlow = cookies
+            .get_private(FLOW_COOKIE)
+            .and_then(|cookie| serde_json::from_str(cookie.value()).ok())
+            .ok_or(oidc_error("login flow expired, please try again"))?;
+        cookies.remove_private(Cookie::build(FLOW_COOKIE).path("/auth/oidc"));
+
+        if flow.state != state {
+            return Err(oidc_error("state mismatch"));
+        }
+
+        self.redeem(code, &flow).await
+    }
+
+    /// Trades the authorization code of `flow` for an id token and verifies
+    /// it against the provider's signing keys
+    async fn redeem(&self, code: &str, flow: &AuthFlow) -> Result<Identity, AppError> {
+        let metadata = self.metadata().await?;
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", flow.verifier.as_str()),
+        ];
+        if let Some(ref secret) = self.config.client_secret {
+            params.push(("client_secret", secret.as_str()));
+        }
+
+        let token: TokenResponse = self
+            .client
+            .post(&metadata.token_endpoint)
+            .form(&params)
+            .send()
+            .await
+            .map_err(oidc_error)?
+            .error_for_status()
+            .map_err(oidc_error)?
+            .json()
+            .await
+            .map_err(oidc_error)?;
+
+        let claims = self.verify_id_token(&token.id_token, &metadata).await?;
+        if claims.nonce.as_deref() != Some(flow.nonce.as_str()) {
+            return Err(oidc_error("nonce mismatch"));
+        }
+
+        let groups = match claims.extra.get(&self.config.groups_claim) {
+            Some(serde_json::Value::Array(groups)) => groups
+                .iter()
+                .filter_map(|group| group.as_str().map(String::from))
+                .collect(),
+            Some(serde_json::Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        };
+
+        Ok(Identity {
+            external_id: format!("{}|{}", metadata.issuer, claims.sub),
+            username: claims
+                .preferred_username
+                .or(claims.email)
+                .unwrap_or(claims.sub),
+            groups,
+        })
+    }
+
+    async fn verify_id_token(
+        &self,
+        id_token: &str,
+        metadata: &ProviderMetadata,
+    ) -> Result<IdTokenClaims, AppError> {
+        let header = decode_header(id_token).map_err(oidc_error)?;
+        let jwks: JwkSet = self
+            .client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .map_err(oidc_error)?
+            .json()
+            .await
+            .map_err(oidc_error)?;
+
+        let jwk = match header.kid {
+            Some(ref kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or(oidc_error("no matching signing key"))?;
+        let key = DecodingKey::from_jwk(jwk).map_err(oidc_error)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&metadata.issuer]);
+
+        let data = decode::<IdTokenClaims>(id_token, &key, &validation).map_err(oidc_error)?;
+        Ok(data.claims)
+    }
+
+    pub fn role_for(&self, groups: &[String]) -> Option<Role> {
+        groups
+            .iter()
+            .filter_map(|group| self.config.role_mapping.get(group).copied())
+            .max()
+            .or(self.config.default_role)
+    }
+
+    /// Finds or provisions the local account for an identity, the role is
+    /// synced from the identity provider on every login
+    pub async fn user_for(
+        &self,
+        identity: Identity,
+        pool: &Pool<Sqlite>,
+    ) -> Result<User, AppError> {
+        let role = self.role_for(&identity.groups).ok_or(AppError {
+            status: Status::Forbidden,
+            message: "Your account has no access to uptime-rs".to_string(),
+        })?;
+
+        if let Ok(mut user) = User::by_external_id(&identity.external_id, pool).await {
+            if user.role != role {
+                User::set_role(user.id, role, pool).await?;
+                user.role = role;
+            }
+            return Ok(user);
+        }
+
+        let mut user = User {
+            id: 0, // field ignored, this is an autoincrement field
+            username: identity.username,
+            password_hash: String::new(), // never matches, sso accounts can't use a password
+            role,
+            created_at: chrono::Local::now().pretty_string(),
+            external_id: Some(identity.external_id.clone()),
+            totp_secret: None,
+        };
+
+        if User::by_username(&user.username, pool).await.is_ok() {
+            user.username = identity.external_id;
+        }
+
+        Ok(user.create(pool).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use rocket::tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    const CLIENT_ID: &str = "uptime-rs";
+    const SECRET: &[u8] = b"signing secret of the mock issuer";
+
+    fn config(issuer_url: &str) -> OidcConfig {
+        OidcConfig {
+            issuer_url: issuer_url.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost:8000/auth/oidc/callback".to_string(),
+            scopes: default_scopes(),
+            groups_claim: default_groups_claim(),
+            role_mapping: HashMap::from([
+                ("ops".to_string(), Role::Editor),
+                ("admins".to_string(), Role::Admin),
+            ]),
+            default_role: None,
+        }
+    }
+
+    fn flow() -> AuthFlow {
+        AuthFlow {
+            state: "state".to_string(),
+            nonce: "nonce".to_string(),
+            verifier: "verifier".to_string(),
+        }
+    }
+
+    /// Claims of a valid id token for `flow()` issued by `issuer`
+    fn claims(issuer: &str) -> serde_json::Value {
+        serde_json::json!({
+            "iss": issuer,
+            "aud": CLIENT_ID,
+            "sub": "42",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "nonce": "nonce",
+            "preferred_username": "alice",
+            "groups": ["ops", "everyone"],
+        })
+    }
+
+    /// Serves the discovery document, token endpoint and signing keys of an
+    /// issuer on localhost. The token endpoint answers every request with
+    /// an id token of `claims`, signed with `secret`, while the keys only
+    /// ever contain `SECRET`. Returns the issuer url
+    async fn mock_issuer(
+        claims: impl FnOnce(&str) -> serde_json::Value,
+        secret: &'static [u8],
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut header = Header::new(jsonwebtoken::Algorithm::HS256);
+        header.kid = Some("mock".to_string());
+        let id_token = encode(&header, &claims(&url), &EncodingKey::from_secret(secret)).unwrap();
+        let discovery = serde_json::json!({
+            "issuer": url,
+            "authorization_endpoint": format!("{}/authorize", url),
+            "token_endpoint": format!("{}/token", url),
+            "jwks_uri": format!("{}/jwks", url),
+        });
+        let token = serde_json::json!({ "id_token": id_token, "token_type": "Bearer" });
+        let jwks = serde_json::json!({
+            "keys": [{ "kty": "oct", "kid": "mock", "alg": "HS256", "k": URL_SAFE_NO_PAD.encode(SECRET) }],
+        });
+
+        rocket::tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let request = read_request(&mut stream).await;
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let body = match path {
+                    "/.well-known/openid-configuration" => discovery.to_string(),
+                    "/token" => token.to_string(),
+                    "/jwks" => jwks.to_string(),
+                    _ => "{}".to_string(),
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        url
+    }
+
+    /// Reads the head and, up to its Content-Length, the body of a request.
+    /// Closing the connection with the body unread would reset it
+    async fn read_request(stream: &mut rocket::tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|length| length.trim().parse().unwrap_or(0))
+                    })
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    return text.to_string();
+                }
+            }
+
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return text.to_string(),
+                Ok(read) => request.extend_from_slice(&buffer[..read]),
+            }
+        }
+    }
+
+    #[test]
+    fn pkce_challenge_matches_rfc_7636() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn role_for_picks_the_highest_mapped_role() {
+        let mut oidc = Oidc::new(config("http://localhost"));
+        let groups = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            oidc.role_for(&groups(&["ops", "admins"])),
+            Some(Role::Admin)
+        );
+        assert_eq!(
+            oidc.role_for(&groups(&["ops", "everyone"])),
+            Some(Role::Editor)
+        );
+        assert_eq!(oidc.role_for(&groups(&["everyone"])), None);
+
+        oidc.config.default_role = Some(Role::Viewer);
+        assert_eq!(oidc.role_for(&groups(&["everyone"])), Some(Role::Viewer));
+        assert_eq!(oidc.role_for(&[]), Some(Role::Viewer));
+    }
+
+    #[rocket::async_test]
+    async fn redeem_verifies_the_id_token() {
+        let url = mock_issuer(claims, SECRET).await;
+        let oidc = Oidc::new(config(&url));
+
+        let identity = oidc
+            .redeem("code", &flow())
+            .await
+            .map_err(|err| err.message)
+            .unwrap();
+        assert_eq!(identity.external_id, format!("{}|42", url));
+        assert_eq!(identity.username, "alice");
+        assert_eq!(identity.groups, ["ops", "everyone"]);
+    }
+
+    #[rocket::async_test]
+    async fn redeem_rejects_a_token_signed_with_another_key() {
+        let url = mock_issuer(claims, b"somebody else's secret").await;
+        let oidc = Oidc::new(config(&url));
+
+        assert!(oidc.redeem("code", &flow()).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn redeem_rejects_a_token_for_another_client() {
+        let url = mock_issuer(
+            |issuer| {
+                let mut claims = claims(issuer);
+                claims["aud"] = "another-client".into();
+                claims
+            },
+            SECRET,
+        )
+        .await;
+        let oidc = Oidc::new(config(&url));
+
+        assert!(oidc.redeem("code", &flow()).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn redeem_rejects_a_replayed_nonce() {
+        let url = mock_issuer(claims, SECRET).await;
+        let oidc = Oidc::new(config(&url));
+        let flow = AuthFlow {
+            nonce: "another nonce".to_string(),
+            ..flow()
+        };
+
+        assert!(oidc.redeem("code", &flow).await.is_err());
+    }
+}
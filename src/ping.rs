@@ -1,33 +1,124 @@
+use crate::anomaly;
+use crate::database::MonitorState;
+use crate::dependency::Dependency;
+use crate::flapping::{self, Flap, FlapChange};
+use crate::live::{LiveEvent, LiveEvents, PingEvent, StateEvent};
+use crate::maintenance::MaintenanceWindow;
+use crate::notification::{Notification, NotifyConfig};
+use crate::probe::{self, EchoStats, Timings};
 use crate::time::PrettyPrint;
 use crate::{database, utils, DatabaseModel};
-use rocket::{futures::lock::Mutex, http::Status};
+use rocket::{
+    futures::lock::Mutex,
+    http::{Status, StatusClass},
+};
 use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::{sync::Arc, thread, time};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Protocol {
+    #[serde(alias = "http")]
     HTTP,
+    #[serde(alias = "https")]
     HTTPS,
+    #[serde(alias = "ws")]
+    WS,
+    #[serde(alias = "wss")]
+    WSS,
+    #[serde(alias = "icmp")]
+    ICMP,
 }
 
-impl Display for Protocol {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Protocol {
+    pub fn all() -> [Protocol; 5] {
+        [
+            Protocol::HTTP,
+            Protocol::HTTPS,
+            Protocol::WS,
+            Protocol::WSS,
+            Protocol::ICMP,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Protocol::HTTP => write!(f, "http"),
-            Protocol::HTTPS => write!(f, "https"),
+            Protocol::HTTP => "http",
+            Protocol::HTTPS => "https",
+            Protocol::WS => "ws",
+            Protocol::WSS => "wss",
+            Protocol::ICMP => "icmp",
         }
     }
+
+    /// Case insensitive, like the `protocol` column and form field
+    pub fn parse(value: &str) -> Option<Self> {
+        Protocol::all()
+            .into_iter()
+            .find(|protocol| protocol.as_str().eq_ignore_ascii_case(value.trim()))
+    }
+
+    pub fn is_websocket(&self) -> bool {
+        matches!(self, Protocol::WS | Protocol::WSS)
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// How long a WebSocket monitor waits for a reply without `ws_timeout_ms`
+pub const DEFAULT_WS_TIMEOUT_MS: i64 = 10_000;
+
+/// Echo requests an ICMP monitor sends without `icmp_count`
+pub const DEFAULT_ICMP_COUNT: i64 = 4;
+
+/// Each request may wait a second for its reply, more would hold up the
+/// other monitors
+pub const MAX_ICMP_COUNT: i64 = 20;
+
+/// Packet loss in percent an ICMP monitor tolerates without `icmp_max_loss`
+pub const DEFAULT_ICMP_MAX_LOSS: i64 = 50;
+
+/// Checks the reply pattern and ICMP settings of a monitor, so a typo
+/// doesn't turn into a monitor that is never up
+pub fn check_settings(
+    ws_expect: Option<&str>,
+    icmp_count: Option<i64>,
+    icmp_max_loss: Option<i64>,
+) -> Result<(), String> {
+    if let Some(pattern) = ws_expect {
+        regex::Regex::new(pattern).map_err(|err| format!("Invalid reply pattern: {}", err))?;
+    }
+    if icmp_count.is_some_and(|count| !(1..=MAX_ICMP_COUNT).contains(&count)) {
+        return Err(format!("Echo requests must be 1 to {}", MAX_ICMP_COUNT));
+    }
+    if icmp_max_loss.is_some_and(|loss| !(0..=100).contains(&loss)) {
+        return Err("Packet loss must be 0 to 100%".to_string());
+    }
+
+    Ok(())
 }
 
+/// States a notification is sent between
+const REPORTABLE: [MonitorState; 3] =
+    [MonitorState::Up, MonitorState::Degraded, MonitorState::Down];
+
 #[derive(Debug, Clone)]
 pub struct Pinger {
     pub monitor: database::Monitor,
     pub callback: fn(),
     pub enabled: bool,
     last_ping: i64,
+    /// Set while a check runs, clones share it so a slow check isn't
+    /// started again before it finished
+    checking: Arc<AtomicBool>,
 }
 
 #[derive(Debug)]
@@ -35,6 +126,9 @@ pub struct PingResponse {
     pub is_alive: bool,
     pub status: Status,
     pub duration: Duration,
+    pub timings: Option<Timings>,
+    pub round_trip_ms: Option<i64>,
+    pub icmp: Option<EchoStats>,
 }
 
 impl Pinger {
@@ -45,80 +139,310 @@ impl Pinger {
             callback,
             enabled,
             last_ping: timeout_sec,
+            checking: Arc::new(AtomicBool::new(false)),
         }
     }
 
     async fn ping(&self) -> PingResponse {
+        if self.monitor.protocol.is_websocket() {
+            return self.ping_websocket().await;
+        }
+        if self.monitor.protocol == Protocol::ICMP {
+            return self.ping_icmp().await;
+        }
+
         let start = Instant::now();
-        let response = reqwest::get(&self.monitor.address()).await;
+        let probe = probe::http(&self.monitor.address()).await;
         let duration = start.elapsed();
 
-        return match response {
-            Ok(res) => {
-                let status = Status::from_code(res.status().as_u16()).unwrap();
-                PingResponse {
-                    is_alive: res.status().is_success(),
-                    status,
-                    duration,
-                }
-            }
-            Err(_) => PingResponse {
+        return match probe.status.and_then(Status::from_code) {
+            Some(status) => PingResponse {
+                is_alive: status.class() == StatusClass::Success,
+                status,
+                duration,
+                timings: Some(probe.timings),
+                round_trip_ms: None,
+                icmp: None,
+            },
+            None => PingResponse {
                 is_alive: false,
                 status: Status::InternalServerError,
                 duration,
+                timings: Some(probe.timings),
+                round_trip_ms: None,
+                icmp: None,
             },
         };
     }
 
-    pub async fn tick(&mut self) {
-        if self.last_ping >= self.monitor.interval {
-            let ping = self.ping().await;
-            let pool = database::initialize().await;
-
-            if ping.is_alive {
-                let ping = database::MonitorPing {
-                    id: utils::gen_id(),
-                    monitor_id: self.monitor.id,
-                    timestamp: chrono::Local::now().pretty_string(),
-                    status: Status::from_code(ping.status.code).unwrap_or(Status::ImATeapot),
-                    duration_ms: ping.duration.as_millis() as i64,
-                    bad: false,
-                };
-
-                match ping.create(&pool).await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        warn!("Failed to create ping: {}", e);
-                    }
-                }
+    /// Alive once the upgrade succeeded and, when the monitor has a message
+    /// or pattern, a matching reply arrived in time
+    async fn ping_websocket(&self) -> PingResponse {
+        let check = probe::WebSocketCheck {
+            message: self.monitor.ws_message.clone(),
+            expect: self.monitor.ws_expect.clone(),
+            timeout: Duration::from_millis(
+                self.monitor
+                    .ws_timeout_ms
+                    .unwrap_or(DEFAULT_WS_TIMEOUT_MS)
+                    .max(0) as u64,
+            ),
+        };
 
-                println!("{} is alive", self.monitor.address());
+        let start = Instant::now();
+        let probe = probe::websocket(&self.monitor.address(), &check).await;
+
+        PingResponse {
+            is_alive: probe.alive,
+            status: if probe.alive {
+                Status::SwitchingProtocols
             } else {
-                let ping = database::MonitorPing {
-                    id: utils::gen_id(),
-                    monitor_id: self.monitor.id,
-                    timestamp: chrono::Local::now().pretty_string(),
-                    status: Status::Ok,
-                    duration_ms: ping.duration.as_millis() as i64,
-                    bad: true,
-                };
-
-                ping.create(&pool).await.expect("Failed to create ping");
-                println!("{} is dead", self.monitor.address());
-            }
+                Status::InternalServerError
+            },
+            duration: start.elapsed(),
+            timings: Some(probe.timings),
+            round_trip_ms: probe.round_trip_ms,
+            icmp: None,
+        }
+    }
+
+    /// Alive when any echo request was answered and the loss stays within
+    /// `icmp_max_loss`, the duration is the average round trip time
+    async fn ping_icmp(&self) -> PingResponse {
+        let count = self
+            .monitor
+            .icmp_count
+            .unwrap_or(DEFAULT_ICMP_COUNT)
+            .clamp(1, MAX_ICMP_COUNT);
+        let max_loss = self.monitor.icmp_max_loss.unwrap_or(DEFAULT_ICMP_MAX_LOSS);
+
+        let start = Instant::now();
+        let stats = probe::icmp(&self.monitor.ip, count as u16).await;
+        let is_alive = stats.received > 0 && stats.loss_percent() <= max_loss as f64;
+
+        PingResponse {
+            is_alive,
+            status: if is_alive {
+                Status::Ok
+            } else {
+                Status::InternalServerError
+            },
+            duration: match stats.received {
+                0 => start.elapsed(),
+                _ => Duration::from_secs_f64(stats.avg_ms / 1000.0),
+            },
+            timings: None,
+            round_trip_ms: None,
+            icmp: Some(stats),
+        }
+    }
 
-            pool.close().await;
+    /// Counts a second, true when a check is due and none is running
+    pub fn tick(&mut self) -> bool {
+        let due = self.last_ping >= self.monitor.interval && !self.checking.load(Ordering::SeqCst);
+        if due {
+            self.checking.store(true, Ordering::SeqCst);
             self.last_ping = 0;
         }
 
         self.last_ping += 1;
+        due
+    }
+
+    /// Pings the monitor, stores the ping and publishes what changed
+    pub async fn check(&self, events: &LiveEvents) {
+        let ping = self.ping().await;
+        let pool = database::initialize().await;
+        let maintenance = MaintenanceWindow::is_monitor_in_maintenance(self.monitor.id, &pool)
+            .await
+            .unwrap_or(false);
+        let history =
+            database::MonitorPing::last_n(&pool, self.monitor.id, anomaly::BASELINE_PINGS).await;
+
+        let ping = if ping.is_alive {
+            let duration_ms = ping.duration.as_millis() as i64;
+            let ping = database::MonitorPing {
+                id: utils::gen_id(),
+                monitor_id: self.monitor.id,
+                timestamp: chrono::Local::now().pretty_string(),
+                status: Status::from_code(ping.status.code).unwrap_or(Status::ImATeapot),
+                duration_ms,
+                bad: false,
+                maintenance,
+                unreachable: false,
+                degraded: anomaly::is_degraded(&self.monitor, duration_ms, &history),
+                round_trip_ms: ping.round_trip_ms,
+                icmp: ping.icmp,
+                timings: ping.timings,
+            };
+
+            match ping.create(&pool).await {
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Failed to create ping: {}", e);
+                }
+            }
+
+            println!("{} is alive", self.monitor.address());
+            ping
+        } else {
+            let unreachable = Dependency::is_parent_down(self.monitor.id, &pool)
+                .await
+                .unwrap_or(false);
+            let ping = database::MonitorPing {
+                id: utils::gen_id(),
+                monitor_id: self.monitor.id,
+                timestamp: chrono::Local::now().pretty_string(),
+                status: Status::Ok,
+                duration_ms: ping.duration.as_millis() as i64,
+                bad: true,
+                maintenance,
+                unreachable,
+                degraded: false,
+                round_trip_ms: ping.round_trip_ms,
+                icmp: ping.icmp,
+                timings: ping.timings,
+            };
+
+            ping.create(&pool).await.expect("Failed to create ping");
+            println!("{} is dead", self.monitor.address());
+            ping
+        };
+
+        let previous = MonitorState::of(&self.monitor, history.first());
+        let current = MonitorState::of(&self.monitor, Some(&ping));
+        let reported = history
+            .iter()
+            .map(|ping| MonitorState::of(&self.monitor, Some(ping)))
+            .find(|state| REPORTABLE.contains(state));
+        let mut states = flapping::states(&self.monitor, std::slice::from_ref(&ping));
+        states.extend(flapping::states(&self.monitor, &history));
+        let rate = flapping::state_change_rate(&states);
+        let change = Flap::update(self.monitor.id, rate, &pool).await;
+        match &change {
+            Ok(FlapChange::Started) => self.notify_flapping(rate),
+            Ok(FlapChange::Stopped) => self.notify_settled(current),
+            Ok(FlapChange::Ongoing) => {}
+            Ok(FlapChange::Stable) => self.notify(reported, current, &ping),
+            Err(err) => {
+                warn!(
+                    "Failed to update flapping of {}: {}",
+                    self.monitor.name, err
+                );
+                self.notify(reported, current, &ping);
+            }
+        }
+
+        let flap_changed = matches!(change, Ok(FlapChange::Started | FlapChange::Stopped));
+        if previous != current || flap_changed {
+            events.publish(LiveEvent::State(StateEvent {
+                monitor_id: self.monitor.id,
+                previous,
+                current,
+                flapping: matches!(change, Ok(FlapChange::Started | FlapChange::Ongoing)),
+            }));
+        }
+        events.publish(LiveEvent::Ping(PingEvent {
+            monitor_id: self.monitor.id,
+            timestamp: ping.timestamp.clone(),
+            duration_ms: ping.duration_ms,
+            bad: ping.bad,
+            degraded: ping.degraded,
+            up: !ping.bad && ping.status.code <= 400,
+            state: current,
+            uptime_percentage: self.monitor.get_uptime_percentage(&pool).await,
+        }));
+
+        pool.close().await;
+    }
+
+    /// Only changes between up, degraded and down are sent, maintenance and
+    /// unreachable pings are expected to fail and the first ping of a new
+    /// monitor isn't news either. `reported` is the newest of those states in
+    /// the history, so leaving maintenance down still alerts
+    fn notify(
+        &self,
+        reported: Option<MonitorState>,
+        current: MonitorState,
+        ping: &database::MonitorPing,
+    ) {
+        let Some(previous) = reported else {
+            return;
+        };
+        if previous == current || !REPORTABLE.contains(&current) {
+            return;
+        }
+
+        let name = &self.monitor.name;
+        let (title, message) = match current {
+            MonitorState::Down => (
+                format!("{} is down", name),
+                format!("{} stopped responding", self.monitor.address()),
+            ),
+            MonitorState::Degraded => (
+                format!("{} is degraded", name),
+                format!(
+                    "{} took {} ms to respond",
+                    self.monitor.address(),
+                    ping.duration_ms
+                ),
+            ),
+            _ => (
+                format!("{} is up", name),
+                format!(
+                    "{} responded in {} ms",
+                    self.monitor.address(),
+                    ping.duration_ms
+                ),
+            ),
+        };
+
+        Notification::new(&format!("monitor.{}", current), title, message)
+            .send_in_background(NotifyConfig::from_env());
+    }
+
+    /// Sent once, state changes are held back until the monitor settles
+    fn notify_flapping(&self, rate: f64) {
+        Notification::new(
+            "monitor.flapping",
+            format!("{} is flapping", self.monitor.name),
+            format!(
+                "{} changed state in {:.0}% of the last {} checks, \
+                 further changes are held back until it settles",
+                self.monitor.address(),
+                rate * 100.0,
+                flapping::WINDOW
+            ),
+        )
+        .send_in_background(NotifyConfig::from_env());
+    }
+
+    /// Tells the state the monitor settled in, whatever it was before flapping
+    fn notify_settled(&self, current: MonitorState) {
+        Notification::new(
+            "monitor.settled",
+            format!("{} stopped flapping", self.monitor.name),
+            format!("{} is {}", self.monitor.address(), current),
+        )
+        .send_in_background(NotifyConfig::from_env());
     }
 }
 
-#[derive(Debug)]
+/// Monitor ids touched by `PingerManager::reload`
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadSummary {
+    pub added: Vec<i64>,
+    pub updated: Vec<i64>,
+    pub removed: Vec<i64>,
+}
+
+/// Clones share the same pingers, so a clone can be handed to background tasks
+#[derive(Debug, Clone)]
 pub struct PingerManager {
     pub started: bool,
     pub pingers: Arc<Mutex<HashMap<i64, Pinger>>>,
+    /// Every ping and state change is published here for `/events`
+    pub events: LiveEvents,
 }
 
 impl PingerManager {
@@ -126,6 +450,7 @@ impl PingerManager {
         PingerManager {
             started: false,
             pingers: Arc::new(Mutex::new(HashMap::new())),
+            events: LiveEvents::new(),
         }
     }
 
@@ -154,20 +479,71 @@ impl PingerManager {
         }
     }
 
+    /// Syncs the running pingers with the monitors stored in the database.
+    /// Unchanged pingers are left alone and updated ones keep their schedule
+    pub async fn reload(&self, pool: &Pool<Sqlite>) -> Result<ReloadSummary, sqlx::Error> {
+        let mut monitors: HashMap<i64, database::Monitor> = database::Monitor::all(pool)
+            .await?
+            .into_iter()
+            .map(|monitor| (monitor.id, monitor))
+            .collect();
+
+        let mut summary = ReloadSummary::default();
+        let mut gaurd = self.pingers.lock().await;
+
+        gaurd.retain(|id, pinger| match monitors.remove(id) {
+            Some(monitor) => {
+                if monitor != pinger.monitor {
+                    pinger.enabled = !&monitor.paused;
+                    pinger.monitor = monitor;
+                    summary.updated.push(*id);
+                }
+                true
+            }
+            None => {
+                summary.removed.push(*id);
+                false
+            }
+        });
+
+        for (id, monitor) in monitors {
+            let interval = monitor.interval;
+            gaurd.insert(id, Pinger::new(monitor, interval, || {}));
+            summary.added.push(id);
+        }
+
+        summary.added.sort();
+        summary.updated.sort();
+        summary.removed.sort();
+
+        Ok(summary)
+    }
+
     pub async fn start(&mut self) {
         let pingers = self.pingers.clone();
+        let events = self.events.clone();
         self.started = true;
         tokio::spawn(async move {
             loop {
+                // checks run outside the lock, a slow ICMP or WebSocket
+                // check would hold back every other monitor otherwise
                 let mut gaurd = pingers.lock().await;
                 for (_, pinger) in gaurd.iter_mut() {
-                    if pinger.enabled {
-                        pinger.tick().await;
+                    if pinger.enabled && pinger.tick() {
+                        let pinger = pinger.clone();
+                        let events = events.clone();
+                        tokio::spawn(async move {
+                            let checking = pinger.checking.clone();
+                            // a panicking check must not keep the monitor
+                            // from being checked again
+                            let _ = tokio::spawn(async move { pinger.check(&events).await }).await;
+                            checking.store(false, Ordering::SeqCst);
+                        });
                     }
                 }
                 drop(gaurd);
 
-                thread::sleep(time::Duration::from_secs(1));
+                tokio::time::sleep(Duration::from_secs(1)).await;
             }
         });
     }
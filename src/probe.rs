@@ -0,0 +1,839 @@
+use regex::Regex;
+use reqwest::Url;
+use rocket::futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsConnector};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use url::Host;
+
+/// Redirects followed before giving up, like reqwest's default policy
+const MAX_REDIRECTS: usize = 10;
+
+/// The whole request including redirects has to finish within this
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest response head accepted, the body is read and dropped
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// How long to wait for each echo reply
+const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Sent after the ICMP header of every echo request
+const ECHO_PAYLOAD: &[u8] = b"uptime-rs icmp echo request 0123";
+
+/// Time spent in each phase of an http request, summed over redirects.
+/// Phases that weren't reached stay 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct Timings {
+    pub dns_ms: i64,
+    pub connect_ms: i64,
+    /// 0 for plain http
+    pub tls_ms: i64,
+    /// From sending the request until the first byte of the response, for
+    /// WebSocket monitors the whole upgrade handshake
+    pub ttfb_ms: i64,
+    pub download_ms: i64,
+}
+
+/// Result of an http probe, `status` is None when no response arrived
+#[derive(Debug)]
+pub struct HttpProbe {
+    pub status: Option<u16>,
+    pub timings: Timings,
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+fn elapsed_ms(start: Instant) -> i64 {
+    start.elapsed().as_millis() as i64
+}
+
+impl Timings {
+    /// Pings stored before timings were measured have none
+    pub fn from_columns(
+        dns_ms: Option<i64>,
+        connect_ms: Option<i64>,
+        tls_ms: Option<i64>,
+        ttfb_ms: Option<i64>,
+        download_ms: Option<i64>,
+    ) -> Option<Self> {
+        Some(Timings {
+            dns_ms: dns_ms?,
+            connect_ms: connect_ms?,
+            tls_ms: tls_ms?,
+            ttfb_ms: ttfb_ms?,
+            download_ms: download_ms?,
+        })
+    }
+
+    pub fn average(timings: &[Timings]) -> Option<Self> {
+        if timings.is_empty() {
+            return None;
+        }
+
+        let count = timings.len() as i64;
+        let sum = |phase: fn(&Timings) -> i64| timings.iter().map(phase).sum::<i64>() / count;
+        Some(Timings {
+            dns_ms: sum(|t| t.dns_ms),
+            connect_ms: sum(|t| t.connect_ms),
+            tls_ms: sum(|t| t.tls_ms),
+            ttfb_ms: sum(|t| t.ttfb_ms),
+            download_ms: sum(|t| t.download_ms),
+        })
+    }
+}
+
+/// Resolves and connects to the host of `url`, with TLS for https and wss
+async fn connect(url: &Url, timings: &mut Timings) -> Result<Box<dyn Stream>, String> {
+    let host = url.host().ok_or("The address has no host")?;
+    let port = url
+        .port_or_known_default()
+        .ok_or("The address has no port")?;
+
+    // IP literals are used as they are, `host_str` keeps the brackets of IPv6
+    let start = Instant::now();
+    let (domain, addresses) = match host {
+        Host::Domain(domain) => {
+            let addresses: Vec<_> = tokio::net::lookup_host((domain, port))
+                .await
+                .map_err(|err| format!("DNS lookup failed: {}", err))?
+                .collect();
+            (domain.to_string(), addresses)
+        }
+        Host::Ipv4(address) => (
+            address.to_string(),
+            vec![SocketAddr::new(address.into(), port)],
+        ),
+        Host::Ipv6(address) => (
+            address.to_string(),
+            vec![SocketAddr::new(address.into(), port)],
+        ),
+    };
+    timings.dns_ms += elapsed_ms(start);
+
+    let start = Instant::now();
+    let mut tcp = None;
+    for address in addresses.iter() {
+        if let Ok(stream) = TcpStream::connect(address).await {
+            tcp = Some(stream);
+            break;
+        }
+    }
+    let tcp = tcp.ok_or(format!("Failed to connect to {}:{}", host, port))?;
+    timings.connect_ms += elapsed_ms(start);
+
+    if !matches!(url.scheme(), "https" | "wss") {
+        return Ok(Box::new(tcp));
+    }
+
+    let start = Instant::now();
+    let connector = native_tls::TlsConnector::new().map_err(|err| err.to_string())?;
+    let tls = TlsConnector::from(connector)
+        .connect(&domain, tcp)
+        .await
+        .map_err(|err| format!("TLS handshake failed: {}", err))?;
+    timings.tls_ms += elapsed_ms(start);
+
+    Ok(Box::new(tls))
+}
+
+/// How the end of a response body is found
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BodyEnd {
+    Length(usize),
+    /// The terminating empty chunk
+    Chunked,
+    /// Neither a length nor chunks, the body ends when the server closes the
+    /// connection
+    Close,
+}
+
+/// Ends a chunked body, trailers aren't expected
+const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+
+impl BodyEnd {
+    /// `tail` holds the last bytes received
+    fn reached(&self, received: usize, tail: &[u8]) -> bool {
+        match self {
+            BodyEnd::Length(length) => received >= *length,
+            BodyEnd::Chunked => tail.ends_with(LAST_CHUNK),
+            BodyEnd::Close => false,
+        }
+    }
+}
+
+/// The status code in the first line of a response head
+fn status_of(head: &[u8]) -> Option<u16> {
+    let line = head.split(|byte| *byte == b'\n').next()?;
+    String::from_utf8_lossy(line)
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// One request without following redirects, returns the status and the
+/// location header. `timings` keeps what was measured when it fails
+async fn request(url: &Url, timings: &mut Timings) -> Result<(u16, Option<String>), String> {
+    let mut stream = connect(url, timings).await?;
+    let host = url.host_str().ok_or("The address has no host")?;
+
+    let host_header = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: uptime-rs\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        path, host_header
+    );
+
+    let start = Instant::now();
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut buffer = vec![0; 8192];
+    let mut response = Vec::new();
+    let mut first_byte = None;
+    let (head_end, status) = loop {
+        if let Some(end) = response.windows(4).position(|window| window == b"\r\n\r\n") {
+            let status = status_of(&response[..end]).ok_or("Invalid response")?;
+            // interim responses such as 100 Continue come before the final one
+            if (100..200).contains(&status) && status != 101 {
+                response.drain(..end + 4);
+                continue;
+            }
+            break (end + 4, status);
+        }
+        if response.len() > MAX_HEAD_BYTES {
+            return Err("The response headers are too large".to_string());
+        }
+
+        let read = stream
+            .read(&mut buffer)
+            .await
+            .map_err(|err| err.to_string())?;
+        match (read, first_byte) {
+            (0, None) => return Err("The server closed the connection without a response".into()),
+            (0, Some(_)) => return Err("The response ended inside its headers".into()),
+            (_, None) => {
+                timings.ttfb_ms += elapsed_ms(start);
+                first_byte = Some(Instant::now());
+            }
+            _ => {}
+        }
+        response.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&response[..head_end]).to_string();
+    let lines = head.lines().skip(1);
+    let headers: Vec<(&str, &str)> = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    };
+    let location = header("location").map(String::from);
+
+    // only the status is needed, the body is read to time the download. Its
+    // end comes from the headers since some servers keep the connection open
+    // despite `Connection: close`
+    let body_end = if (100..200).contains(&status) || status == 204 || status == 304 {
+        BodyEnd::Length(0)
+    } else if header("transfer-encoding")
+        .is_some_and(|value| value.to_lowercase().contains("chunked"))
+    {
+        BodyEnd::Chunked
+    } else {
+        match header("content-length").and_then(|length| length.parse().ok()) {
+            Some(length) => BodyEnd::Length(length),
+            None => BodyEnd::Close,
+        }
+    };
+
+    // only the tail is kept, enough to spot the last chunk
+    let mut received = response.len() - head_end;
+    let mut tail = response.split_off(head_end);
+    while !body_end.reached(received, &tail) {
+        let read = stream
+            .read(&mut buffer)
+            .await
+            .map_err(|err| err.to_string())?;
+        if read == 0 {
+            break;
+        }
+        received += read;
+        tail.extend_from_slice(&buffer[..read]);
+        tail.drain(..tail.len().saturating_sub(LAST_CHUNK.len()));
+    }
+    timings.download_ms += first_byte.map_or(0, elapsed_ms);
+
+    Ok((status, location))
+}
+
+/// GETs `address` following redirects, timing every phase separately since
+/// reqwest only tells the total
+pub async fn http(address: &str) -> HttpProbe {
+    let mut timings = Timings::default();
+
+    let probe = async {
+        let mut url = Url::parse(address).map_err(|err| err.to_string())?;
+        for _ in 0..=MAX_REDIRECTS {
+            let (status, location) = request(&url, &mut timings).await?;
+            match location {
+                Some(location) if (300..400).contains(&status) => {
+                    url = url.join(&location).map_err(|err| err.to_string())?;
+                }
+                _ => return Ok(status),
+            }
+        }
+
+        Err(format!("More than {} redirects", MAX_REDIRECTS))
+    };
+
+    let status = match tokio::time::timeout(TIMEOUT, probe).await {
+        Ok(Ok(status)) => Some(status),
+        Ok(Err(err)) => {
+            debug!("Probe of {} failed: {}", address, err);
+            None
+        }
+        Err(_) => {
+            debug!("Probe of {} timed out", address);
+            None
+        }
+    };
+
+    HttpProbe { status, timings }
+}
+
+/// What a WebSocket monitor sends and expects back
+#[derive(Debug, Clone)]
+pub struct WebSocketCheck {
+    /// Sent as a text message after the handshake
+    pub message: Option<String>,
+    /// Regex the reply has to match, any reply does without it
+    pub expect: Option<String>,
+    /// How long to wait for the reply
+    pub timeout: Duration,
+}
+
+/// Result of a WebSocket probe. The handshake is timed like an http request
+/// with the upgrade response as first byte, `round_trip_ms` is set when a
+/// reply arrived
+#[derive(Debug)]
+pub struct WebSocketProbe {
+    pub alive: bool,
+    pub timings: Timings,
+    pub round_trip_ms: Option<i64>,
+}
+
+impl WebSocketCheck {
+    /// Whether the probe waits for a reply at all
+    fn expects_reply(&self) -> bool {
+        self.message.is_some() || self.expect.is_some()
+    }
+}
+
+/// Sends the message of `check` and waits for a matching reply, messages
+/// that don't match are skipped
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut WebSocketStream<S>,
+    check: &WebSocketCheck,
+    expect: Option<&Regex>,
+) -> Result<(), String> {
+    if let Some(message) = check.message.clone() {
+        socket
+            .send(Message::Text(message))
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    while let Some(reply) = socket.next().await {
+        let reply = match reply.map_err(|err| err.to_string())? {
+            Message::Text(text) => text,
+            Message::Binary(data) => String::from_utf8_lossy(&data).into_owned(),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        if expect.is_none_or(|pattern| pattern.is_match(&reply)) {
+            return Ok(());
+        }
+    }
+
+    Err("The server closed the connection without a matching reply".to_string())
+}
+
+/// Performs the upgrade handshake against a `ws://` or `wss://` address and
+/// optionally exchanges a message
+pub async fn websocket(address: &str, check: &WebSocketCheck) -> WebSocketProbe {
+    let mut timings = Timings::default();
+    let mut round_trip_ms = None;
+
+    let probe = async {
+        let url = Url::parse(address).map_err(|err| err.to_string())?;
+        let expect = check
+            .expect
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|err| format!("Invalid pattern: {}", err))?;
+
+        let mut socket = tokio::time::timeout(TIMEOUT, async {
+            let stream = connect(&url, &mut timings).await?;
+            let start = Instant::now();
+            let (socket, _) = tokio_tungstenite::client_async(url.as_str(), stream)
+                .await
+                .map_err(|err| format!("Upgrade failed: {}", err))?;
+            timings.ttfb_ms += elapsed_ms(start);
+            Ok::<_, String>(socket)
+        })
+        .await
+        .map_err(|_| "The handshake timed out".to_string())??;
+
+        if check.expects_reply() {
+            let start = Instant::now();
+            tokio::time::timeout(check.timeout, exchange(&mut socket, check, expect.as_ref()))
+                .await
+                .map_err(|_| "No matching reply in time".to_string())??;
+            round_trip_ms = Some(elapsed_ms(start));
+        }
+
+        let _ = socket.close(None).await;
+        Ok::<_, String>(())
+    };
+
+    let alive = match probe.await {
+        Ok(()) => true,
+        Err(err) => {
+            debug!("Probe of {} failed: {}", address, err);
+            false
+        }
+    };
+
+    WebSocketProbe {
+        alive,
+        timings,
+        round_trip_ms,
+    }
+}
+
+/// Results of the echo requests of one ICMP check. The round trip times are
+/// 0 when no reply arrived
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct EchoStats {
+    pub sent: i64,
+    pub received: i64,
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    /// Mean difference between the round trip times of consecutive replies
+    pub jitter_ms: f64,
+}
+
+impl EchoStats {
+    /// Pings of other protocols have none
+    pub fn from_columns(
+        sent: Option<i64>,
+        received: Option<i64>,
+        min_ms: Option<f64>,
+        avg_ms: Option<f64>,
+        max_ms: Option<f64>,
+        jitter_ms: Option<f64>,
+    ) -> Option<Self> {
+        Some(EchoStats {
+            sent: sent?,
+            received: received?,
+            min_ms: min_ms?,
+            avg_ms: avg_ms?,
+            max_ms: max_ms?,
+            jitter_ms: jitter_ms?,
+        })
+    }
+
+    /// From the round trip time of every request, None for a lost one
+    fn of(round_trips: &[Option<f64>]) -> Self {
+        let replies: Vec<f64> = round_trips.iter().flatten().copied().collect();
+        if replies.is_empty() {
+            return EchoStats {
+                sent: round_trips.len() as i64,
+                ..Default::default()
+            };
+        }
+
+        let jitter_ms = match replies.len() {
+            1 => 0.0,
+            count => {
+                replies
+                    .windows(2)
+                    .map(|pair| (pair[1] - pair[0]).abs())
+                    .sum::<f64>()
+                    / (count - 1) as f64
+            }
+        };
+
+        EchoStats {
+            sent: round_trips.len() as i64,
+            received: replies.len() as i64,
+            min_ms: replies.iter().copied().fold(f64::INFINITY, f64::min),
+            avg_ms: replies.iter().sum::<f64>() / replies.len() as f64,
+            max_ms: replies.iter().copied().fold(0.0, f64::max),
+            jitter_ms,
+        }
+    }
+
+    /// Packets are summed up, round trip times averaged over the checks
+    /// that got a reply
+    pub fn merge(stats: &[EchoStats]) -> Option<Self> {
+        if stats.is_empty() {
+            return None;
+        }
+
+        let mut merged = EchoStats {
+            sent: stats.iter().map(|stats| stats.sent).sum(),
+            received: stats.iter().map(|stats| stats.received).sum(),
+            ..Default::default()
+        };
+        let answered: Vec<&EchoStats> = stats.iter().filter(|stats| stats.received > 0).collect();
+        if !answered.is_empty() {
+            let count = answered.len() as f64;
+            let mean =
+                |rtt: fn(&EchoStats) -> f64| answered.iter().map(|s| rtt(s)).sum::<f64>() / count;
+            merged.min_ms = mean(|s| s.min_ms);
+            merged.avg_ms = mean(|s| s.avg_ms);
+            merged.max_ms = mean(|s| s.max_ms);
+            merged.jitter_ms = mean(|s| s.jitter_ms);
+        }
+
+        Some(merged)
+    }
+
+    pub fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+
+        (self.sent - self.received) as f64 * 100.0 / self.sent as f64
+    }
+}
+
+/// Internet checksum of an ICMPv4 message, the kernel fills it in for
+/// ICMPv6
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+fn echo_request(ipv6: bool, identifier: u16, sequence: u16) -> Vec<u8> {
+    let mut packet = vec![if ipv6 { 128 } else { 8 }, 0, 0, 0];
+    packet.extend_from_slice(&identifier.to_be_bytes());
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(ECHO_PAYLOAD);
+    if !ipv6 {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    packet
+}
+
+/// Whether `packet` answers the request with `sequence`. Raw IPv4 sockets
+/// receive the IP header too and every ICMP message of the host, datagram
+/// sockets only get replies to their own requests since the kernel swaps in
+/// its own identifier
+fn is_echo_reply(packet: &[u8], ipv6: bool, raw: bool, identifier: u16, sequence: u16) -> bool {
+    let message = match packet.first() {
+        Some(first) if raw && !ipv6 => &packet[packet.len().min((first & 0x0f) as usize * 4)..],
+        _ => packet,
+    };
+    if message.len() < 8 {
+        return false;
+    }
+
+    message[0] == if ipv6 { 129 } else { 0 }
+        && u16::from_be_bytes([message[6], message[7]]) == sequence
+        && (!raw || u16::from_be_bytes([message[4], message[5]]) == identifier)
+}
+
+/// Unprivileged datagram sockets need the group of the process in
+/// `net.ipv4.ping_group_range`, raw sockets need CAP_NET_RAW
+fn echo_socket(ipv6: bool) -> Result<(Socket, bool), String> {
+    let (domain, protocol) = match ipv6 {
+        true => (Domain::IPV6, Protocol::ICMPV6),
+        false => (Domain::IPV4, Protocol::ICMPV4),
+    };
+
+    match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+        Ok(socket) => Ok((socket, false)),
+        Err(dgram_err) => Socket::new(domain, Type::RAW, Some(protocol))
+            .map(|socket| (socket, true))
+            .map_err(|raw_err| {
+                format!(
+                    "No ICMP socket, datagram: {}, raw: {}. Add the group to \
+                     net.ipv4.ping_group_range or grant CAP_NET_RAW",
+                    dgram_err, raw_err
+                )
+            }),
+    }
+}
+
+/// Sends `count` echo requests one after another, blocking until each is
+/// answered or timed out
+fn echo(address: IpAddr, count: u16) -> Result<Vec<Option<f64>>, String> {
+    let ipv6 = address.is_ipv6();
+    let (socket, raw) = echo_socket(ipv6)?;
+    let target = SockAddr::from(SocketAddr::new(address, 0));
+    let identifier = rand::random::<u16>();
+
+    let mut buffer = [0; 1500];
+    let mut round_trips = Vec::new();
+    for sequence in 0..count {
+        let start = Instant::now();
+        socket
+            .send_to(&echo_request(ipv6, identifier, sequence), &target)
+            .map_err(|err| err.to_string())?;
+
+        let mut round_trip = None;
+        while let Some(left) = ECHO_TIMEOUT
+            .checked_sub(start.elapsed())
+            .filter(|left| !left.is_zero())
+        {
+            socket
+                .set_read_timeout(Some(left))
+                .map_err(|err| err.to_string())?;
+            match (&socket).read(&mut buffer) {
+                Ok(read) if is_echo_reply(&buffer[..read], ipv6, raw, identifier, sequence) => {
+                    round_trip = Some(start.elapsed().as_secs_f64() * 1000.0);
+                    break;
+                }
+                Ok(_) => continue,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    break
+                }
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+        round_trips.push(round_trip);
+    }
+
+    Ok(round_trips)
+}
+
+/// Pings `host` with `count` ICMP echo requests. Every request counts as
+/// lost when the host can't be resolved or no socket can be opened
+pub async fn icmp(host: &str, count: u16) -> EchoStats {
+    let lost = EchoStats::of(&vec![None; count as usize]);
+    let address = match tokio::net::lookup_host((host, 0)).await {
+        Ok(mut addresses) => addresses.next().map(|address| address.ip()),
+        Err(err) => {
+            debug!("DNS lookup of {} failed: {}", host, err);
+            None
+        }
+    };
+    let Some(address) = address else {
+        return lost;
+    };
+
+    match tokio::task::spawn_blocking(move || echo(address, count)).await {
+        Ok(Ok(round_trips)) => EchoStats::of(&round_trips),
+        Ok(Err(err)) => {
+            warn!("ICMP probe of {} failed: {}", host, err);
+            lost
+        }
+        Err(err) => {
+            warn!("ICMP probe of {} panicked: {}", host, err);
+            lost
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An echo reply to `request` as the host sends it back
+    fn reply(request: &[u8], ipv6: bool) -> Vec<u8> {
+        let mut reply = request.to_vec();
+        reply[0] = if ipv6 { 129 } else { 0 };
+        reply
+    }
+
+    #[test]
+    fn checksum_matches_rfc_1071() {
+        assert_eq!(
+            checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+            0x220d
+        );
+        assert_eq!(checksum(&[0x00, 0x01, 0xf2]), !0xf201);
+    }
+
+    #[test]
+    fn echo_request_carries_its_identifier_and_sequence() {
+        let request = echo_request(false, 0x1234, 7);
+        assert_eq!(request[0], 8);
+        assert_eq!(&request[4..8], &[0x12, 0x34, 0, 7]);
+        assert_eq!(&request[8..], ECHO_PAYLOAD);
+        assert_eq!(checksum(&request), 0);
+
+        let request = echo_request(true, 0x1234, 7);
+        assert_eq!(request[0], 128);
+        assert_eq!(&request[2..4], &[0, 0]);
+    }
+
+    #[test]
+    fn is_echo_reply_matches_the_request() {
+        let request = echo_request(false, 0x1234, 7);
+        assert!(is_echo_reply(
+            &reply(&request, false),
+            false,
+            false,
+            0x1234,
+            7
+        ));
+        assert!(!is_echo_reply(&request, false, false, 0x1234, 7));
+        assert!(!is_echo_reply(
+            &reply(&request, false),
+            false,
+            false,
+            0x1234,
+            8
+        ));
+        assert!(!is_echo_reply(
+            &reply(&request, false)[..6],
+            false,
+            false,
+            0x1234,
+            7
+        ));
+
+        // the kernel replaces the identifier of datagram sockets
+        assert!(is_echo_reply(
+            &reply(&request, false),
+            false,
+            false,
+            0x4321,
+            7
+        ));
+
+        let mut raw = vec![0x45];
+        raw.resize(20, 0);
+        raw.extend(reply(&request, false));
+        assert!(is_echo_reply(&raw, false, true, 0x1234, 7));
+        assert!(!is_echo_reply(&raw, false, true, 0x4321, 7));
+        assert!(!is_echo_reply(&raw[..24], false, true, 0x1234, 7));
+
+        let request = echo_request(true, 0x1234, 7);
+        assert!(is_echo_reply(&reply(&request, true), true, true, 0x1234, 7));
+        assert!(!is_echo_reply(
+            &reply(&request, false),
+            true,
+            true,
+            0x1234,
+            7
+        ));
+    }
+
+    #[test]
+    fn echo_stats_of_round_trips() {
+        let stats = EchoStats::of(&[Some(10.0), None, Some(30.0), Some(20.0)]);
+        assert_eq!(
+            stats,
+            EchoStats {
+                sent: 4,
+                received: 3,
+                min_ms: 10.0,
+                avg_ms: 20.0,
+                max_ms: 30.0,
+                jitter_ms: 15.0,
+            }
+        );
+        assert_eq!(stats.loss_percent(), 25.0);
+
+        assert_eq!(EchoStats::of(&[Some(5.0)]).jitter_ms, 0.0);
+
+        let lost = EchoStats::of(&[None, None]);
+        assert_eq!((lost.sent, lost.received, lost.max_ms), (2, 0, 0.0));
+        assert_eq!(lost.loss_percent(), 100.0);
+    }
+
+    #[test]
+    fn echo_stats_merge_averages_the_answered_checks() {
+        assert_eq!(EchoStats::merge(&[]), None);
+
+        let merged = EchoStats::merge(&[
+            EchoStats::of(&[Some(10.0), Some(20.0)]),
+            EchoStats::of(&[None, None]),
+            EchoStats::of(&[Some(30.0), None]),
+        ])
+        .unwrap();
+        assert_eq!(
+            merged,
+            EchoStats {
+                sent: 6,
+                received: 3,
+                min_ms: 20.0,
+                avg_ms: 22.5,
+                max_ms: 25.0,
+                jitter_ms: 5.0,
+            }
+        );
+        assert_eq!(merged.loss_percent(), 50.0);
+    }
+
+    #[rocket::async_test]
+    async fn http_skips_interim_responses_of_ipv6_hosts() {
+        let listener = match tokio::net::TcpListener::bind("[::1]:0").await {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("skipped: {}", err);
+                return;
+            }
+        };
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let _ = stream.read(&mut request).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n")
+                .await;
+        });
+
+        let probe = http(&format!("http://[::1]:{}/", port)).await;
+        assert_eq!(probe.status, Some(204));
+    }
+
+    #[rocket::async_test]
+    async fn icmp_pings_localhost() {
+        if let Err(err) = echo_socket(false) {
+            eprintln!("skipped: {}", err);
+            return;
+        }
+
+        let stats = icmp("127.0.0.1", 3).await;
+        assert_eq!((stats.sent, stats.received), (3, 3));
+        assert!(stats.min_ms <= stats.avg_ms && stats.avg_ms <= stats.max_ms);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::{
+    database::{DatabaseModel, Monitor, MonitorPing, ResponseStats},
+    group::Group,
+    incident::Incident,
+    notification::{Email, EmailAttachment, NotifyConfig},
+    rollup::DailyUptime,
+    templates::ReportTemplate,
+    time::{DateOffset, PrettyPrint},
+};
+use askama_rocket::Template;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use rocket::{FromForm, FromFormField};
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+
+const DAY_FORMAT: &str = "%Y-%m-%d";
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum ReportFormat {
+    #[field(value = "html")]
+    Html,
+    #[field(value = "csv")]
+    Csv,
+    #[field(value = "json")]
+    Json,
+}
+
+impl ReportFormat {
+    pub fn all() -> [ReportFormat; 3] {
+        [ReportFormat::Html, ReportFormat::Csv, ReportFormat::Json]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "text/html",
+            ReportFormat::Csv => "text/csv",
+            ReportFormat::Json => "application/json",
+        }
+    }
+}
+
+/// Days are `YYYY-MM-DD` and both included, defaults to the last month
+#[derive(Debug, Clone, Default, FromForm)]
+pub struct ReportQuery {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub group_id: Option<i64>,
+    pub format: Option<ReportFormat>,
+}
+
+impl ReportQuery {
+    pub fn days(&self) -> Result<(NaiveDate, NaiveDate), String> {
+        let parse = |day: &str| {
+            NaiveDate::parse_from_str(day, DAY_FORMAT).map_err(|_| format!("Invalid day {}", day))
+        };
+        let (default_start, default_end) = ReportSchedule::Monthly.previous_period(Local::now());
+        let start = match self.start.as_deref().filter(|day| !day.is_empty()) {
+            Some(day) => parse(day)?,
+            None => default_start,
+        };
+        let end = match self.end.as_deref().filter(|day| !day.is_empty()) {
+            Some(day) => parse(day)?,
+            None => default_end,
+        };
+        if end < start {
+            return Err("The report ends before it starts".to_string());
+        }
+
+        Ok((start, end))
+    }
+}
+
+/// Availability of one monitor over the report
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorReport {
+    pub monitor_id: i64,
+    pub name: String,
+    pub group: Option<String>,
+    /// Percentage of pings outside maintenance that succeeded
+    pub uptime: Option<f64>,
+    pub downtime_minutes: i64,
+    pub incidents: Vec<Incident>,
+    /// Mean time to recovery of the resolved incidents
+    pub mttr_minutes: Option<f64>,
+    pub response: Option<ResponseStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub start: String,
+    pub end: String,
+    pub group: Option<String>,
+    pub generated_at: String,
+    pub monitors: Vec<MonitorReport>,
+}
+
+fn local_midnight(day: NaiveDate) -> DateTime<Local> {
+    day.and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap_or_else(Local::now)
+}
+
+fn minutes_between(start: &str, end: &str) -> Option<f64> {
+    let start = NaiveDateTime::parse_from_str(start, TIMESTAMP_FORMAT).ok()?;
+    let end = NaiveDateTime::parse_from_str(end, TIMESTAMP_FORMAT).ok()?;
+
+    Some((end - start).num_seconds() as f64 / 60.0)
+}
+
+/// Quotes a field when it contains a separator, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Report {
+    /// Uptime and downtime come from the daily rollups, response times and
+    /// incidents from the pings of the range
+    pub async fn generate(
+        start: NaiveDate,
+        end: NaiveDate,
+        group_id: Option<i64>,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Self, sqlx::Error> {
+        let groups = Group::all(pool).await?;
+        let paths = Group::paths(&groups);
+        let path = |id: i64| {
+            paths
+                .iter()
+                .find(|(group_id, _)| *group_id == id)
+                .map(|(_, path)| path.clone())
+        };
+        let subtree = group_id.map(|id| Group::subtree(id, &groups));
+
+        let offset = DateOffset {
+            start: local_midnight(start),
+            end: local_midnight(end + Duration::days(1)),
+        };
+        let (since, until) = offset.pretty_strings();
+        let first_day = start.format(DAY_FORMAT).to_string();
+        let last_day = end.format(DAY_FORMAT).to_string();
+        let incidents = Incident::since(Some(&since), pool).await?;
+
+        let mut monitors = Vec::new();
+        for monitor in Monitor::all(pool).await? {
+            if let Some(subtree) = subtree.as_ref() {
+                if !monitor.group_id.is_some_and(|id| subtree.contains(&id)) {
+                    continue;
+                }
+            }
+
+            let days: Vec<DailyUptime> = DailyUptime::for_monitor(&monitor, &first_day, pool)
+                .await?
+                .into_iter()
+                .filter(|day| day.day <= last_day)
+                .collect();
+            let pings: i64 = days.iter().map(|day| day.pings).sum();
+            let bad_pings: i64 = days.iter().map(|day| day.bad_pings).sum();
+
+            let mut monitor_incidents: Vec<Incident> = incidents
+                .iter()
+                .filter(|incident| incident.monitor_id == monitor.id && incident.started_at < until)
+                .cloned()
+                .collect();
+            monitor_incidents.reverse();
+            let recoveries: Vec<f64> = monitor_incidents
+                .iter()
+                .filter_map(|incident| {
+                    minutes_between(&incident.started_at, incident.resolved_at.as_deref()?)
+                })
+                .collect();
+
+            let response_pings = MonitorPing::between(pool, monitor.id, offset.clone()).await?;
+
+            monitors.push(MonitorReport {
+                monitor_id: monitor.id,
+                group: monitor.group_id.and_then(path),
+                name: monitor.name,
+                uptime: (pings > 0).then(|| (pings - bad_pings) as f64 * 100.0 / pings as f64),
+                downtime_minutes: days.iter().map(|day| day.downtime_seconds).sum::<i64>() / 60,
+                incidents: monitor_incidents,
+                mttr_minutes: (!recoveries.is_empty())
+                    .then(|| recoveries.iter().sum::<f64>() / recoveries.len() as f64),
+                response: ResponseStats::of(&response_pings),
+            });
+        }
+
+        Ok(Report {
+            start: first_day,
+            end: last_day,
+            group: group_id.and_then(path),
+            generated_at: Local::now().pretty_string(),
+            monitors,
+        })
+    }
+
+    pub fn filename(&self, format: ReportFormat) -> String {
+        format!(
+            "uptime-report-{}-{}.{}",
+            self.start,
+            self.end,
+            format.as_str()
+        )
+    }
+
+    /// One row per monitor, incidents are only counted
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "monitor_id,name,group,uptime_percent,downtime_minutes,incidents,mttr_minutes,\
+             p50_ms,p90_ms,p95_ms,p99_ms,average_ms\n",
+        );
+        let number = |value: Option<f64>| value.map(|value| format!("{:.3}", value));
+
+        for monitor in self.monitors.iter() {
+            let response = monitor.response.as_ref();
+            let fields = [
+                monitor.monitor_id.to_string(),
+                csv_field(&monitor.name),
+                csv_field(monitor.group.as_deref().unwrap_or_default()),
+                number(monitor.uptime).unwrap_or_default(),
+                monitor.downtime_minutes.to_string(),
+                monitor.incidents.len().to_string(),
+                number(monitor.mttr_minutes).unwrap_or_default(),
+                response.map(|r| r.p50_ms.to_string()).unwrap_or_default(),
+                response.map(|r| r.p90_ms.to_string()).unwrap_or_default(),
+                response.map(|r| r.p95_ms.to_string()).unwrap_or_default(),
+                response.map(|r| r.p99_ms.to_string()).unwrap_or_default(),
+                response
+                    .map(|r| r.average_ms.to_string())
+                    .unwrap_or_default(),
+            ];
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Result<String, String> {
+        match format {
+            ReportFormat::Html => ReportTemplate {
+                report: self.clone(),
+            }
+            .render()
+            .map_err(|err| err.to_string()),
+            ReportFormat::Csv => Ok(self.to_csv()),
+            ReportFormat::Json => serde_json::to_string_pretty(self).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportSchedule {
+    Weekly,
+    Monthly,
+}
+
+impl ReportSchedule {
+    /// The last finished week, monday to sunday, or month before `now`
+    pub fn previous_period(&self, now: DateTime<Local>) -> (NaiveDate, NaiveDate) {
+        let today = now.date_naive();
+        match self {
+            ReportSchedule::Weekly => {
+                let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                (monday - Duration::days(7), monday - Duration::days(1))
+            }
+            ReportSchedule::Monthly => {
+                let first = today.with_day(1).unwrap_or(today);
+                let last = first - Duration::days(1);
+                (last.with_day(1).unwrap_or(last), last)
+            }
+        }
+    }
+
+    /// Start of the next period, when its previous one is reported
+    fn next_run(&self, now: DateTime<Local>) -> NaiveDateTime {
+        let today = now.date_naive();
+        let day = match self {
+            ReportSchedule::Weekly => {
+                today + Duration::days(7 - today.weekday().num_days_from_monday() as i64)
+            }
+            ReportSchedule::Monthly => {
+                let first = today.with_day(1).unwrap_or(today);
+                (first + Duration::days(32)).with_day(1).unwrap_or(first)
+            }
+        };
+
+        day.and_time(NaiveTime::MIN)
+    }
+}
+
+/// Read from `REPORT_SCHEDULE` (`weekly` or `monthly`), `REPORT_EMAIL_TO`
+/// (comma separated) and `REPORT_FORMAT` (default `html`), reports are only
+/// emailed when a schedule and recipients are set
+#[derive(Debug, Clone)]
+pub struct ReportConfig {
+    pub schedule: Option<ReportSchedule>,
+    pub to: Vec<String>,
+    pub format: ReportFormat,
+}
+
+impl ReportConfig {
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+
+        ReportConfig {
+            schedule: match var("REPORT_SCHEDULE").as_deref() {
+                Some("weekly") => Some(ReportSchedule::Weekly),
+                Some("monthly") => Some(ReportSchedule::Monthly),
+                _ => None,
+            },
+            to: var("REPORT_EMAIL_TO")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|to| !to.is_empty())
+                .map(str::to_string)
+                .collect(),
+            format: match var("REPORT_FORMAT").as_deref() {
+                Some("csv") => ReportFormat::Csv,
+                Some("json") => ReportFormat::Json,
+                _ => ReportFormat::Html,
+            },
+        }
+    }
+}
+
+/// Emails the report of the previous period to the configured recipients
+pub async fn send(
+    schedule: ReportSchedule,
+    config: &ReportConfig,
+    notify: &NotifyConfig,
+    pool: &Pool<Sqlite>,
+) -> Result<(), String> {
+    let (start, end) = schedule.previous_period(Local::now());
+    let report = Report::generate(start, end, None, pool)
+        .await
+        .map_err(|err| err.to_string())?;
+    let content = report.render(config.format)?;
+
+    let text = format!(
+        "Uptime report for {} to {}, {} monitors.",
+        report.start,
+        report.end,
+        report.monitors.len()
+    );
+    let email = Email {
+        to: config.to.clone(),
+        subject: format!("Uptime report {} to {}", report.start, report.end),
+        html: (config.format == ReportFormat::Html).then(|| content.clone()),
+        attachment: Some(EmailAttachment {
+            filename: report.filename(config.format),
+            content_type: config.format.mime_type().to_string(),
+            content,
+        }),
+        text,
+    };
+
+    email.send(notify).await
+}
+
+pub fn schedule(config: ReportConfig, notify: NotifyConfig, pool: Pool<Sqlite>) {
+    let Some(schedule) = config.schedule else {
+        return;
+    };
+    if config.to.is_empty() {
+        warn!("REPORT_SCHEDULE is set without REPORT_EMAIL_TO, no reports will be sent");
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let now = Local::now();
+            let wait = (schedule.next_run(now) - now.naive_local())
+                .to_std()
+                .unwrap_or_default();
+            // a little past midnight so the last day is rolled up
+            tokio::time::sleep(wait + std::time::Duration::from_secs(5 * 60)).await;
+
+            if let Err(err) = send(schedule, &config, &notify, &pool).await {
+                warn!("Failed to send the scheduled report: {}", err);
+            }
+        }
+    });
+}
@@ -0,0 +1,310 @@
+use crate::{
+    database::{Monitor, MonitorPing},
+    utils, DatabaseModel,
+};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveTime};
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+/// Weeks shown by the uptime calendar
+const CALENDAR_WEEKS: i64 = 53;
+
+/// Uptime of one monitor over one local day. Finished days are rolled up
+/// from the pings once so long ranges don't have to read every ping
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyUptime {
+    pub monitor_id: i64,
+    pub day: String,
+    /// Pings outside maintenance
+    pub pings: i64,
+    pub bad_pings: i64,
+    /// Every failed ping counts as one interval of downtime
+    pub downtime_seconds: i64,
+    /// Incidents that started on this day, counted like `Incident`
+    pub incidents: i64,
+}
+
+/// A cell of the uptime calendar
+#[derive(Debug, Clone)]
+pub struct CalendarDay {
+    /// 0 without pings, 1 to 4 from the worst to the best uptime
+    pub level: u8,
+    pub title: String,
+}
+
+/// A column of the uptime calendar, monday to sunday
+#[derive(Debug, Clone)]
+pub struct CalendarWeek {
+    /// Set on the first week of a month
+    pub month: Option<String>,
+    pub days: Vec<CalendarDay>,
+}
+
+impl DailyUptime {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/monitor_daily.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    /// Uptime percentage, None for a day without counted pings
+    pub fn uptime(&self) -> Option<f64> {
+        if self.pings == 0 {
+            return None;
+        }
+
+        Some((self.pings - self.bad_pings) as f64 * 100.0 / self.pings as f64)
+    }
+
+    /// Rolls up `pings` of a single day, oldest first. `previous_bad` tells
+    /// whether an incident was already running when the day started
+    fn of(monitor: &Monitor, day: &str, pings: &[MonitorPing], previous_bad: bool) -> Self {
+        let mut rollup = DailyUptime {
+            monitor_id: monitor.id,
+            day: day.to_string(),
+            pings: 0,
+            bad_pings: 0,
+            downtime_seconds: 0,
+            incidents: 0,
+        };
+        let mut previous_bad = previous_bad;
+
+        for ping in pings.iter() {
+            let incident = ping.bad && !ping.maintenance && !ping.unreachable;
+            if incident && !previous_bad {
+                rollup.incidents += 1;
+            }
+            previous_bad = incident;
+
+            if ping.maintenance {
+                continue;
+            }
+            rollup.pings += 1;
+            if ping.bad {
+                rollup.bad_pings += 1;
+                rollup.downtime_seconds += monitor.interval;
+            }
+        }
+
+        rollup
+    }
+
+    /// Whether the last ping before `before` belongs to an incident
+    async fn previous_bad(
+        monitor_id: i64,
+        before: &str,
+        pool: &Pool<Sqlite>,
+    ) -> Result<bool, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT bad, maintenance, unreachable FROM monitor_ping WHERE monitor_id = ? AND timestamp < ? ORDER BY timestamp DESC LIMIT 1
+            "#,
+            monitor_id,
+            before
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(query_result
+            .is_some_and(|ping| ping.bad != 0 && ping.maintenance == 0 && ping.unreachable == 0))
+    }
+
+    /// Rolls up every finished day that isn't stored yet
+    pub async fn rollup(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let today = Local::now().format(DAY_FORMAT).to_string();
+
+        for monitor in Monitor::all(pool).await? {
+            let last = sqlx::query!(
+                r#"
+                SELECT MAX(day) AS day FROM monitor_daily WHERE monitor_id = ?
+                "#,
+                monitor.id
+            )
+            .fetch_one(pool)
+            .await?
+            .day;
+
+            let since = match last {
+                Some(day) => match NaiveDate::parse_from_str(&day, DAY_FORMAT) {
+                    Ok(day) => (day + Duration::days(1)).format(DAY_FORMAT).to_string(),
+                    Err(_) => continue,
+                },
+                None => String::new(),
+            };
+            let mut previous_bad = DailyUptime::previous_bad(monitor.id, &since, pool).await?;
+            let pings = MonitorPing::since(pool, monitor.id, &since).await?;
+
+            let mut tx = pool.begin().await?;
+            for day_pings in pings.chunk_by(|a, b| a.timestamp[..10] == b.timestamp[..10]) {
+                let day = &day_pings[0].timestamp[..10];
+                if day >= today.as_str() {
+                    break;
+                }
+
+                let rollup = DailyUptime::of(&monitor, day, day_pings, previous_bad);
+                previous_bad = day_pings
+                    .last()
+                    .is_some_and(|ping| ping.bad && !ping.maintenance && !ping.unreachable);
+
+                sqlx::query!(
+                    r#"
+                    INSERT INTO monitor_daily (monitor_id, day, pings, bad_pings, downtime_seconds, incidents) VALUES (?, ?, ?, ?, ?, ?)
+                    ON CONFLICT(monitor_id, day) DO UPDATE SET pings = excluded.pings, bad_pings = excluded.bad_pings, downtime_seconds = excluded.downtime_seconds, incidents = excluded.incidents
+                    "#,
+                    rollup.monitor_id,
+                    rollup.day,
+                    rollup.pings,
+                    rollup.bad_pings,
+                    rollup.downtime_seconds,
+                    rollup.incidents
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Days of a monitor from `since` on, today is rolled up from its pings
+    /// on the fly
+    pub async fn for_monitor(
+        monitor: &Monitor,
+        since: &str,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM monitor_daily WHERE monitor_id = ? AND day >= ? ORDER BY day
+            "#,
+            monitor.id,
+            since
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut days: Vec<DailyUptime> = query_result
+            .into_iter()
+            .map(|rollup| DailyUptime {
+                monitor_id: rollup.monitor_id,
+                day: rollup.day,
+                pings: rollup.pings,
+                bad_pings: rollup.bad_pings,
+                downtime_seconds: rollup.downtime_seconds,
+                incidents: rollup.incidents,
+            })
+            .collect();
+
+        let today = Local::now().format(DAY_FORMAT).to_string();
+        let previous_bad = DailyUptime::previous_bad(monitor.id, &today, pool).await?;
+        let pings = MonitorPing::since(pool, monitor.id, &today).await?;
+        if !pings.is_empty() {
+            days.push(DailyUptime::of(monitor, &today, &pings, previous_bad));
+        }
+
+        Ok(days)
+    }
+
+    /// The last year of a monitor as weeks starting on monday, GitHub
+    /// contribution style
+    pub async fn calendar(
+        monitor: &Monitor,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Vec<CalendarWeek>, sqlx::Error> {
+        let today = Local::now().date_naive();
+        let start = today
+            - Duration::weeks(CALENDAR_WEEKS - 1)
+            - Duration::days(today.weekday().num_days_from_monday() as i64);
+
+        let days: HashMap<String, DailyUptime> =
+            DailyUptime::for_monitor(monitor, &start.format(DAY_FORMAT).to_string(), pool)
+                .await?
+                .into_iter()
+                .map(|rollup| (rollup.day.clone(), rollup))
+                .collect();
+
+        let mut weeks: Vec<CalendarWeek> = Vec::new();
+        let mut day = start;
+        while day <= today {
+            if day.weekday().num_days_from_monday() == 0 {
+                let first_of_month = (0..7).any(|offset| (day + Duration::days(offset)).day() == 1);
+                weeks.push(CalendarWeek {
+                    month: (first_of_month || weeks.is_empty())
+                        .then(|| (day + Duration::days(6)).format("%b").to_string()),
+                    days: Vec::new(),
+                });
+            }
+
+            let key = day.format(DAY_FORMAT).to_string();
+            let cell = match days.get(&key) {
+                Some(rollup) => rollup.calendar_day(),
+                None => CalendarDay {
+                    level: 0,
+                    title: format!("{}: no data", key),
+                },
+            };
+            if let Some(week) = weeks.last_mut() {
+                week.days.push(cell);
+            }
+
+            day += Duration::days(1);
+        }
+
+        Ok(weeks)
+    }
+
+    fn calendar_day(&self) -> CalendarDay {
+        let Some(uptime) = self.uptime() else {
+            return CalendarDay {
+                level: 0,
+                title: format!("{}: no data", self.day),
+            };
+        };
+
+        let level = if uptime >= 99.9 {
+            4
+        } else if uptime >= 99.0 {
+            3
+        } else if uptime >= 95.0 {
+            2
+        } else {
+            1
+        };
+        let incidents = match self.incidents {
+            1 => "1 incident".to_string(),
+            count => format!("{} incidents", count),
+        };
+
+        CalendarDay {
+            level,
+            title: format!(
+                "{}: {:.2}% uptime, {}, {} minutes down",
+                self.day,
+                uptime,
+                incidents,
+                self.downtime_seconds / 60
+            ),
+        }
+    }
+}
+
+/// Rolls up finished days now and after every midnight
+pub fn schedule(pool: Pool<Sqlite>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = DailyUptime::rollup(&pool).await {
+                warn!("Daily uptime rollup failed: {}", err);
+            }
+
+            let now = Local::now().naive_local();
+            let midnight = (now.date() + Duration::days(1)).and_time(NaiveTime::MIN);
+            let wait = (midnight - now).to_std().unwrap_or_default();
+            tokio::time::sleep(wait + std::time::Duration::from_secs(60)).await;
+        }
+    });
+}
@@ -1,52 +1,194 @@
 use crate::{
-    database::{self, DatabaseModel},
+    api_token::{ApiToken, CreateApiTokenForm, Scope},
+    audit::{Actor, AuditEntry, AuditFilter},
+    auth::{self, Admin, Editor, RoleForm, SessionUser, User, UserInvite, Viewer},
+    backup::{self, BackupConfig},
+    database::{self, DatabaseModel, MonitorFilter, Setting},
+    dependency::Dependency,
+    export::{self, Export, ImportForm, ImportMode, ImportSummary},
+    flapping::Flap,
+    group::{CreateGroupForm, Group},
+    incident::Incident,
+    maintenance::{MaintenanceForm, MaintenanceWindow, ScheduledWindow},
+    monitors_file,
+    oidc::{Oidc, Sso},
     ping::{self, PingerManager},
+    report::{Report, ReportFormat, ReportQuery, ReportSchedule},
+    rollup::DailyUptime,
+    slo::{self, Slo, SloForm, SloStatus},
+    tag::{self, MonitorTag, Tag, TagColorForm},
     templates::*,
-    time::DateOffset,
-    utils::{self, template_response, TemplateResponse},
+    time::{GraphRange, PrettyPrint, RangePreset},
+    totp::{self, PendingLogins, RecoveryCode},
+    uptime_kuma::{self, KumaBackup, KumaImportForm},
+    utils::{self, json_response, template_response, TemplateResponse},
 };
 use askama_rocket::Template;
 use rocket::{
     form::{Contextual, Form},
-    http::Status,
-    State,
+    fs::TempFile,
+    http::{ContentType, CookieJar, Status},
+    response::{
+        stream::{Event, EventStream},
+        Redirect,
+    },
+    serde::json::Json,
+    tokio::{select, sync::broadcast::error::RecvError},
+    Either, Shutdown, State,
 };
 use sqlx::{Pool, Sqlite};
-use uptime_rs::{AppError, CreateMonitor, RedirectResponder, RedirectResult, TemplateResult};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use tokio::io::AsyncReadExt;
+use uptime_rs::{
+    AppError, CreateMonitor, CreateUserForm, DownloadResponder, JsonResult, LoginForm,
+    RedirectResponder, RedirectResult, TemplateResult, ToggleSettingForm, TotpCodeForm,
+};
 use utils::{serde_response, JsonResponse};
 
 //
 // monitor_list.html
 //
-pub async fn get_monitor_list_items(
-    pool: &State<Pool<Sqlite>>,
-) -> Result<Vec<MonitorListItem>, sqlx::Error> {
-    let monitors = database::Monitor::all(&pool).await?;
-    let mut monitor_list_items: Vec<MonitorListItem> = Vec::new();
+/// Nests the filtered monitors into their groups, groups without any
+/// matching monitor are left out
+fn group_tree(
+    parent_id: Option<i64>,
+    children: &HashMap<Option<i64>, Vec<Group>>,
+    items: &mut HashMap<Option<i64>, Vec<MonitorListItem>>,
+    visited: &mut HashSet<i64>,
+) -> Vec<MonitorGroupItem> {
+    let mut tree = Vec::new();
+
+    for group in children.get(&parent_id).into_iter().flatten() {
+        if !visited.insert(group.id) {
+            continue;
+        }
+
+        let groups = group_tree(Some(group.id), children, items, visited);
+        let monitors = items.remove(&Some(group.id)).unwrap_or_default();
+        if groups.is_empty() && monitors.is_empty() {
+            continue;
+        }
+
+        let state = groups
+            .iter()
+            .map(|group| group.state)
+            .chain(monitors.iter().map(|item| item.state))
+            .max()
+            .unwrap_or(database::MonitorState::Unknown);
+
+        tree.push(MonitorGroupItem {
+            group: group.clone(),
+            state,
+            groups,
+            items: monitors,
+        });
+    }
+
+    tree
+}
+
+pub async fn get_monitor_list(
+    pool: &Pool<Sqlite>,
+    filter: &MonitorFilter,
+) -> Result<MonitorListItemsTemplate, sqlx::Error> {
+    let monitors = database::Monitor::all(pool).await?;
+    let mut tags: HashMap<i64, Vec<MonitorTag>> = HashMap::new();
+    for tag in MonitorTag::all(pool).await? {
+        tags.entry(tag.monitor_id).or_default().push(tag);
+    }
+    let flapping_ids = Flap::flapping(pool).await?;
+
+    let mut items: HashMap<Option<i64>, Vec<MonitorListItem>> = HashMap::new();
+    for monitor in monitors.into_iter() {
+        let monitor_tags = tags.remove(&monitor.id).unwrap_or_default();
+        if !filter.matches(&monitor, &monitor_tags) {
+            continue;
+        }
 
-    for monitor in monitors.iter() {
-        let uptime_percentage = monitor.get_uptime_percentage(&pool).await;
-        let pings = database::MonitorPing::last_n(&pool, monitor.id, 1).await;
+        let uptime_percentage = monitor.get_uptime_percentage(pool).await;
+        let pings = database::MonitorPing::last_n(pool, monitor.id, 1).await;
         let up = match pings.first() {
             Some(ping) => !ping.bad && ping.status.code <= 400,
             None => false,
         };
+        let degraded = pings.first().is_some_and(|ping| ping.degraded);
+        let flapping = flapping_ids.contains(&monitor.id);
+
+        items
+            .entry(monitor.group_id)
+            .or_default()
+            .push(MonitorListItem {
+                state: database::MonitorState::of(&monitor, pings.first()),
+                monitor,
+                uptime_percentage,
+                up,
+                degraded,
+                flapping,
+                tags: monitor_tags,
+            });
+    }
+
+    let mut children: HashMap<Option<i64>, Vec<Group>> = HashMap::new();
+    for group in Group::all(pool).await? {
+        children.entry(group.parent_id).or_default().push(group);
+    }
+
+    let groups = group_tree(None, &children, &mut items, &mut HashSet::new());
+    // whatever is left belongs to a deleted or unreachable group
+    let mut ungrouped: Vec<MonitorListItem> = items.into_values().flatten().collect();
+    ungrouped.sort_by_key(|item| item.monitor.id);
+
+    Ok(MonitorListItemsTemplate {
+        groups,
+        items: ungrouped,
+    })
+}
+
+pub async fn get_monitor_list_component(
+    pool: &Pool<Sqlite>,
+    filter: &MonitorFilter,
+) -> Result<MonitorListComponentTemplate, sqlx::Error> {
+    let mut chips: Vec<TagChip> = Vec::new();
+    for tag in MonitorTag::all(pool).await? {
+        let key = tag.key();
+        if chips.iter().any(|chip| chip.key == key) {
+            continue;
+        }
 
-        monitor_list_items.push(MonitorListItem {
-            monitor: monitor.clone(),
-            uptime_percentage,
-            up,
+        chips.push(TagChip {
+            selected: filter.tag.contains(&key),
+            key,
+            color: tag.color,
         });
     }
 
-    Ok(monitor_list_items)
+    Ok(MonitorListComponentTemplate {
+        chips,
+        search: filter.search.clone().unwrap_or_default(),
+        list: get_monitor_list(pool, filter).await?,
+    })
 }
 
-#[get("/")]
-pub async fn monitor_list<'a>(pool: &State<Pool<Sqlite>>) -> TemplateResult {
-    let view = MonitorListComponentTemplate {
-        items: get_monitor_list_items(pool).await?,
-    };
+#[get("/?<filter..>")]
+pub async fn monitor_list<'a>(
+    pool: &State<Pool<Sqlite>>,
+    filter: MonitorFilter,
+    _user: Viewer,
+) -> TemplateResult {
+    let view = get_monitor_list_component(pool, &filter).await?;
+
+    Ok(template_response(Status::Ok, view))
+}
+
+/// Only the list below the search box, swapped in while filtering
+#[get("/items?<filter..>")]
+pub async fn monitor_list_items<'a>(
+    pool: &State<Pool<Sqlite>>,
+    filter: MonitorFilter,
+    _user: Viewer,
+) -> TemplateResult {
+    let view = get_monitor_list(pool, &filter).await?;
 
     Ok(template_response(Status::Ok, view))
 }
@@ -54,13 +196,48 @@ pub async fn monitor_list<'a>(pool: &State<Pool<Sqlite>>) -> TemplateResult {
 //
 // uptime_graph.html
 //
-#[get("/<id>/uptime-graph")]
-pub async fn uptime_graph<'a>(pool: &State<Pool<Sqlite>>, id: i64) -> TemplateResult {
-    let uptime_data = database::MonitorPing::last_n(pool, id, 30).await;
+/// Most points drawn in the graph, longer ranges are downsampled to this
+const GRAPH_POINTS: usize = 120;
+
+async fn uptime_graph_template(
+    monitor: database::Monitor,
+    range: GraphRange,
+    pool: &Pool<Sqlite>,
+) -> Result<UptimeGraphTemplate, sqlx::Error> {
+    // stats are taken before downsampling, which averages the tail away
+    let pings = database::MonitorPing::between(pool, monitor.id, range.offset()).await?;
+    let stats = database::ResponseStats::of(&pings);
+
+    Ok(UptimeGraphTemplate {
+        uptime_graph: Some(database::MonitorPing::downsample(pings, GRAPH_POINTS)),
+        monitor,
+        range,
+        stats,
+    })
+}
+
+#[get("/<id>/uptime-graph?<range..>")]
+pub async fn uptime_graph<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    range: GraphRange,
+    _user: Viewer,
+) -> TemplateResult {
+    let monitor = database::Monitor::by_id(id, pool).await?;
+    let view = uptime_graph_template(monitor, range, pool).await?;
+
+    Ok(template_response(Status::Ok, view))
+}
 
-    let view = UptimeGraphTemplate {
-        uptime_graph: Some(uptime_data),
-        monitor: database::Monitor::by_id(id, pool).await?,
+#[get("/<id>/calendar")]
+pub async fn uptime_calendar<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    _user: Viewer,
+) -> TemplateResult {
+    let monitor = database::Monitor::by_id(id, pool).await?;
+    let view = UptimeCalendarTemplate {
+        weeks: DailyUptime::calendar(&monitor, pool).await?,
     };
 
     Ok(template_response(Status::Ok, view))
@@ -69,16 +246,21 @@ pub async fn uptime_graph<'a>(pool: &State<Pool<Sqlite>>, id: i64) -> TemplateRe
 //
 // index.html
 //
-#[get("/")]
-pub async fn index<'a>(pool: &State<Pool<Sqlite>>) -> TemplateResult {
+#[get("/?<filter..>")]
+pub async fn index<'a>(
+    pool: &State<Pool<Sqlite>>,
+    filter: MonitorFilter,
+    user: Viewer,
+) -> TemplateResult {
     let monitors = database::Monitor::all(&pool).await?;
+    let slos = slo_statuses(&monitors, pool).await?;
 
     let view = IndexTemplate {
         title: "world",
+        user: user.0,
         monitors,
-        monitor_list_view: MonitorListComponentTemplate {
-            items: get_monitor_list_items(pool).await?,
-        },
+        monitor_list_view: get_monitor_list_component(pool, &filter).await?,
+        slos,
     };
 
     Ok(template_response(Status::Ok, view))
@@ -88,57 +270,122 @@ pub async fn index<'a>(pool: &State<Pool<Sqlite>>) -> TemplateResult {
 // create_monitor.html
 //
 #[get("/create")]
-pub async fn create_monitor_view<'a>() -> TemplateResponse<'a> {
-    let view = CreateMonitorViewTemplate { title: "world" };
+pub async fn create_monitor_view<'a>(pool: &State<Pool<Sqlite>>, _user: Editor) -> TemplateResult {
+    let view = CreateMonitorViewTemplate {
+        title: "world",
+        groups: Group::paths(&Group::all(pool).await?),
+        monitors: database::Monitor::all(pool).await?,
+        protocols: ping::Protocol::all(),
+    };
 
-    template_response(Status::Ok, view)
+    Ok(template_response(Status::Ok, view))
 }
 
 //
 // monitor_status_badge.html
 //
 #[get("/<id>/status-badge")]
-pub async fn monitor_status_badge<'a>(pool: &State<Pool<Sqlite>>, id: i64) -> TemplateResult {
+pub async fn monitor_status_badge<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    _user: Viewer,
+) -> TemplateResult {
     let monitor = database::Monitor::by_id(id, pool).await?;
     let pings = database::MonitorPing::last_n(pool, id, 1).await;
     let up = match pings.first() {
         Some(ping) => !ping.bad && ping.status.code <= 400,
         None => false,
     };
+    let degraded = pings.first().is_some_and(|ping| ping.degraded);
 
     let uptime_percentage = monitor.get_uptime_percentage(pool).await;
     let view = MonitorStatusBadgeTemplate {
         monitor,
         up,
+        degraded,
         uptime_percentage,
     };
 
     Ok(template_response(Status::Ok, view))
 }
 
+//
+// live updates
+//
+/// Streams every ping and state change as server-sent events, only those of
+/// one monitor when `monitor_id` is set. Clients that fall behind skip what
+/// they missed
+#[get("/?<monitor_id>")]
+pub fn live_events(
+    manager: &State<PingerManager>,
+    monitor_id: Option<i64>,
+    mut shutdown: Shutdown,
+    _user: Viewer,
+) -> EventStream![] {
+    let mut receiver = manager.events.subscribe();
+
+    EventStream! {
+        loop {
+            let event = select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            if monitor_id.is_some_and(|id| id != event.monitor_id()) {
+                continue;
+            }
+
+            yield Event::json(&event).event(event.name());
+        }
+    }
+}
+
 //
 // monitor.html
 //
-#[get("/<id>")]
-pub async fn monitor_view<'a>(pool: &State<Pool<Sqlite>>, id: i64) -> TemplateResult {
+#[get("/<id>?<range..>")]
+pub async fn monitor_view<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    range: Option<GraphRange>,
+    user: Viewer,
+) -> TemplateResult {
+    // an Option so redirects can leave the range out with `_`
+    let range = range.unwrap_or_default();
     let monitor = database::Monitor::by_id(id, &pool).await?;
-    // let uptime_data = database::MonitorPing::last_n(pool, id, 30).await;
-    let offset = DateOffset::new(chrono::Duration::days(2));
-    dbg!(&offset.normalize().pretty_strings());
-    let uptime_data = database::MonitorPing::between(pool, id, offset, 50).await?;
-
-    let uptime_graph = UptimeGraphTemplate {
-        uptime_graph: Some(uptime_data),
-        monitor: database::Monitor::by_id(id, pool).await?,
-    };
+    let created_by = User::username_by_id(monitor.created_by, pool).await;
+    let updated_by = User::username_by_id(monitor.updated_by, pool).await;
+    let windows = MaintenanceWindow::for_monitor(id, pool).await?;
+
+    let dependencies = Dependency::all(pool).await?;
+    let related = Dependency::related(id, &dependencies);
+    let mut graph_monitors = Vec::new();
+    for other in database::Monitor::all(pool).await? {
+        if related.contains(&other.id) {
+            let pings = database::MonitorPing::last_n(pool, other.id, 1).await;
+            let state = database::MonitorState::of(&other, pings.first());
+            graph_monitors.push((other, state));
+        }
+    }
+
+    let uptime_graph = uptime_graph_template(monitor.clone(), range.clone(), pool).await?;
 
     let view = MonitorViewTemplate {
         title: "Monitor",
+        user: user.0,
         monitor,
-        monitor_list_view: MonitorListComponentTemplate {
-            items: get_monitor_list_items(pool).await?,
-        },
+        created_by,
+        updated_by,
+        monitor_list_view: get_monitor_list_component(pool, &MonitorFilter::default()).await?,
         uptime_graph,
+        range,
+        presets: RangePreset::all(),
+        maintenance: MaintenanceWindow::upcoming(&windows),
+        upstream: Dependency::graph(id, true, &graph_monitors, &dependencies),
+        downstream: Dependency::graph(id, false, &graph_monitors, &dependencies),
     };
 
     Ok(template_response(Status::Ok, view))
@@ -149,12 +396,15 @@ pub async fn pause_monitor(
     pool: &State<Pool<Sqlite>>,
     id: i64,
     pinger_manager: &State<PingerManager>,
+    user: Editor,
+    ip: Option<IpAddr>,
 ) -> RedirectResult {
-    database::Monitor::toggle_paused(id, &pool, pinger_manager).await?;
+    let actor = Actor::new(&user.0, ip);
+    change_paused(id, None, &actor, pool, pinger_manager).await?;
 
     Ok(RedirectResponder {
         content: "ok".into(),
-        redirect_uri: Some(uri!("/monitor", monitor_view(id))),
+        redirect_uri: Some(uri!("/monitor", monitor_view(id, _))),
     })
 }
 
@@ -163,6 +413,7 @@ pub async fn last_pings<'a>(
     pool: &State<Pool<Sqlite>>,
     monitor_id: i64,
     amount: i64,
+    _user: Viewer,
 ) -> JsonResponse<'a> {
     let pings = database::MonitorPing::last_n(&pool, monitor_id, amount).await;
 
@@ -170,42 +421,101 @@ pub async fn last_pings<'a>(
 }
 
 #[get("/<id>/edit")]
-pub async fn edit_monitor_view<'a>(pool: &State<Pool<Sqlite>>, id: i64) -> TemplateResult {
+pub async fn edit_monitor_view<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    _user: Editor,
+) -> TemplateResult {
     let monitor = database::Monitor::by_id(id, &pool).await?;
-    let view = EditMonitorView { monitor };
+    ensure_unmanaged(&monitor)?;
+    let view = edit_monitor_template(monitor, pool).await?;
 
     Ok(template_response(Status::Ok, view))
 }
 
+async fn edit_monitor_template(
+    monitor: database::Monitor,
+    pool: &Pool<Sqlite>,
+) -> Result<EditMonitorView, sqlx::Error> {
+    let tags = MonitorTag::by_monitor(monitor.id, pool)
+        .await?
+        .iter()
+        .map(MonitorTag::key)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let monitors = database::Monitor::all(pool)
+        .await?
+        .into_iter()
+        .filter(|other| other.id != monitor.id)
+        .collect();
+
+    Ok(EditMonitorView {
+        parents: Dependency::parents(monitor.id, pool).await?,
+        groups: Group::paths(&Group::all(pool).await?),
+        monitor,
+        tags,
+        monitors,
+        protocols: ping::Protocol::all(),
+    })
+}
+
 #[put("/<id>", data = "<form>")]
 pub async fn update_monitor<'a>(
     id: i64,
     pool: &State<Pool<Sqlite>>,
     pinger_manager: &State<PingerManager>,
     form: Form<Contextual<'a, CreateMonitor>>,
+    user: Editor,
+    ip: Option<IpAddr>,
 ) -> RedirectResult {
     match form.value {
         Some(ref data) => {
+            let existing = database::Monitor::by_id(id, &pool).await?;
+            ensure_unmanaged(&existing)?;
+            let protocol = form_protocol(data)?;
+            set_parents(id, &data.parents, pool).await?;
             let monitor = database::Monitor {
                 interval: data.interval,
-                protocol: ping::Protocol::HTTP,
+                protocol,
                 id,
                 name: data.name.clone(),
                 ip: data.ip.clone(),
                 port: data.port,
-                paused: database::Monitor::is_paused(id, &pool).await,
+                paused: existing.paused,
+                created_by: existing.created_by,
+                updated_by: Some(user.0.id),
+                managed_key: None,
+                group_id: data.group_id,
+                degraded_threshold_ms: data.degraded_threshold_ms,
+                ws_message: non_empty(&data.ws_message),
+                ws_expect: non_empty(&data.ws_expect),
+                ws_timeout_ms: data.ws_timeout_ms,
+                icmp_count: data.icmp_count,
+                icmp_max_loss: data.icmp_max_loss,
             };
 
             let db_result = monitor.update(&pool).await?;
             pinger_manager.update_pinger(db_result.clone()).await?;
+            let tags = tag::parse_tags(data.tags.as_deref().unwrap_or_default());
+            MonitorTag::set_for_monitor(id, &tags, pool).await?;
 
-            let view = EditMonitorView {
-                monitor: db_result.clone(),
-            };
+            AuditEntry::record(
+                pool,
+                &Actor::new(&user.0, ip),
+                "monitor.update",
+                "monitor",
+                Some(id),
+                Some(&existing),
+                Some(db_result),
+            )
+            .await;
+
+            let view = edit_monitor_template(db_result.clone(), pool).await?;
 
             Ok(RedirectResponder {
                 content: view.render()?,
-                redirect_uri: Some(uri!("/monitor", monitor_view(id))),
+                redirect_uri: Some(uri!("/monitor", monitor_view(id, _))),
             })
         }
         None => Ok(RedirectResponder {
@@ -220,9 +530,10 @@ pub async fn delete_monitor<'a>(
     pool: &State<Pool<Sqlite>>,
     pinger_manager: &State<PingerManager>,
     id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
 ) -> RedirectResult {
-    database::Monitor::delete(id, pool).await?;
-    pinger_manager.remove_pinger(id).await;
+    remove_monitor(id, &Actor::new(&user.0, ip), pool, pinger_manager).await?;
 
     Ok(RedirectResponder {
         content: "ok".into(),
@@ -235,29 +546,16 @@ pub async fn create_monitor<'a>(
     form: Form<Contextual<'a, CreateMonitor>>,
     pool: &State<Pool<Sqlite>>,
     manager: &State<PingerManager>,
+    user: Editor,
+    ip: Option<IpAddr>,
 ) -> RedirectResult {
     match form.value {
         Some(ref data) => {
-            let monitor = database::Monitor {
-                id: 0, // field ignored, this is an autoincrement field
-                interval: data.interval,
-                protocol: ping::Protocol::HTTP,
-                name: data.name.clone(),
-                ip: data.ip.clone(),
-                port: data.port,
-                paused: false,
-            };
-
-            let result = monitor.create(&pool).await?;
-            let interval = monitor.interval.clone();
-
-            manager
-                .add_pinger(ping::Pinger::new(monitor, interval, || {}))
-                .await;
+            let result = insert_monitor(data, &Actor::new(&user.0, ip), pool, manager).await?;
 
             Ok(RedirectResponder {
                 content: "ok".into(),
-                redirect_uri: Some(uri!("/monitor", monitor_view(result.id))),
+                redirect_uri: Some(uri!("/monitor", monitor_view(result.id, _))),
             })
         }
         None => Err(AppError {
@@ -266,3 +564,1887 @@ pub async fn create_monitor<'a>(
         }),
     }
 }
+
+/// Fails without changing anything when a parent depends on the monitor itself
+async fn set_parents(id: i64, parents: &[i64], pool: &Pool<Sqlite>) -> Result<(), AppError> {
+    Dependency::set_parents(id, parents, pool)
+        .await?
+        .map_err(|parent_id| AppError {
+            status: Status::BadRequest,
+            message: format!("Monitor {} already depends on this monitor", parent_id),
+        })
+}
+
+/// Form fields left empty come in as empty strings
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.clone().filter(|value| !value.trim().is_empty())
+}
+
+fn form_protocol(data: &CreateMonitor) -> Result<ping::Protocol, AppError> {
+    let protocol = match non_empty(&data.protocol) {
+        Some(value) => ping::Protocol::parse(&value).ok_or_else(|| AppError {
+            status: Status::BadRequest,
+            message: format!("Unknown protocol {}", value),
+        })?,
+        None => ping::Protocol::HTTP,
+    };
+
+    ping::check_settings(
+        non_empty(&data.ws_expect).as_deref(),
+        data.icmp_count,
+        data.icmp_max_loss,
+    )
+    .map_err(|message| AppError {
+        status: Status::BadRequest,
+        message,
+    })?;
+
+    Ok(protocol)
+}
+
+async fn insert_monitor(
+    data: &CreateMonitor,
+    actor: &Actor,
+    pool: &Pool<Sqlite>,
+    manager: &PingerManager,
+) -> Result<database::Monitor, AppError> {
+    let monitor = database::Monitor {
+        id: 0, // field ignored, this is an autoincrement field
+        interval: data.interval,
+        protocol: form_protocol(data)?,
+        name: data.name.clone(),
+        ip: data.ip.clone(),
+        port: data.port,
+        paused: false,
+        created_by: actor.id,
+        updated_by: actor.id,
+        managed_key: None,
+        group_id: data.group_id,
+        degraded_threshold_ms: data.degraded_threshold_ms,
+        ws_message: non_empty(&data.ws_message),
+        ws_expect: non_empty(&data.ws_expect),
+        ws_timeout_ms: data.ws_timeout_ms,
+        icmp_count: data.icmp_count,
+        icmp_max_loss: data.icmp_max_loss,
+    };
+
+    let result = monitor.create(pool).await?;
+    let interval = result.interval.clone();
+    let tags = tag::parse_tags(data.tags.as_deref().unwrap_or_default());
+    MonitorTag::set_for_monitor(result.id, &tags, pool).await?;
+    set_parents(result.id, &data.parents, pool).await?;
+
+    manager
+        .add_pinger(ping::Pinger::new(result.clone(), interval, || {}))
+        .await;
+
+    AuditEntry::record(
+        pool,
+        actor,
+        "monitor.create",
+        "monitor",
+        Some(result.id),
+        None,
+        Some(&result),
+    )
+    .await;
+
+    Ok(result)
+}
+
+/// Pauses or resumes a monitor, `None` toggles the current state
+async fn change_paused(
+    id: i64,
+    paused: Option<bool>,
+    actor: &Actor,
+    pool: &Pool<Sqlite>,
+    manager: &State<PingerManager>,
+) -> Result<(), sqlx::Error> {
+    let before = database::Monitor::by_id(id, pool).await?;
+    let paused = paused.unwrap_or(!before.paused);
+    database::Monitor::set_paused(id, paused, actor.id, pool, manager).await?;
+    let after = database::Monitor::by_id(id, pool).await?;
+
+    let action = match paused {
+        true => "monitor.pause",
+        false => "monitor.resume",
+    };
+    AuditEntry::record(
+        pool,
+        actor,
+        action,
+        "monitor",
+        Some(id),
+        Some(&before),
+        Some(&after),
+    )
+    .await;
+
+    Ok(())
+}
+
+fn ensure_unmanaged(monitor: &database::Monitor) -> Result<(), AppError> {
+    match monitor.is_managed() {
+        true => Err(AppError {
+            status: Status::Forbidden,
+            message: "This monitor is managed by the monitors file".to_string(),
+        }),
+        false => Ok(()),
+    }
+}
+
+async fn remove_monitor(
+    id: i64,
+    actor: &Actor,
+    pool: &Pool<Sqlite>,
+    manager: &PingerManager,
+) -> Result<(), AppError> {
+    let before = database::Monitor::by_id(id, pool).await?;
+    ensure_unmanaged(&before)?;
+    database::Monitor::delete(id, pool).await?;
+    manager.remove_pinger(id).await;
+
+    AuditEntry::record(
+        pool,
+        actor,
+        "monitor.delete",
+        "monitor",
+        Some(id),
+        Some(&before),
+        None,
+    )
+    .await;
+
+    Ok(())
+}
+
+//
+// REST api
+//
+/// Every monitor, narrowed down with `?search=` and repeated `?tag=` like the list
+#[get("/?<filter..>")]
+pub async fn all_monitors<'a>(
+    pool: &State<Pool<Sqlite>>,
+    filter: MonitorFilter,
+    _user: Viewer,
+) -> JsonResult<'a> {
+    let mut monitors = database::Monitor::all(pool).await?;
+    if !filter.is_empty() {
+        let tags = MonitorTag::all(pool).await?;
+        monitors.retain(|monitor| {
+            let monitor_tags: Vec<MonitorTag> = tags
+                .iter()
+                .filter(|tag| tag.monitor_id == monitor.id)
+                .cloned()
+                .collect();
+            filter.matches(monitor, &monitor_tags)
+        });
+    }
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&monitors)))
+}
+
+#[get("/<id>")]
+pub async fn get_monitor<'a>(pool: &State<Pool<Sqlite>>, id: i64, _user: Viewer) -> JsonResult<'a> {
+    let monitor = database::Monitor::by_id(id, pool).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&monitor)))
+}
+
+/// Response time percentiles over the same ranges as the monitor page
+#[get("/<id>/stats?<range..>")]
+pub async fn monitor_stats<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    range: GraphRange,
+    _user: Viewer,
+) -> JsonResult<'a> {
+    let offset = range.offset();
+    let (start, end) = offset.pretty_strings();
+    let pings = database::MonitorPing::between(pool, id, offset).await?;
+    let stats = database::RangeStats {
+        start,
+        end,
+        pings: pings.len(),
+        response: database::ResponseStats::of(&pings),
+    };
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&stats)))
+}
+
+/// Daily uptime rollups of a monitor, `since` is a `YYYY-MM-DD` day
+#[get("/<id>/daily?<since>")]
+pub async fn monitor_daily<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    since: &str,
+    _user: Viewer,
+) -> JsonResult<'a> {
+    let monitor = database::Monitor::by_id(id, pool).await?;
+    let days = DailyUptime::for_monitor(&monitor, since, pool).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&days)))
+}
+
+/// Ping history of a monitor, `since` is a `YYYY-MM-DD HH:MM:SS` timestamp
+#[get("/<id>/pings?<since>")]
+pub async fn monitor_history<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    since: &str,
+    _user: Viewer,
+) -> JsonResult<'a> {
+    let pings = database::MonitorPing::since(pool, id, since).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&pings)))
+}
+
+#[post("/", data = "<data>")]
+pub async fn api_create_monitor<'a>(
+    data: Json<CreateMonitor>,
+    pool: &State<Pool<Sqlite>>,
+    manager: &State<PingerManager>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let monitor = insert_monitor(&data, &Actor::new(&user.0, ip), pool, manager).await?;
+
+    Ok(serde_response(
+        Status::Created,
+        serde_json::to_string(&monitor),
+    ))
+}
+
+#[post("/<id>/pause")]
+pub async fn api_pause_monitor<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    pinger_manager: &State<PingerManager>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let actor = Actor::new(&user.0, ip);
+    change_paused(id, Some(true), &actor, pool, pinger_manager).await?;
+
+    Ok(json_response(Status::Ok, None))
+}
+
+#[post("/<id>/resume")]
+pub async fn api_resume_monitor<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    pinger_manager: &State<PingerManager>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let actor = Actor::new(&user.0, ip);
+    change_paused(id, Some(false), &actor, pool, pinger_manager).await?;
+
+    Ok(json_response(Status::Ok, None))
+}
+
+#[delete("/<id>")]
+pub async fn api_delete_monitor<'a>(
+    pool: &State<Pool<Sqlite>>,
+    pinger_manager: &State<PingerManager>,
+    id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    remove_monitor(id, &Actor::new(&user.0, ip), pool, pinger_manager).await?;
+
+    Ok(json_response(Status::Ok, None))
+}
+
+#[get("/?<since>")]
+pub async fn api_incidents<'a>(
+    pool: &State<Pool<Sqlite>>,
+    since: Option<&str>,
+    _user: Viewer,
+) -> JsonResult<'a> {
+    let incidents = Incident::since(since, pool).await?;
+
+    Ok(serde_response(
+        Status::Ok,
+        serde_json::to_string(&incidents),
+    ))
+}
+
+/// Reconciles the database with the monitors file, `dry_run` only returns the plan
+#[post("/reconcile?<dry_run>")]
+pub async fn reconcile_monitors_file<'a>(
+    pool: &State<Pool<Sqlite>>,
+    manager: &State<PingerManager>,
+    dry_run: Option<bool>,
+    _user: Admin,
+) -> JsonResult<'a> {
+    let path = monitors_file::path().ok_or(AppError {
+        status: Status::NotFound,
+        message: "MONITORS_FILE is not set".to_string(),
+    })?;
+
+    let changes = monitors_file::reconcile(&path, dry_run.unwrap_or(false), pool, manager).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&changes)))
+}
+
+/// Re-reads the monitors file and syncs the running pingers with the database
+#[post("/reload")]
+pub async fn reload_config<'a>(
+    pool: &State<Pool<Sqlite>>,
+    manager: &State<PingerManager>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let reload = monitors_file::reload(pool, manager).await?;
+
+    AuditEntry::record(
+        pool,
+        &Actor::new(&user.0, ip),
+        "config.reload",
+        "config",
+        None,
+        None,
+        Some(&reload.pingers),
+    )
+    .await;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&reload)))
+}
+
+//
+// login.html
+//
+#[get("/login")]
+pub async fn login_view<'a>(pool: &State<Pool<Sqlite>>, sso: Sso) -> TemplateResult<'a> {
+    let view = LoginViewTemplate {
+        title: "Login",
+        setup: User::count(pool).await? == 0,
+        sso: sso.0,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/login", data = "<form>")]
+pub async fn login<'a>(
+    form: Form<Contextual<'a, LoginForm>>,
+    pool: &State<Pool<Sqlite>>,
+    pending: &State<PendingLogins>,
+    cookies: &CookieJar<'_>,
+) -> RedirectResult {
+    let data = form.value.as_ref().ok_or(AppError {
+        status: Status::BadRequest,
+        message: "Invalid form data".to_string(),
+    })?;
+
+    match User::authenticate(&data.username, &data.password, pool).await {
+        Some(user) if user.has_totp() => {
+            pending.start(&user, cookies);
+            Ok(RedirectResponder {
+                content: "ok".into(),
+                redirect_uri: Some(uri!("/auth", totp_login_view())),
+            })
+        }
+        Some(user) => {
+            user.login(cookies);
+            Ok(RedirectResponder {
+                content: "ok".into(),
+                redirect_uri: Some(uri!("/")),
+            })
+        }
+        None => Ok(RedirectResponder {
+            content: "Invalid username or password".into(),
+            redirect_uri: None,
+        }),
+    }
+}
+
+/// Creates the first admin account, only available while no users exist
+#[post("/setup", data = "<form>")]
+pub async fn setup<'a>(
+    form: Form<Contextual<'a, CreateUserForm>>,
+    pool: &State<Pool<Sqlite>>,
+    cookies: &CookieJar<'_>,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    if User::count(pool).await? > 0 {
+        return Err(AppError {
+            status: Status::Forbidden,
+            message: "Setup has already been completed".to_string(),
+        });
+    }
+
+    let user = create_user(form.value.as_ref(), auth::Role::Admin, pool).await?;
+    let actor = Actor::new(&user, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "user.create",
+        "user",
+        Some(user.id),
+        None,
+        Some(&user),
+    )
+    .await;
+    user.login(cookies);
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/")),
+    })
+}
+
+//
+// totp_login.html
+//
+#[get("/2fa")]
+pub async fn totp_login_view<'a>(
+    pending: &State<PendingLogins>,
+    cookies: &CookieJar<'_>,
+) -> Result<TemplateResponse<'a>, Redirect> {
+    match pending.user_id(cookies) {
+        Some(_) => Ok(template_response(
+            Status::Ok,
+            TotpLoginViewTemplate {
+                title: "Two-factor authentication",
+            },
+        )),
+        None => Err(Redirect::to(uri!("/auth", login_view()))),
+    }
+}
+
+/// Second login step, accepts either a current totp code or an unused recovery code
+#[post("/2fa", data = "<form>")]
+pub async fn totp_login<'a>(
+    form: Form<Contextual<'a, TotpCodeForm>>,
+    pool: &State<Pool<Sqlite>>,
+    pending: &State<PendingLogins>,
+    cookies: &CookieJar<'_>,
+) -> RedirectResult {
+    let code = form
+        .value
+        .as_ref()
+        .map(|data| data.code.clone())
+        .ok_or(AppError {
+            status: Status::BadRequest,
+            message: "Invalid form data".to_string(),
+        })?;
+
+    let user_id = pending.user_id(cookies).ok_or(AppError {
+        status: Status::Unauthorized,
+        message: "Login expired, please log in again".to_string(),
+    })?;
+    let user = User::by_id(user_id, pool).await?;
+
+    let valid = match user.totp_secret {
+        Some(ref secret) => match totp::code_step(secret, &user.username, &code) {
+            // a code works once, even while its time step lasts
+            Some(step) => User::use_totp_step(user.id, step, pool).await?,
+            None => RecoveryCode::redeem(user.id, &code, pool).await,
+        },
+        None => false,
+    };
+
+    if !valid {
+        if !pending.fail(cookies) {
+            return Err(AppError {
+                status: Status::Unauthorized,
+                message: "Too many invalid codes, please log in again".to_string(),
+            });
+        }
+        return Ok(RedirectResponder {
+            content: "Invalid code".into(),
+            redirect_uri: None,
+        });
+    }
+
+    pending.clear(cookies);
+    user.login(cookies);
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/")),
+    })
+}
+
+#[get("/oidc/login")]
+pub async fn oidc_login(oidc: &State<Oidc>, cookies: &CookieJar<'_>) -> Result<Redirect, AppError> {
+    Ok(Redirect::to(oidc.authorization_url(cookies).await?))
+}
+
+#[get("/oidc/callback?<code>&<state>")]
+pub async fn oidc_callback(
+    oidc: &State<Oidc>,
+    pool: &State<Pool<Sqlite>>,
+    cookies: &CookieJar<'_>,
+    code: &str,
+    state: &str,
+) -> Result<Redirect, AppError> {
+    let identity = oidc.exchange(code, state, cookies).await?;
+    let user = oidc.user_for(identity, pool).await?;
+    user.login(cookies);
+
+    Ok(Redirect::to(uri!("/")))
+}
+
+#[post("/logout")]
+pub async fn logout(cookies: &CookieJar<'_>) -> RedirectResult {
+    User::logout(cookies);
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/auth", login_view())),
+    })
+}
+
+async fn create_user(
+    data: Option<&CreateUserForm>,
+    role: auth::Role,
+    pool: &Pool<Sqlite>,
+) -> Result<User, AppError> {
+    let data = data.ok_or(AppError {
+        status: Status::BadRequest,
+        message: "Invalid form data".to_string(),
+    })?;
+
+    if data.username.trim().is_empty() || data.password.is_empty() {
+        return Err(AppError {
+            status: Status::BadRequest,
+            message: "Username and password are required".to_string(),
+        });
+    }
+
+    if data.password != data.password_confirm {
+        return Err(AppError {
+            status: Status::BadRequest,
+            message: "Passwords do not match".to_string(),
+        });
+    }
+
+    let password_hash = auth::hash_password(&data.password).map_err(|err| AppError {
+        status: Status::InternalServerError,
+        message: format!("Error hashing password: {}", err),
+    })?;
+
+    let user = User {
+        id: 0, // field ignored, this is an autoincrement field
+        username: data.username.trim().to_string(),
+        password_hash,
+        role,
+        created_at: chrono::Local::now().pretty_string(),
+        external_id: None,
+        totp_secret: None,
+    };
+
+    Ok(user.create(pool).await?)
+}
+
+//
+// totp.html
+//
+#[get("/2fa")]
+pub async fn totp_view<'a>(
+    pool: &State<Pool<Sqlite>>,
+    cookies: &CookieJar<'_>,
+    user: SessionUser,
+) -> TemplateResult<'a> {
+    let user = user.0;
+    let (secret, qr_code) = match user.has_totp() {
+        true => (None, None),
+        false => {
+            let secret = totp::enrollment_secret(cookies);
+            let qr_code = totp::qr_code(&secret, &user.username);
+            (Some(secret), qr_code)
+        }
+    };
+
+    let view = TotpViewTemplate {
+        title: "Two-factor authentication",
+        required: Setting::get_bool(Setting::REQUIRE_TOTP, pool).await,
+        remaining_codes: RecoveryCode::remaining(user.id, pool).await?,
+        user,
+        secret,
+        qr_code,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+fn totp_code(form: &Form<Contextual<'_, TotpCodeForm>>) -> Result<String, AppError> {
+    form.value
+        .as_ref()
+        .map(|data| data.code.clone())
+        .ok_or(AppError {
+            status: Status::BadRequest,
+            message: "Invalid form data".to_string(),
+        })
+}
+
+#[post("/2fa", data = "<form>")]
+pub async fn enable_totp<'a>(
+    form: Form<Contextual<'a, TotpCodeForm>>,
+    pool: &State<Pool<Sqlite>>,
+    cookies: &CookieJar<'_>,
+    user: SessionUser,
+    ip: Option<IpAddr>,
+) -> TemplateResult<'a> {
+    let code = totp_code(&form)?;
+    let secret = totp::enrollment_secret(cookies);
+
+    if !totp::check_code(&secret, &user.0.username, &code) {
+        return Err(AppError {
+            status: Status::BadRequest,
+            message: "Invalid code, please try again".to_string(),
+        });
+    }
+
+    User::set_totp_secret(user.0.id, Some(secret), pool).await?;
+    totp::clear_enrollment(cookies);
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record::<()>(
+        pool,
+        &actor,
+        "user.enable_2fa",
+        "user",
+        Some(user.0.id),
+        None,
+        None,
+    )
+    .await;
+
+    let view = RecoveryCodesTemplate {
+        codes: RecoveryCode::regenerate(user.0.id, pool).await?,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/2fa/recovery-codes", data = "<form>")]
+pub async fn regenerate_recovery_codes<'a>(
+    form: Form<Contextual<'a, TotpCodeForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: SessionUser,
+    ip: Option<IpAddr>,
+) -> TemplateResult<'a> {
+    let code = totp_code(&form)?;
+    let valid = match user.0.totp_secret {
+        Some(ref secret) => totp::check_code(secret, &user.0.username, &code),
+        None => false,
+    };
+
+    if !valid {
+        return Err(AppError {
+            status: Status::BadRequest,
+            message: "Invalid code, please try again".to_string(),
+        });
+    }
+
+    let codes = RecoveryCode::regenerate(user.0.id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record::<()>(
+        pool,
+        &actor,
+        "user.recovery_codes",
+        "user",
+        Some(user.0.id),
+        None,
+        None,
+    )
+    .await;
+
+    let view = RecoveryCodesTemplate { codes };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/2fa/disable", data = "<form>")]
+pub async fn disable_totp<'a>(
+    form: Form<Contextual<'a, TotpCodeForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: SessionUser,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    if Setting::get_bool(Setting::REQUIRE_TOTP, pool).await {
+        return Err(AppError {
+            status: Status::Forbidden,
+            message: "Two-factor authentication is required for all accounts".to_string(),
+        });
+    }
+
+    let code = totp_code(&form)?;
+    let valid = match user.0.totp_secret {
+        Some(ref secret) => totp::check_code(secret, &user.0.username, &code),
+        None => false,
+    };
+
+    if !valid {
+        return Err(AppError {
+            status: Status::BadRequest,
+            message: "Invalid code, please try again".to_string(),
+        });
+    }
+
+    User::set_totp_secret(user.0.id, None, pool).await?;
+    RecoveryCode::delete_for_user(user.0.id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record::<()>(
+        pool,
+        &actor,
+        "user.disable_2fa",
+        "user",
+        Some(user.0.id),
+        None,
+        None,
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/account", totp_view())),
+    })
+}
+
+//
+// accept_invite.html
+//
+#[get("/invite/<token>")]
+pub async fn accept_invite_view<'a>(
+    pool: &'a State<Pool<Sqlite>>,
+    token: &'a str,
+) -> TemplateResult<'a> {
+    let view = AcceptInviteViewTemplate {
+        title: "Accept invite",
+        invite: UserInvite::pending_by_token(token, pool).await?,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/invite/<token>", data = "<form>")]
+pub async fn accept_invite<'a>(
+    token: &str,
+    form: Form<Contextual<'a, CreateUserForm>>,
+    pool: &State<Pool<Sqlite>>,
+    cookies: &CookieJar<'_>,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let invite = UserInvite::pending_by_token(token, pool).await?;
+    let user = create_user(form.value.as_ref(), invite.role, pool).await?;
+    invite.accept(pool).await?;
+    let actor = Actor::new(&user, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "user.create",
+        "user",
+        Some(user.id),
+        None,
+        Some(&user),
+    )
+    .await;
+    user.login(cookies);
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/")),
+    })
+}
+
+//
+// users.html
+//
+#[get("/")]
+pub async fn users_view<'a>(pool: &State<Pool<Sqlite>>, user: Admin) -> TemplateResult {
+    let view = UsersViewTemplate {
+        title: "Users",
+        user: user.0,
+        users: User::all(pool).await?,
+        invites: UserInvite::pending(pool).await?,
+        roles: auth::Role::all(),
+        require_totp: Setting::get_bool(Setting::REQUIRE_TOTP, pool).await,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/invite", data = "<form>")]
+pub async fn create_invite<'a>(
+    form: Form<Contextual<'a, RoleForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let role = form.value.as_ref().map(|data| data.role).ok_or(AppError {
+        status: Status::BadRequest,
+        message: "Invalid form data".to_string(),
+    })?;
+
+    let invite = UserInvite::new(role, user.0.id).create(pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "invite.create",
+        "invite",
+        Some(invite.id),
+        None,
+        Some(&invite),
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/users", users_view())),
+    })
+}
+
+#[delete("/invite/<id>")]
+pub async fn delete_invite(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let before = UserInvite::by_id(id, pool).await?;
+    UserInvite::delete(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "invite.delete",
+        "invite",
+        Some(id),
+        Some(&before),
+        None,
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/users", users_view())),
+    })
+}
+
+#[put("/<id>/role", data = "<form>")]
+pub async fn update_user_role<'a>(
+    id: i64,
+    form: Form<Contextual<'a, RoleForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let role = form.value.as_ref().map(|data| data.role).ok_or(AppError {
+        status: Status::BadRequest,
+        message: "Invalid form data".to_string(),
+    })?;
+
+    if id == user.0.id {
+        return Err(AppError {
+            status: Status::BadRequest,
+            message: "You can't change your own role".to_string(),
+        });
+    }
+
+    let before = User::by_id(id, pool).await?;
+    User::set_role(id, role, pool).await?;
+    let after = User::by_id(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "user.role",
+        "user",
+        Some(id),
+        Some(&before),
+        Some(&after),
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/users", users_view())),
+    })
+}
+
+#[delete("/<id>")]
+pub async fn delete_user(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    if id == user.0.id {
+        return Err(AppError {
+            status: Status::BadRequest,
+            message: "You can't delete your own account".to_string(),
+        });
+    }
+
+    let before = User::by_id(id, pool).await?;
+    User::delete(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "user.delete",
+        "user",
+        Some(id),
+        Some(&before),
+        None,
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/users", users_view())),
+    })
+}
+
+#[put("/settings/require-2fa", data = "<form>")]
+pub async fn update_require_totp<'a>(
+    form: Form<Contextual<'a, ToggleSettingForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let before = Setting::get_bool(Setting::REQUIRE_TOTP, pool).await;
+    let enabled = form
+        .value
+        .as_ref()
+        .map(|data| data.enabled)
+        .unwrap_or(false);
+    Setting::set(Setting::REQUIRE_TOTP, &enabled.to_string(), pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "setting.update",
+        Setting::REQUIRE_TOTP,
+        None,
+        Some(&before),
+        Some(&enabled),
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/users", users_view())),
+    })
+}
+
+/// Lets an admin reset the second factor of a user that lost their device
+#[delete("/<id>/2fa", rank = 2)]
+pub async fn reset_user_totp(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    User::set_totp_secret(id, None, pool).await?;
+    RecoveryCode::delete_for_user(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record::<()>(pool, &actor, "user.reset_2fa", "user", Some(id), None, None).await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/users", users_view())),
+    })
+}
+
+//
+// tags.html
+//
+async fn tags_template<'a>(
+    user: User,
+    pool: &Pool<Sqlite>,
+) -> Result<TagsViewTemplate<'a>, sqlx::Error> {
+    Ok(TagsViewTemplate {
+        title: "Tags",
+        user,
+        tags: Tag::all(pool).await?,
+        group_paths: Group::paths(&Group::all(pool).await?),
+    })
+}
+
+#[get("/")]
+pub async fn tags_view<'a>(pool: &State<Pool<Sqlite>>, user: Editor) -> TemplateResult {
+    let view = tags_template(user.0, pool).await?;
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[put("/<id>/color", data = "<form>")]
+pub async fn update_tag_color<'a>(
+    id: i64,
+    form: Form<Contextual<'a, TagColorForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let color = form
+        .value
+        .as_ref()
+        .map(|data| data.color.to_lowercase())
+        .filter(|color| tag::is_color(color))
+        .ok_or(AppError {
+            status: Status::BadRequest,
+            message: "Invalid color".to_string(),
+        })?;
+
+    let before = Tag::by_id(id, pool).await?;
+    Tag::set_color(id, &color, pool).await?;
+    let after = Tag::by_id(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "tag.update",
+        "tag",
+        Some(id),
+        Some(&before),
+        Some(&after),
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/tags", tags_view())),
+    })
+}
+
+#[delete("/<id>")]
+pub async fn delete_tag(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let before = Tag::by_id(id, pool).await?;
+    Tag::delete(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "tag.delete",
+        "tag",
+        Some(id),
+        Some(&before),
+        None,
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/tags", tags_view())),
+    })
+}
+
+#[post("/group", data = "<form>")]
+pub async fn create_group<'a>(
+    form: Form<Contextual<'a, CreateGroupForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let data = form
+        .value
+        .as_ref()
+        .filter(|data| !data.name.trim().is_empty())
+        .ok_or(AppError {
+            status: Status::BadRequest,
+            message: "Invalid form data".to_string(),
+        })?;
+
+    let group = Group::create(data.name.trim(), data.parent_id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "group.create",
+        "group",
+        Some(group.id),
+        None,
+        Some(&group),
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/tags", tags_view())),
+    })
+}
+
+#[delete("/group/<id>")]
+pub async fn delete_group(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let before = Group::all(pool)
+        .await?
+        .into_iter()
+        .find(|group| group.id == id);
+    Group::delete(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "group.delete",
+        "group",
+        Some(id),
+        before.as_ref(),
+        None,
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/tags", tags_view())),
+    })
+}
+
+#[get("/")]
+pub async fn api_tags<'a>(pool: &State<Pool<Sqlite>>, _user: Viewer) -> JsonResult<'a> {
+    let tags = Tag::all(pool).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&tags)))
+}
+
+/// The monitor list as a tree, every group carries the worst state of its monitors
+#[get("/?<filter..>")]
+pub async fn api_groups<'a>(
+    pool: &State<Pool<Sqlite>>,
+    filter: MonitorFilter,
+    _user: Viewer,
+) -> JsonResult<'a> {
+    let tree = get_monitor_list(pool, &filter).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&tree)))
+}
+
+//
+// maintenance.html
+//
+#[get("/")]
+pub async fn maintenance_view<'a>(pool: &State<Pool<Sqlite>>, user: Editor) -> TemplateResult {
+    let monitors = database::Monitor::all(pool).await?;
+    let now = chrono::Local::now().naive_local();
+    let windows = MaintenanceWindow::all(pool)
+        .await?
+        .into_iter()
+        .map(|window| MaintenanceItem {
+            target: match (window.monitor_id, window.tag.as_deref()) {
+                (Some(id), _) => monitors
+                    .iter()
+                    .find(|monitor| monitor.id == id)
+                    .map(|monitor| monitor.name.clone())
+                    .unwrap_or_else(|| format!("Monitor {}", id)),
+                (None, Some(tag)) => format!("Tag {}", tag),
+                (None, None) => String::new(),
+            },
+            next: window.next_occurrence(now),
+            window,
+        })
+        .collect();
+
+    // sorted by name and value, so duplicates are next to each other
+    let mut tags: Vec<String> = MonitorTag::all(pool)
+        .await?
+        .iter()
+        .map(MonitorTag::key)
+        .collect();
+    tags.dedup();
+
+    let view = MaintenanceViewTemplate {
+        title: "Maintenance",
+        user: user.0,
+        windows,
+        monitors,
+        tags,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+async fn insert_maintenance(
+    data: &MaintenanceForm,
+    actor: &Actor,
+    pool: &Pool<Sqlite>,
+) -> Result<MaintenanceWindow, AppError> {
+    let window = MaintenanceWindow::from_form(data, actor.id).map_err(|message| AppError {
+        status: Status::BadRequest,
+        message,
+    })?;
+    let window = window.create(pool).await?;
+
+    AuditEntry::record(
+        pool,
+        actor,
+        "maintenance.create",
+        "maintenance",
+        Some(window.id),
+        None,
+        Some(&window),
+    )
+    .await;
+
+    Ok(window)
+}
+
+async fn remove_maintenance(id: i64, actor: &Actor, pool: &Pool<Sqlite>) -> Result<(), AppError> {
+    let before = MaintenanceWindow::by_id(id, pool).await?;
+    MaintenanceWindow::delete(id, pool).await?;
+    AuditEntry::record(
+        pool,
+        actor,
+        "maintenance.delete",
+        "maintenance",
+        Some(id),
+        Some(&before),
+        None,
+    )
+    .await;
+
+    Ok(())
+}
+
+#[post("/", data = "<form>")]
+pub async fn create_maintenance<'a>(
+    form: Form<Contextual<'a, MaintenanceForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let data = form.value.as_ref().ok_or(AppError {
+        status: Status::BadRequest,
+        message: "Invalid form data".to_string(),
+    })?;
+    insert_maintenance(data, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/maintenance", maintenance_view())),
+    })
+}
+
+#[delete("/<id>")]
+pub async fn delete_maintenance(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    remove_maintenance(id, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/maintenance", maintenance_view())),
+    })
+}
+
+/// Windows with their running or next occurrence
+#[get("/")]
+pub async fn api_maintenance<'a>(pool: &State<Pool<Sqlite>>, _user: Viewer) -> JsonResult<'a> {
+    let now = chrono::Local::now().naive_local();
+    let windows: Vec<ScheduledWindow> = MaintenanceWindow::all(pool)
+        .await?
+        .into_iter()
+        .map(|window| ScheduledWindow {
+            next: window.next_occurrence(now),
+            window,
+        })
+        .collect();
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&windows)))
+}
+
+#[post("/", data = "<data>")]
+pub async fn api_create_maintenance<'a>(
+    data: Json<MaintenanceForm>,
+    pool: &State<Pool<Sqlite>>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let window = insert_maintenance(&data, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(serde_response(
+        Status::Created,
+        serde_json::to_string(&window),
+    ))
+}
+
+#[delete("/<id>")]
+pub async fn api_delete_maintenance<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    remove_maintenance(id, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(json_response(Status::Ok, None))
+}
+
+//
+// slo.html
+//
+async fn slo_statuses(
+    monitors: &[database::Monitor],
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<SloStatus>, sqlx::Error> {
+    let groups = Group::all(pool).await?;
+    let mut statuses = Vec::new();
+    for slo in Slo::all(pool).await? {
+        statuses.push(slo.status(monitors, &groups, pool).await?);
+    }
+
+    Ok(statuses)
+}
+
+#[get("/")]
+pub async fn slo_view<'a>(pool: &State<Pool<Sqlite>>, user: Editor) -> TemplateResult {
+    let monitors = database::Monitor::all(pool).await?;
+    let slos = slo_statuses(&monitors, pool).await?;
+
+    let view = SloViewTemplate {
+        title: "SLOs",
+        user: user.0,
+        slos,
+        monitors,
+        groups: Group::paths(&Group::all(pool).await?),
+        default_alerts: slo::DEFAULT_ALERTS,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+async fn insert_slo(data: &SloForm, actor: &Actor, pool: &Pool<Sqlite>) -> Result<Slo, AppError> {
+    let slo = Slo::from_form(data, actor.id).map_err(|message| AppError {
+        status: Status::BadRequest,
+        message,
+    })?;
+    let slo = slo.create(pool).await?;
+
+    AuditEntry::record(
+        pool,
+        actor,
+        "slo.create",
+        "slo",
+        Some(slo.id),
+        None,
+        Some(&slo),
+    )
+    .await;
+
+    Ok(slo)
+}
+
+async fn remove_slo(id: i64, actor: &Actor, pool: &Pool<Sqlite>) -> Result<(), AppError> {
+    let before = Slo::by_id(id, pool).await?;
+    Slo::delete(id, pool).await?;
+    AuditEntry::record(
+        pool,
+        actor,
+        "slo.delete",
+        "slo",
+        Some(id),
+        Some(&before),
+        None,
+    )
+    .await;
+
+    Ok(())
+}
+
+#[post("/", data = "<form>")]
+pub async fn create_slo<'a>(
+    form: Form<Contextual<'a, SloForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let data = form.value.as_ref().ok_or(AppError {
+        status: Status::BadRequest,
+        message: "Invalid form data".to_string(),
+    })?;
+    insert_slo(data, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/slo", slo_view())),
+    })
+}
+
+#[delete("/<id>")]
+pub async fn delete_slo(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    remove_slo(id, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/slo", slo_view())),
+    })
+}
+
+/// Objectives with their compliance, error budget and burn rate
+#[get("/")]
+pub async fn api_slos<'a>(pool: &State<Pool<Sqlite>>, _user: Viewer) -> JsonResult<'a> {
+    let monitors = database::Monitor::all(pool).await?;
+    let slos = slo_statuses(&monitors, pool).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&slos)))
+}
+
+#[post("/", data = "<data>")]
+pub async fn api_create_slo<'a>(
+    data: Json<SloForm>,
+    pool: &State<Pool<Sqlite>>,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let slo = insert_slo(&data, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(serde_response(Status::Created, serde_json::to_string(&slo)))
+}
+
+#[delete("/<id>")]
+pub async fn api_delete_slo<'a>(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Editor,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    remove_slo(id, &Actor::new(&user.0, ip), pool).await?;
+
+    Ok(json_response(Status::Ok, None))
+}
+
+//
+// reports.html
+//
+#[get("/")]
+pub async fn reports_view<'a>(pool: &State<Pool<Sqlite>>, user: Viewer) -> TemplateResult {
+    let (start, end) = ReportSchedule::Monthly.previous_period(chrono::Local::now());
+
+    let view = ReportsViewTemplate {
+        title: "Reports",
+        user: user.0,
+        groups: Group::paths(&Group::all(pool).await?),
+        formats: ReportFormat::all(),
+        start: start.to_string(),
+        end: end.to_string(),
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+async fn build_report(query: &ReportQuery, pool: &Pool<Sqlite>) -> Result<Report, AppError> {
+    let (start, end) = query.days().map_err(|message| AppError {
+        status: Status::BadRequest,
+        message,
+    })?;
+
+    Ok(Report::generate(start, end, query.group_id, pool).await?)
+}
+
+/// The html report is shown for printing, csv and json are downloaded
+#[get("/generate?<query..>")]
+pub async fn generate_report<'a>(
+    pool: &State<Pool<Sqlite>>,
+    query: ReportQuery,
+    _user: Viewer,
+) -> Result<Either<TemplateResponse<'a>, DownloadResponder>, AppError> {
+    let report = build_report(&query, pool).await?;
+    let format = query.format.unwrap_or(ReportFormat::Html);
+    if format == ReportFormat::Html {
+        return Ok(Either::Left(template_response(
+            Status::Ok,
+            ReportTemplate { report },
+        )));
+    }
+
+    let content = report.render(format).map_err(|message| AppError {
+        status: Status::InternalServerError,
+        message,
+    })?;
+    Ok(Either::Right(DownloadResponder {
+        filename: report.filename(format),
+        content_type: match format {
+            ReportFormat::Csv => ContentType::CSV,
+            _ => ContentType::JSON,
+        },
+        content,
+    }))
+}
+
+/// Same query as the reports page, always json
+#[get("/?<query..>")]
+pub async fn api_report<'a>(
+    pool: &State<Pool<Sqlite>>,
+    query: ReportQuery,
+    _user: Viewer,
+) -> JsonResult<'a> {
+    let report = build_report(&query, pool).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&report)))
+}
+
+//
+// audit.html
+//
+#[get("/?<filter..>")]
+pub async fn audit_view<'a>(
+    pool: &State<Pool<Sqlite>>,
+    filter: AuditFilter,
+    user: Admin,
+) -> TemplateResult {
+    let view = AuditViewTemplate {
+        title: "Audit log",
+        user: user.0,
+        entries: AuditEntry::filter(pool, &filter).await?,
+        actions: AuditEntry::actions(pool).await?,
+        filter,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+/// Audit log for shipping to external log collectors, poll with `after_id`
+/// set to the last seen id to receive new entries oldest first
+#[get("/?<filter..>")]
+pub async fn api_audit_log<'a>(
+    pool: &State<Pool<Sqlite>>,
+    filter: AuditFilter,
+    _user: Admin,
+) -> JsonResult<'a> {
+    let entries = AuditEntry::filter(pool, &filter).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&entries)))
+}
+
+//
+// export.html
+//
+async fn run_import(
+    document: Export,
+    mode: ImportMode,
+    actor: &Actor,
+    user_id: i64,
+    pool: &Pool<Sqlite>,
+    manager: &PingerManager,
+) -> Result<ImportSummary, AppError> {
+    let summary = export::import(document, mode, user_id, pool).await?;
+    manager.reload(pool).await?;
+
+    AuditEntry::record(
+        pool,
+        actor,
+        "config.import",
+        "config",
+        None,
+        None,
+        Some(&summary),
+    )
+    .await;
+
+    Ok(summary)
+}
+
+async fn read_upload<T: serde::de::DeserializeOwned>(file: &TempFile<'_>) -> Result<T, AppError> {
+    let mut content = String::new();
+    file.open().await?.read_to_string(&mut content).await?;
+
+    serde_json::from_str(&content).map_err(|err| AppError {
+        status: Status::UnprocessableEntity,
+        message: format!("Invalid file: {}", err),
+    })
+}
+
+#[get("/")]
+pub async fn export_view<'a>(config: &State<BackupConfig>, user: Admin) -> TemplateResult {
+    let view = ExportViewTemplate {
+        title: "Export and import",
+        user: user.0,
+        backups: backup::list(config)?,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/backup")]
+pub async fn create_backup<'a>(
+    config: &State<BackupConfig>,
+    pool: &State<Pool<Sqlite>>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> TemplateResult<'a> {
+    let created = backup::create(config, pool).await?;
+    AuditEntry::record(
+        pool,
+        &Actor::new(&user.0, ip),
+        "backup.create",
+        "backup",
+        None,
+        None,
+        Some(&created),
+    )
+    .await;
+
+    let view = BackupListTemplate {
+        backups: backup::list(config)?,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/import", data = "<form>")]
+pub async fn import_file<'a>(
+    form: Form<ImportForm<'_>>,
+    pool: &State<Pool<Sqlite>>,
+    manager: &State<PingerManager>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> TemplateResult<'a> {
+    let document: Export = read_upload(&form.file).await?;
+    let actor = Actor::new(&user.0, ip);
+    let summary = run_import(document, form.mode, &actor, user.0.id, pool, manager).await?;
+
+    Ok(template_response(
+        Status::Ok,
+        ImportResultTemplate { summary },
+    ))
+}
+
+#[post("/uptime-kuma", data = "<form>")]
+pub async fn import_uptime_kuma_file<'a>(
+    form: Form<KumaImportForm<'_>>,
+    pool: &State<Pool<Sqlite>>,
+    manager: &State<PingerManager>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> TemplateResult<'a> {
+    let backup: KumaBackup = read_upload(&form.file).await?;
+    let report = uptime_kuma::import(backup, &Actor::new(&user.0, ip), pool, manager).await?;
+
+    Ok(template_response(
+        Status::Ok,
+        KumaImportResultTemplate { report },
+    ))
+}
+
+#[get("/?<history>")]
+pub async fn api_export(
+    pool: &State<Pool<Sqlite>>,
+    history: Option<bool>,
+    _user: Admin,
+) -> Result<DownloadResponder, AppError> {
+    let document = export::export(history.unwrap_or(false), pool).await?;
+    let content = serde_json::to_string_pretty(&document).map_err(|err| AppError {
+        status: Status::InternalServerError,
+        message: format!("Failed to serialize export: {}", err),
+    })?;
+
+    Ok(DownloadResponder {
+        filename: format!("uptime-rs-{}.json", chrono::Local::now().format("%Y-%m-%d")),
+        content_type: ContentType::JSON,
+        content,
+    })
+}
+
+#[post("/?<mode>", data = "<document>")]
+pub async fn api_import<'a>(
+    document: Json<Export>,
+    mode: ImportMode,
+    pool: &State<Pool<Sqlite>>,
+    manager: &State<PingerManager>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let actor = Actor::new(&user.0, ip);
+    let summary = run_import(
+        document.into_inner(),
+        mode,
+        &actor,
+        user.0.id,
+        pool,
+        manager,
+    )
+    .await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&summary)))
+}
+
+#[get("/")]
+pub async fn api_backups<'a>(config: &State<BackupConfig>, _user: Admin) -> JsonResult<'a> {
+    let backups = backup::list(config)?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&backups)))
+}
+
+/// Backs up the database now, old backups are rotated like scheduled ones
+#[post("/")]
+pub async fn api_create_backup<'a>(
+    config: &State<BackupConfig>,
+    pool: &State<Pool<Sqlite>>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let created = backup::create(config, pool).await?;
+    AuditEntry::record(
+        pool,
+        &Actor::new(&user.0, ip),
+        "backup.create",
+        "backup",
+        None,
+        None,
+        Some(&created),
+    )
+    .await;
+
+    Ok(serde_response(
+        Status::Created,
+        serde_json::to_string(&created),
+    ))
+}
+
+/// Imports an Uptime Kuma backup, the report lists every setting that couldn't be mapped
+#[post("/uptime-kuma", data = "<backup>")]
+pub async fn api_import_uptime_kuma<'a>(
+    backup: Json<KumaBackup>,
+    pool: &State<Pool<Sqlite>>,
+    manager: &State<PingerManager>,
+    user: Admin,
+    ip: Option<IpAddr>,
+) -> JsonResult<'a> {
+    let actor = Actor::new(&user.0, ip);
+    let report = uptime_kuma::import(backup.into_inner(), &actor, pool, manager).await?;
+
+    Ok(serde_response(Status::Ok, serde_json::to_string(&report)))
+}
+
+//
+// tokens.html
+//
+#[get("/")]
+pub async fn tokens_view<'a>(pool: &State<Pool<Sqlite>>, user: Viewer) -> TemplateResult {
+    let view = TokensViewTemplate {
+        title: "API tokens",
+        tokens: ApiToken::by_user(user.0.id, pool).await?,
+        scopes: Scope::all(),
+        user: user.0,
+    };
+
+    Ok(template_response(Status::Ok, view))
+}
+
+#[post("/", data = "<form>")]
+pub async fn create_token<'a>(
+    form: Form<Contextual<'a, CreateApiTokenForm>>,
+    pool: &State<Pool<Sqlite>>,
+    user: Viewer,
+    ip: Option<IpAddr>,
+) -> TemplateResult<'a> {
+    let data = form.value.as_ref().ok_or(AppError {
+        status: Status::BadRequest,
+        message: "Invalid form data".to_string(),
+    })?;
+
+    if data.scope.role() > user.0.role {
+        return Err(AppError {
+            status: Status::Forbidden,
+            message: "Scope exceeds your own role".to_string(),
+        });
+    }
+
+    let (api_token, token) = ApiToken::generate(
+        user.0.id,
+        data.name.clone(),
+        data.scope,
+        data.expires_in_days,
+    );
+
+    let api_token = api_token.create(pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "token.create",
+        "api_token",
+        Some(api_token.id),
+        None,
+        Some(&api_token),
+    )
+    .await;
+
+    let view = ApiTokenCreatedTemplate { api_token, token };
+
+    Ok(template_response(Status::Created, view))
+}
+
+#[delete("/<id>")]
+pub async fn revoke_token(
+    pool: &State<Pool<Sqlite>>,
+    id: i64,
+    user: Viewer,
+    ip: Option<IpAddr>,
+) -> RedirectResult {
+    let api_token = ApiToken::by_id(id, pool).await?;
+    if api_token.user_id != user.0.id && !user.0.role.is_admin() {
+        return Err(AppError {
+            status: Status::Forbidden,
+            message: "You can only revoke your own tokens".to_string(),
+        });
+    }
+
+    ApiToken::delete(id, pool).await?;
+    let actor = Actor::new(&user.0, ip);
+    AuditEntry::record(
+        pool,
+        &actor,
+        "token.revoke",
+        "api_token",
+        Some(id),
+        Some(&api_token),
+        None,
+    )
+    .await;
+
+    Ok(RedirectResponder {
+        content: "ok".into(),
+        redirect_uri: Some(uri!("/tokens", tokens_view())),
+    })
+}
+
+//
+// Catchers
+//
+#[catch(401)]
+pub fn unauthorized() -> Redirect {
+    Redirect::to(uri!("/auth", login_view()))
+}
+
+#[catch(403)]
+pub fn forbidden() -> &'static str {
+    "You don't have permission to do that"
+}
+
+#[catch(428)]
+pub fn totp_required() -> Redirect {
+    Redirect::to(uri!("/account", totp_view()))
+}
+
+#[catch(401)]
+pub fn api_unauthorized<'a>() -> JsonResponse<'a> {
+    json_response(Status::Unauthorized, None)
+}
+
+#[catch(403)]
+pub fn api_forbidden<'a>() -> JsonResponse<'a> {
+    json_response(Status::Forbidden, None)
+}
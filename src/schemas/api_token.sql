@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS api_token (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  scope TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT,
+  last_used_at TEXT,
+  FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  actor_id INTEGER,
+  actor_name TEXT NOT NULL,
+  action TEXT NOT NULL,
+  target_type TEXT NOT NULL,
+  target_id INTEGER,
+  before_json TEXT,
+  after_json TEXT,
+  source_ip TEXT,
+  timestamp TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS audit_log_timestamp ON audit_log (timestamp);
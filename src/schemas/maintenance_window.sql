@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS maintenance_window (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  monitor_id INTEGER,
+  tag TEXT,
+  starts_at TEXT NOT NULL,
+  duration_minutes INTEGER NOT NULL,
+  recurrence TEXT,
+  created_by INTEGER,
+  FOREIGN KEY (monitor_id) REFERENCES monitor(id) ON DELETE CASCADE,
+  FOREIGN KEY (created_by) REFERENCES user(id) ON DELETE SET NULL
+);
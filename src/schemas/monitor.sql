@@ -3,6 +3,20 @@ CREATE TABLE IF NOT EXISTS monitor (
   name TEXT NOT NULL,
   ip TEXT NOT NULL,
   port INTEGER,
+  protocol TEXT NOT NULL DEFAULT 'http',
   interval INTEGER NOT NULL,
-  paused INTEGER NOT NULL
+  paused INTEGER NOT NULL,
+  created_by INTEGER,
+  updated_by INTEGER,
+  managed_key TEXT UNIQUE,
+  group_id INTEGER,
+  degraded_threshold_ms INTEGER,
+  ws_message TEXT,
+  ws_expect TEXT,
+  ws_timeout_ms INTEGER,
+  icmp_count INTEGER,
+  icmp_max_loss INTEGER,
+  FOREIGN KEY (created_by) REFERENCES user(id) ON DELETE SET NULL,
+  FOREIGN KEY (updated_by) REFERENCES user(id) ON DELETE SET NULL,
+  FOREIGN KEY (group_id) REFERENCES monitor_group(id) ON DELETE SET NULL
 );
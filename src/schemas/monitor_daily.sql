@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS monitor_daily (
+  monitor_id INTEGER NOT NULL,
+  day TEXT NOT NULL,
+  pings INTEGER NOT NULL,
+  bad_pings INTEGER NOT NULL,
+  downtime_seconds INTEGER NOT NULL,
+  incidents INTEGER NOT NULL,
+  PRIMARY KEY (monitor_id, day),
+  FOREIGN KEY (monitor_id) REFERENCES monitor(id) ON DELETE CASCADE
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS monitor_dependency (
+  monitor_id INTEGER NOT NULL,
+  parent_id INTEGER NOT NULL,
+  PRIMARY KEY (monitor_id, parent_id),
+  FOREIGN KEY (monitor_id) REFERENCES monitor(id) ON DELETE CASCADE,
+  FOREIGN KEY (parent_id) REFERENCES monitor(id) ON DELETE CASCADE
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS monitor_flap (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  monitor_id INTEGER NOT NULL,
+  started_at TEXT NOT NULL,
+  ended_at TEXT,
+  FOREIGN KEY (monitor_id) REFERENCES monitor(id) ON DELETE CASCADE
+);
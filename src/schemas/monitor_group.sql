@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS monitor_group (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  parent_id INTEGER,
+  FOREIGN KEY (parent_id) REFERENCES monitor_group(id) ON DELETE SET NULL
+);
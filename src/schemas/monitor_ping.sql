@@ -5,5 +5,20 @@ CREATE TABLE IF NOT EXISTS monitor_ping (
     timestamp TEXT NOT NULL,
     duration_ms INTEGER NOT NULL,
     bad INTEGER NOT NULL,
+    maintenance INTEGER NOT NULL DEFAULT 0,
+    unreachable INTEGER NOT NULL DEFAULT 0,
+    degraded INTEGER NOT NULL DEFAULT 0,
+    dns_ms INTEGER,
+    connect_ms INTEGER,
+    tls_ms INTEGER,
+    ttfb_ms INTEGER,
+    download_ms INTEGER,
+    round_trip_ms INTEGER,
+    icmp_sent INTEGER,
+    icmp_received INTEGER,
+    rtt_min_ms REAL,
+    rtt_avg_ms REAL,
+    rtt_max_ms REAL,
+    jitter_ms REAL,
     FOREIGN KEY (monitor_id) REFERENCES monitor(id)
 );
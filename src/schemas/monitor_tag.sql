@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS monitor_tag (
+  monitor_id INTEGER NOT NULL,
+  tag_id INTEGER NOT NULL,
+  value TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (monitor_id, tag_id, value),
+  FOREIGN KEY (monitor_id) REFERENCES monitor(id) ON DELETE CASCADE,
+  FOREIGN KEY (tag_id) REFERENCES tag(id) ON DELETE CASCADE
+);
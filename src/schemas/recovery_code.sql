@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS recovery_code (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL,
+  code_hash TEXT NOT NULL,
+  used_at TEXT,
+  FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE
+);
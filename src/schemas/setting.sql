@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS setting (
+  key TEXT NOT NULL PRIMARY KEY,
+  value TEXT NOT NULL
+);
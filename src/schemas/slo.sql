@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS slo (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  monitor_id INTEGER,
+  group_id INTEGER,
+  target REAL NOT NULL,
+  threshold_ms INTEGER,
+  window_days INTEGER NOT NULL,
+  alerts TEXT NOT NULL,
+  firing TEXT,
+  created_by INTEGER,
+  FOREIGN KEY (monitor_id) REFERENCES monitor(id) ON DELETE CASCADE,
+  FOREIGN KEY (group_id) REFERENCES monitor_group(id) ON DELETE CASCADE,
+  FOREIGN KEY (created_by) REFERENCES user(id) ON DELETE SET NULL
+);
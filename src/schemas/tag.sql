@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS tag (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE,
+  color TEXT NOT NULL
+);
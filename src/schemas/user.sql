@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS user (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  username TEXT NOT NULL UNIQUE,
+  password_hash TEXT NOT NULL,
+  role TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  external_id TEXT UNIQUE,
+  totp_secret TEXT,
+  totp_last_step INTEGER
+);
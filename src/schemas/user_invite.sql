@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS user_invite (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  token TEXT NOT NULL UNIQUE,
+  role TEXT NOT NULL,
+  created_by INTEGER NOT NULL,
+  created_at TEXT NOT NULL,
+  accepted_at TEXT,
+  FOREIGN KEY (created_by) REFERENCES user(id) ON DELETE CASCADE
+);
@@ -0,0 +1,521 @@
+use crate::{
+    database::{DatabaseModel, Monitor},
+    group::Group,
+    notification::{Notification, NotifyConfig},
+    time::PrettyPrint,
+    utils,
+};
+use chrono::{Duration, Local};
+use rocket::FromForm;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::fmt::Display;
+
+/// Page, page and ticket thresholds from the SRE workbook
+pub const DEFAULT_ALERTS: &str = "14.4:1h:5m, 6:6h:30m, 1:3d:6h";
+
+/// Window of the burn rate shown on the dashboard
+const DASHBOARD_BURN_MINUTES: i64 = 60;
+
+/// How often the burn rate alerts are evaluated
+const EVALUATE_EVERY_SECONDS: u64 = 60;
+
+/// A service level objective for a monitor or every monitor in a group and
+/// its subgroups. A ping is good when it didn't fail and, with a threshold,
+/// answered within `threshold_ms`. Pings during maintenance don't count
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Slo {
+    pub id: i64,
+    pub name: String,
+    pub monitor_id: Option<i64>,
+    pub group_id: Option<i64>,
+    /// Percentage of good pings, like 99.9
+    pub target: f64,
+    pub threshold_ms: Option<i64>,
+    pub window_days: i64,
+    /// Burn rate alerts like `14.4:1h:5m`, see `BurnAlert`
+    pub alerts: String,
+    /// The alert currently firing
+    pub firing: Option<String>,
+    pub created_by: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, FromForm)]
+pub struct SloForm {
+    pub name: String,
+    pub monitor_id: Option<i64>,
+    pub group_id: Option<i64>,
+    pub target: f64,
+    pub threshold_ms: Option<i64>,
+    pub window_days: i64,
+    pub alerts: Option<String>,
+}
+
+/// Fires when the burn rate over both the long and the short window is at
+/// least `burn_rate`, the short window makes it resolve quickly once the
+/// errors stop. Written as `burn_rate:long:short` with m, h or d windows
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurnAlert {
+    pub burn_rate: f64,
+    pub long_minutes: i64,
+    pub short_minutes: i64,
+}
+
+/// Pings counted against an objective
+#[derive(Debug, Clone, Copy, Default)]
+struct Events {
+    total: i64,
+    good: i64,
+}
+
+impl Events {
+    fn error_rate(&self) -> Option<f64> {
+        if self.total == 0 {
+            return None;
+        }
+
+        Some((self.total - self.good) as f64 / self.total as f64)
+    }
+}
+
+/// An objective with its numbers over its window, as shown on the dashboard
+#[derive(Debug, Clone, Serialize)]
+pub struct SloStatus {
+    #[serde(flatten)]
+    pub slo: Slo,
+    /// The monitor name or group path
+    pub target_name: String,
+    pub events: i64,
+    pub good_events: i64,
+    /// Percentage of good pings over the window
+    pub compliance: Option<f64>,
+    /// Percentage of the error budget left, negative once it's spent
+    pub budget_remaining: Option<f64>,
+    /// Burn rate over the last hour, 1 spends the budget exactly over the window
+    pub burn_rate: Option<f64>,
+}
+
+fn parse_minutes(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+    let amount: i64 = amount.parse().ok().filter(|amount| *amount > 0)?;
+
+    match unit {
+        "m" => Some(amount),
+        "h" => Some(amount * 60),
+        "d" => Some(amount * 60 * 24),
+        _ => None,
+    }
+}
+
+fn format_minutes(minutes: i64) -> String {
+    if minutes % (60 * 24) == 0 {
+        format!("{}d", minutes / (60 * 24))
+    } else if minutes % 60 == 0 {
+        format!("{}h", minutes / 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+impl BurnAlert {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = value.trim().split(':').collect();
+        let [burn_rate, long, short] = parts.as_slice() else {
+            return Err(format!("`{}` isn't burn_rate:long:short", value.trim()));
+        };
+
+        let alert = BurnAlert {
+            burn_rate: burn_rate
+                .trim()
+                .parse()
+                .ok()
+                .filter(|rate: &f64| *rate > 0.0)
+                .ok_or(format!("Invalid burn rate `{}`", burn_rate.trim()))?,
+            long_minutes: parse_minutes(long).ok_or(format!("Invalid window `{}`", long.trim()))?,
+            short_minutes: parse_minutes(short)
+                .ok_or(format!("Invalid window `{}`", short.trim()))?,
+        };
+        if alert.short_minutes > alert.long_minutes {
+            return Err(format!(
+                "The short window of `{}` is longer than the long one",
+                alert
+            ));
+        }
+
+        Ok(alert)
+    }
+
+    /// Comma separated alerts, the first one matching wins so the most
+    /// severe should come first
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value
+            .split(',')
+            .filter(|alert| !alert.trim().is_empty())
+            .map(BurnAlert::parse)
+            .collect()
+    }
+}
+
+impl Display for BurnAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.burn_rate,
+            format_minutes(self.long_minutes),
+            format_minutes(self.short_minutes)
+        )
+    }
+}
+
+impl Slo {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/slo.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    pub fn from_form(form: &SloForm, created_by: Option<i64>) -> Result<Self, String> {
+        let name = form.name.trim();
+        if name.is_empty() {
+            return Err("The objective needs a name".to_string());
+        }
+        if form.monitor_id.is_some() == form.group_id.is_some() {
+            return Err("Pick either a monitor or a group".to_string());
+        }
+        if !(form.target > 0.0 && form.target < 100.0) {
+            return Err("The target has to be between 0 and 100%".to_string());
+        }
+        if form.threshold_ms.is_some_and(|threshold| threshold <= 0) {
+            return Err("The response time threshold has to be positive".to_string());
+        }
+        if !(1..=366).contains(&form.window_days) {
+            return Err("The window has to be between 1 and 366 days".to_string());
+        }
+        let alerts = form
+            .alerts
+            .as_deref()
+            .map(str::trim)
+            .filter(|alerts| !alerts.is_empty())
+            .unwrap_or(DEFAULT_ALERTS);
+        let alerts = BurnAlert::parse_list(alerts)?;
+
+        Ok(Slo {
+            id: 0, // field ignored, this is an autoincrement field
+            name: name.to_string(),
+            monitor_id: form.monitor_id,
+            group_id: form.group_id,
+            target: form.target,
+            threshold_ms: form.threshold_ms,
+            window_days: form.window_days,
+            alerts: alerts
+                .iter()
+                .map(BurnAlert::to_string)
+                .collect::<Vec<String>>()
+                .join(", "),
+            firing: None,
+            created_by,
+        })
+    }
+
+    /// Allowed share of bad pings
+    fn budget(&self) -> f64 {
+        1.0 - self.target / 100.0
+    }
+
+    /// Like `99.9% over 30 days` or `95% under 300ms over 30 days`
+    pub fn describe(&self) -> String {
+        match self.threshold_ms {
+            Some(threshold) => format!(
+                "{}% under {}ms over {} days",
+                self.target, threshold, self.window_days
+            ),
+            None => format!("{}% over {} days", self.target, self.window_days),
+        }
+    }
+
+    /// The monitor or the monitors of the group and its subgroups
+    pub fn monitor_ids(&self, monitors: &[Monitor], groups: &[Group]) -> Vec<i64> {
+        if let Some(monitor_id) = self.monitor_id {
+            return vec![monitor_id];
+        }
+
+        let subtree = self
+            .group_id
+            .map(|id| Group::subtree(id, groups))
+            .unwrap_or_default();
+        monitors
+            .iter()
+            .filter(|monitor| monitor.group_id.is_some_and(|id| subtree.contains(&id)))
+            .map(|monitor| monitor.id)
+            .collect()
+    }
+
+    async fn events(
+        &self,
+        monitor_ids: &[i64],
+        minutes: i64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Events, sqlx::Error> {
+        let since = (Local::now() - Duration::minutes(minutes)).pretty_string();
+        let mut events = Events::default();
+
+        for monitor_id in monitor_ids.iter() {
+            let query_result = sqlx::query!(
+                r#"
+                SELECT COUNT(*) AS "total!: i64", SUM(CASE WHEN bad = 0 AND (?3 IS NULL OR duration_ms <= ?3) THEN 1 ELSE 0 END) AS "good: i64"
+                FROM monitor_ping WHERE monitor_id = ?1 AND timestamp >= ?2 AND maintenance = 0
+                "#,
+                monitor_id,
+                since,
+                self.threshold_ms
+            )
+            .fetch_one(pool)
+            .await?;
+
+            events.total += query_result.total;
+            events.good += query_result.good.unwrap_or(0);
+        }
+
+        Ok(events)
+    }
+
+    async fn burn_rate(
+        &self,
+        monitor_ids: &[i64],
+        minutes: i64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Option<f64>, sqlx::Error> {
+        let events = self.events(monitor_ids, minutes, pool).await?;
+
+        Ok(events.error_rate().map(|rate| rate / self.budget()))
+    }
+
+    pub async fn status(
+        self,
+        monitors: &[Monitor],
+        groups: &[Group],
+        pool: &Pool<Sqlite>,
+    ) -> Result<SloStatus, sqlx::Error> {
+        let monitor_ids = self.monitor_ids(monitors, groups);
+        let events = self
+            .events(&monitor_ids, self.window_days * 60 * 24, pool)
+            .await?;
+        let burn_rate = self
+            .burn_rate(&monitor_ids, DASHBOARD_BURN_MINUTES, pool)
+            .await?;
+
+        let target_name = match (self.monitor_id, self.group_id) {
+            (Some(id), _) => monitors
+                .iter()
+                .find(|monitor| monitor.id == id)
+                .map(|monitor| monitor.name.clone())
+                .unwrap_or_else(|| format!("Monitor {}", id)),
+            (None, Some(id)) => Group::paths(groups)
+                .into_iter()
+                .find(|(group_id, _)| *group_id == id)
+                .map(|(_, path)| path)
+                .unwrap_or_else(|| format!("Group {}", id)),
+            (None, None) => String::new(),
+        };
+
+        Ok(SloStatus {
+            target_name,
+            events: events.total,
+            good_events: events.good,
+            compliance: events.error_rate().map(|rate| (1.0 - rate) * 100.0),
+            budget_remaining: events
+                .error_rate()
+                .map(|rate| (1.0 - rate / self.budget()) * 100.0),
+            burn_rate,
+            slo: self,
+        })
+    }
+
+    /// The first alert whose long and short window both burn fast enough
+    async fn evaluate(
+        &self,
+        monitors: &[Monitor],
+        groups: &[Group],
+        pool: &Pool<Sqlite>,
+    ) -> Result<Option<BurnAlert>, sqlx::Error> {
+        let monitor_ids = self.monitor_ids(monitors, groups);
+
+        // stored alerts were validated when the objective was created
+        for alert in BurnAlert::parse_list(&self.alerts).unwrap_or_default() {
+            let long = self
+                .burn_rate(&monitor_ids, alert.long_minutes, pool)
+                .await?;
+            let short = self
+                .burn_rate(&monitor_ids, alert.short_minutes, pool)
+                .await?;
+            if long.is_some_and(|rate| rate >= alert.burn_rate)
+                && short.is_some_and(|rate| rate >= alert.burn_rate)
+            {
+                return Ok(Some(alert));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT * FROM slo ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|slo| Slo {
+                id: slo.id,
+                name: slo.name,
+                monitor_id: slo.monitor_id,
+                group_id: slo.group_id,
+                target: slo.target,
+                threshold_ms: slo.threshold_ms,
+                window_days: slo.window_days,
+                alerts: slo.alerts,
+                firing: slo.firing,
+                created_by: slo.created_by,
+            })
+            .collect())
+    }
+
+    pub async fn by_id(id: i64, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let slo = sqlx::query!(
+            r#"
+            SELECT * FROM slo WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Slo {
+            id: slo.id,
+            name: slo.name,
+            monitor_id: slo.monitor_id,
+            group_id: slo.group_id,
+            target: slo.target,
+            threshold_ms: slo.threshold_ms,
+            window_days: slo.window_days,
+            alerts: slo.alerts,
+            firing: slo.firing,
+            created_by: slo.created_by,
+        })
+    }
+
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            INSERT INTO slo (name, monitor_id, group_id, target, threshold_ms, window_days, alerts, created_by) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            self.name,
+            self.monitor_id,
+            self.group_id,
+            self.target,
+            self.threshold_ms,
+            self.window_days,
+            self.alerts,
+            self.created_by
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(Slo {
+            id: query_result.last_insert_rowid(),
+            ..self.clone()
+        })
+    }
+
+    async fn set_firing(
+        &self,
+        firing: Option<&str>,
+        pool: &Pool<Sqlite>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE slo SET firing = ? WHERE id = ?
+            "#,
+            firing,
+            self.id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM slo WHERE id = ?
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Evaluates every objective once a minute and notifies when an alert starts
+/// firing, changes or resolves
+async fn evaluate_all(config: &NotifyConfig, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let monitors = Monitor::all(pool).await?;
+    let groups = Group::all(pool).await?;
+
+    for slo in Slo::all(pool).await? {
+        let alert = slo.evaluate(&monitors, &groups, pool).await?;
+        let firing = alert.as_ref().map(BurnAlert::to_string);
+        if firing == slo.firing {
+            continue;
+        }
+        slo.set_firing(firing.as_deref(), pool).await?;
+
+        let notification = match alert {
+            Some(alert) => Notification::new(
+                "slo.burning",
+                format!("{} is burning its error budget", slo.name),
+                format!(
+                    "{} ({}) burns at least {}x over {} and {}",
+                    slo.name,
+                    slo.describe(),
+                    alert.burn_rate,
+                    format_minutes(alert.long_minutes),
+                    format_minutes(alert.short_minutes)
+                ),
+            ),
+            None => Notification::new(
+                "slo.recovered",
+                format!("{} recovered", slo.name),
+                format!(
+                    "{} ({}) is back under its burn rate alerts",
+                    slo.name,
+                    slo.describe()
+                ),
+            ),
+        };
+        notification.send(config).await;
+    }
+
+    Ok(())
+}
+
+pub fn schedule(config: NotifyConfig, pool: Pool<Sqlite>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = evaluate_all(&config, &pool).await {
+                warn!("Failed to evaluate SLOs: {}", err);
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(EVALUATE_EVERY_SECONDS)).await;
+        }
+    });
+}
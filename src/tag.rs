@@ -0,0 +1,263 @@
+use crate::utils;
+use rocket::FromForm;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+
+/// Colors new tags cycle through, from the rosé pine palette used by the theme
+pub const COLORS: [&str; 6] = [
+    "#eb6f92", "#f6c177", "#ebbcba", "#31748f", "#9ccfd8", "#c4a7e7",
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub color: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct TagColorForm {
+    pub color: String,
+}
+
+/// A tag attached to a monitor, the same tag can be attached with several values
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MonitorTag {
+    pub monitor_id: i64,
+    pub tag_id: i64,
+    pub name: String,
+    pub value: String,
+    pub color: String,
+}
+
+/// Parses a comma separated list like `env:prod, db` into names and values
+pub fn parse_tags(input: &str) -> Vec<(String, String)> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| match tag.split_once(':') {
+            Some((name, value)) => (name.trim().to_string(), value.trim().to_string()),
+            None => (tag.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Accepts `#rrggbb` as sent by a color input
+pub fn is_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl Tag {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/tag.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+        let schema = utils::parse_sql_file("src/schemas/monitor_tag.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT id, name, color FROM tag ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|tag| Tag {
+                id: tag.id,
+                name: tag.name,
+                color: tag.color,
+            })
+            .collect())
+    }
+
+    pub async fn by_id(id: i64, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let tag = sqlx::query!(
+            r#"
+            SELECT id, name, color FROM tag WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Tag {
+            id: tag.id,
+            name: tag.name,
+            color: tag.color,
+        })
+    }
+
+    pub async fn find_or_create(name: &str, pool: &Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS count FROM tag
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+        let color = COLORS[query_result.count as usize % COLORS.len()];
+
+        sqlx::query!(
+            r#"
+            INSERT INTO tag (name, color) VALUES (?, ?) ON CONFLICT(name) DO NOTHING
+            "#,
+            name,
+            color
+        )
+        .execute(pool)
+        .await?;
+
+        let tag = sqlx::query!(
+            r#"
+            SELECT id, name, color FROM tag WHERE name = ?
+            "#,
+            name
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Tag {
+            id: tag.id,
+            name: tag.name,
+            color: tag.color,
+        })
+    }
+
+    pub async fn set_color(id: i64, color: &str, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE tag SET color = ? WHERE id = ?
+            "#,
+            color,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Also detaches the tag from every monitor
+    pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM tag WHERE id = ?
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl MonitorTag {
+    /// `name` or `name:value`, the format used in filters and forms
+    pub fn key(&self) -> String {
+        if self.value.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}:{}", self.name, self.value)
+        }
+    }
+
+    /// A filter of just a name matches the tag with any value
+    pub fn matches(&self, filter: &str) -> bool {
+        match filter.split_once(':') {
+            Some((name, value)) => self.name == name && self.value == value,
+            None => self.name == filter,
+        }
+    }
+
+    pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT monitor_tag.monitor_id, monitor_tag.tag_id, tag.name, monitor_tag.value, tag.color
+            FROM monitor_tag JOIN tag ON tag.id = monitor_tag.tag_id
+            ORDER BY tag.name, monitor_tag.value
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|tag| MonitorTag {
+                monitor_id: tag.monitor_id,
+                tag_id: tag.tag_id,
+                name: tag.name,
+                value: tag.value,
+                color: tag.color,
+            })
+            .collect())
+    }
+
+    pub async fn by_monitor(
+        monitor_id: i64,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT monitor_tag.monitor_id, monitor_tag.tag_id, tag.name, monitor_tag.value, tag.color
+            FROM monitor_tag JOIN tag ON tag.id = monitor_tag.tag_id
+            WHERE monitor_tag.monitor_id = ?
+            ORDER BY tag.name, monitor_tag.value
+            "#,
+            monitor_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(query_result
+            .into_iter()
+            .map(|tag| MonitorTag {
+                monitor_id: tag.monitor_id,
+                tag_id: tag.tag_id,
+                name: tag.name,
+                value: tag.value,
+                color: tag.color,
+            })
+            .collect())
+    }
+
+    /// Replaces the tags of a monitor, unknown tag names are created
+    pub async fn set_for_monitor(
+        monitor_id: i64,
+        tags: &[(String, String)],
+        pool: &Pool<Sqlite>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM monitor_tag WHERE monitor_id = ?
+            "#,
+            monitor_id
+        )
+        .execute(pool)
+        .await?;
+
+        for (name, value) in tags.iter() {
+            let tag = Tag::find_or_create(name, pool).await?;
+            sqlx::query!(
+                r#"
+                INSERT INTO monitor_tag (monitor_id, tag_id, value) VALUES (?, ?, ?)
+                ON CONFLICT DO NOTHING
+                "#,
+                monitor_id,
+                tag.id,
+                value
+            )
+            .execute(pool)
+            .await?;
+        }
+
+        MonitorTag::by_monitor(monitor_id, pool).await
+    }
+}
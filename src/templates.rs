@@ -1,45 +1,277 @@
-use crate::database::{Monitor, MonitorPing};
+use crate::api_token::{ApiToken, Scope};
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::auth::{Role, User, UserInvite};
+use crate::backup::Backup;
+use crate::database::{Monitor, MonitorPing, MonitorState, ResponseStats};
+use crate::export::ImportSummary;
+use crate::group::Group;
+use crate::maintenance::{MaintenanceWindow, Occurrence};
+use crate::ping::Protocol;
+use crate::probe::EchoStats;
+use crate::report::{Report, ReportFormat};
+use crate::rollup::CalendarWeek;
+use crate::slo::SloStatus;
+use crate::tag::{MonitorTag, Tag};
+use crate::time::{GraphRange, RangePreset};
+use crate::uptime_kuma::KumaImportReport;
 use askama_rocket::Template;
+use serde::Serialize;
 
 // Views
 
+#[derive(Template)]
+#[template(path = "views/login.html")]
+pub struct LoginViewTemplate<'a> {
+    pub title: &'a str,
+    pub setup: bool,
+    pub sso: bool,
+}
+
+#[derive(Template)]
+#[template(path = "views/accept_invite.html")]
+pub struct AcceptInviteViewTemplate<'a> {
+    pub title: &'a str,
+    pub invite: UserInvite,
+}
+
+#[derive(Template)]
+#[template(path = "views/users.html")]
+pub struct UsersViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub users: Vec<User>,
+    pub invites: Vec<UserInvite>,
+    pub roles: [Role; 3],
+    pub require_totp: bool,
+}
+
+#[derive(Template)]
+#[template(path = "views/totp_login.html")]
+pub struct TotpLoginViewTemplate<'a> {
+    pub title: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "views/totp.html")]
+pub struct TotpViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub required: bool,
+    pub secret: Option<String>,
+    pub qr_code: Option<String>,
+    pub remaining_codes: i64,
+}
+
 #[derive(Template)]
 #[template(path = "views/create_monitor.html")]
 pub struct CreateMonitorViewTemplate<'a> {
     pub title: &'a str,
+    /// Group ids with their full path, see `Group::paths`
+    pub groups: Vec<(i64, String)>,
+    pub monitors: Vec<Monitor>,
+    pub protocols: [Protocol; 5],
 }
 
 #[derive(Template)]
 #[template(path = "views/monitor.html")]
 pub struct MonitorViewTemplate<'a> {
     pub title: &'a str,
+    pub user: User,
     pub monitor: Monitor,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
     pub monitor_list_view: MonitorListComponentTemplate,
     pub uptime_graph: UptimeGraphTemplate,
+    pub range: GraphRange,
+    pub presets: [RangePreset; 5],
+    /// Running and upcoming maintenance of this monitor
+    pub maintenance: Vec<Occurrence>,
+    /// Monitors this one depends on, and monitors that depend on it
+    pub upstream: Vec<DependencyNode>,
+    pub downstream: Vec<DependencyNode>,
 }
 
 #[derive(Template)]
 #[template(path = "views/index.html")]
 pub struct IndexTemplate<'a> {
     pub title: &'a str,
+    pub user: User,
     pub monitors: Vec<Monitor>,
     pub monitor_list_view: MonitorListComponentTemplate,
+    pub slos: Vec<SloStatus>,
+}
+
+#[derive(Template)]
+#[template(path = "views/tags.html")]
+pub struct TagsViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub tags: Vec<Tag>,
+    pub group_paths: Vec<(i64, String)>,
+}
+
+pub struct MaintenanceItem {
+    pub window: MaintenanceWindow,
+    /// The monitor name or tag the window applies to
+    pub target: String,
+    pub next: Option<Occurrence>,
+}
+
+#[derive(Template)]
+#[template(path = "views/maintenance.html")]
+pub struct MaintenanceViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub windows: Vec<MaintenanceItem>,
+    pub monitors: Vec<Monitor>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "views/slo.html")]
+pub struct SloViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub slos: Vec<SloStatus>,
+    pub monitors: Vec<Monitor>,
+    /// Group ids with their full path
+    pub groups: Vec<(i64, String)>,
+    pub default_alerts: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "views/reports.html")]
+pub struct ReportsViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    /// Group ids with their full path
+    pub groups: Vec<(i64, String)>,
+    pub formats: [ReportFormat; 3],
+    pub start: String,
+    pub end: String,
+}
+
+/// Standalone with inline styles so it prints and emails as is
+#[derive(Template)]
+#[template(path = "report.html")]
+pub struct ReportTemplate {
+    pub report: Report,
+}
+
+#[derive(Template)]
+#[template(path = "views/tokens.html")]
+pub struct TokensViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub tokens: Vec<ApiToken>,
+    pub scopes: [Scope; 3],
+}
+
+#[derive(Template)]
+#[template(path = "views/audit.html")]
+pub struct AuditViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub entries: Vec<AuditEntry>,
+    pub actions: Vec<String>,
+    pub filter: AuditFilter,
+}
+
+#[derive(Template)]
+#[template(path = "views/export.html")]
+pub struct ExportViewTemplate<'a> {
+    pub title: &'a str,
+    pub user: User,
+    pub backups: Vec<Backup>,
 }
 
 // Components
 
 #[derive(Template)]
+#[template(path = "components/import_result.html")]
+pub struct ImportResultTemplate {
+    pub summary: ImportSummary,
+}
+
+#[derive(Template)]
+#[template(path = "components/backup_list.html")]
+pub struct BackupListTemplate {
+    pub backups: Vec<Backup>,
+}
+
+#[derive(Template)]
+#[template(path = "components/kuma_import_result.html")]
+pub struct KumaImportResultTemplate {
+    pub report: KumaImportReport,
+}
+
+#[derive(Template)]
+#[template(path = "components/recovery_codes.html")]
+pub struct RecoveryCodesTemplate {
+    pub codes: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "components/api_token_created.html")]
+pub struct ApiTokenCreatedTemplate {
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "components/monitor_card.html")]
 pub struct MonitorListItem {
     pub monitor: Monitor,
     pub uptime_percentage: i64,
     pub up: bool,
+    /// Up but slower than usual
+    pub degraded: bool,
+    /// Changing state too often, see `Flap`
+    pub flapping: bool,
+    pub state: MonitorState,
+    pub tags: Vec<MonitorTag>,
+}
+
+/// A group in the monitor list, `state` is the worst state of everything in it
+#[derive(Template, Serialize)]
+#[template(path = "components/monitor_group.html")]
+pub struct MonitorGroupItem {
+    pub group: Group,
+    pub state: MonitorState,
+    pub groups: Vec<MonitorGroupItem>,
+    pub items: Vec<MonitorListItem>,
+}
+
+/// A monitor in the dependency graph of the monitor page, `children` are its
+/// parents when walking upstream
+#[derive(Template, Serialize)]
+#[template(path = "components/dependency_node.html")]
+pub struct DependencyNode {
+    pub monitor: Monitor,
+    pub state: MonitorState,
+    pub children: Vec<DependencyNode>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/monitor_list_items.html")]
+pub struct MonitorListItemsTemplate {
+    pub groups: Vec<MonitorGroupItem>,
+    /// Monitors outside of any group
+    pub items: Vec<MonitorListItem>,
+}
+
+pub struct TagChip {
+    pub key: String,
+    pub color: String,
+    pub selected: bool,
 }
 
 #[derive(Template)]
 #[template(path = "components/monitor_list.html")]
 pub struct MonitorListComponentTemplate {
-    pub items: Vec<MonitorListItem>,
+    pub chips: Vec<TagChip>,
+    pub search: String,
+    pub list: MonitorListItemsTemplate,
 }
 
 #[derive(Template)]
@@ -47,6 +279,33 @@ pub struct MonitorListComponentTemplate {
 pub struct UptimeGraphTemplate {
     pub uptime_graph: Option<Vec<MonitorPing>>,
     pub monitor: Monitor,
+    pub range: GraphRange,
+    /// Over every ping in the range, not just the drawn points
+    pub stats: Option<ResponseStats>,
+}
+
+impl UptimeGraphTemplate {
+    /// Whether any drawn ping has a timing breakdown
+    pub fn has_timings(&self) -> bool {
+        self.uptime_graph
+            .iter()
+            .flatten()
+            .any(|ping| ping.timings.is_some())
+    }
+
+    /// Packet loss and round trip times of the newest ICMP check
+    pub fn last_icmp(&self) -> Option<EchoStats> {
+        self.uptime_graph
+            .iter()
+            .flatten()
+            .find_map(|ping| ping.icmp)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "components/uptime_calendar.html")]
+pub struct UptimeCalendarTemplate {
+    pub weeks: Vec<CalendarWeek>,
 }
 
 #[derive(Template)]
@@ -55,6 +314,7 @@ pub struct MonitorStatusBadgeTemplate {
     pub uptime_percentage: i64,
     pub monitor: Monitor,
     pub up: bool,
+    pub degraded: bool,
 }
 
 #[derive(Template)]
@@ -67,4 +327,11 @@ pub struct CreateMonitorResultTemplate {
 #[template(path = "components/edit_monitor.html")]
 pub struct EditMonitorView {
     pub monitor: Monitor,
+    pub groups: Vec<(i64, String)>,
+    /// Current tags in the comma separated form the input expects
+    pub tags: String,
+    /// Monitors that can be picked as a parent
+    pub monitors: Vec<Monitor>,
+    pub parents: Vec<i64>,
+    pub protocols: [Protocol; 5],
 }
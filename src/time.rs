@@ -1,4 +1,5 @@
 use chrono::{prelude::*, Duration};
+use rocket::{http::RawStr, FromForm, FromFormField};
 
 pub trait PrettyPrint {
     fn pretty_string(&self) -> String;
@@ -10,7 +11,7 @@ impl PrettyPrint for DateTime<Local> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DateOffset {
     pub start: DateTime<Local>,
     pub end: DateTime<Local>,
@@ -23,15 +24,22 @@ impl DateOffset {
         Self { start, end: now }
     }
 
+    /// The first hour of the day, which is past midnight where daylight
+    /// saving time skips it
     pub fn normalize_date(date: DateTime<Local>) -> Result<DateTime<Local>, std::io::Error> {
-        let midnight = chrono::naive::NaiveTime::from_hms_opt(0, 0, 0);
-        Ok(date.with_time(midnight.unwrap()).unwrap())
+        (0..24)
+            .filter_map(|hour| chrono::naive::NaiveTime::from_hms_opt(hour, 0, 0))
+            .find_map(|time| date.with_time(time).earliest())
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} has no start of day", date),
+            ))
     }
 
     /// Normalize the start and end dates to midnight
     pub fn normalize(&self) -> Self {
         DateOffset {
-            start: DateOffset::normalize_date(self.start).unwrap(),
+            start: DateOffset::normalize_date(self.start).unwrap_or(self.start),
             end: self.end,
         }
     }
@@ -44,3 +52,138 @@ impl DateOffset {
         (self.start.pretty_string(), self.end.pretty_string())
     }
 }
+
+/// Presets of the graph range selector
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum RangePreset {
+    #[field(value = "1h")]
+    Hour,
+    #[field(value = "24h")]
+    Day,
+    #[field(value = "7d")]
+    Week,
+    #[field(value = "30d")]
+    Month,
+    #[field(value = "90d")]
+    Quarter,
+}
+
+impl RangePreset {
+    pub fn all() -> [RangePreset; 5] {
+        [
+            RangePreset::Hour,
+            RangePreset::Day,
+            RangePreset::Week,
+            RangePreset::Month,
+            RangePreset::Quarter,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RangePreset::Hour => "1h",
+            RangePreset::Day => "24h",
+            RangePreset::Week => "7d",
+            RangePreset::Month => "30d",
+            RangePreset::Quarter => "90d",
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            RangePreset::Hour => Duration::hours(1),
+            RangePreset::Day => Duration::days(1),
+            RangePreset::Week => Duration::days(7),
+            RangePreset::Month => Duration::days(30),
+            RangePreset::Quarter => Duration::days(90),
+        }
+    }
+}
+
+/// `?range=7d` or `?start=..&end=..` as sent by the range selector, a custom
+/// start wins over the preset and the end defaults to now
+#[derive(Debug, Clone, Default, FromForm)]
+pub struct GraphRange {
+    pub range: Option<RangePreset>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+fn parse_local(value: &str) -> Option<DateTime<Local>> {
+    ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value.trim(), format).ok())
+        .and_then(|date| date.and_local_timezone(Local).earliest())
+}
+
+impl GraphRange {
+    fn custom_start(&self) -> Option<DateTime<Local>> {
+        self.start.as_deref().and_then(parse_local)
+    }
+
+    fn custom_end(&self) -> Option<DateTime<Local>> {
+        self.end.as_deref().and_then(parse_local)
+    }
+
+    pub fn is_custom(&self) -> bool {
+        self.custom_start().is_some()
+    }
+
+    /// The preset in use, 24h when neither a preset nor a custom start is given
+    pub fn preset(&self) -> Option<RangePreset> {
+        if self.is_custom() {
+            None
+        } else {
+            Some(self.range.unwrap_or(RangePreset::Day))
+        }
+    }
+
+    /// Ranges ending now move along with the clock and are worth polling
+    pub fn is_live(&self) -> bool {
+        self.custom_end().is_none()
+    }
+
+    pub fn offset(&self) -> DateOffset {
+        let end = self.custom_end().unwrap_or_else(Local::now);
+        let Some(preset) = self.preset() else {
+            let start = self.custom_start().unwrap_or(end);
+            return DateOffset {
+                start: start.min(end),
+                end,
+            };
+        };
+
+        let offset = DateOffset {
+            start: end - preset.duration(),
+            end,
+        };
+        // longer presets start at midnight so every day is complete
+        if preset.duration() >= Duration::days(7) {
+            offset.normalize()
+        } else {
+            offset
+        }
+    }
+
+    /// Query string that reproduces this range, for links and polling
+    pub fn query(&self) -> String {
+        match self.preset() {
+            Some(preset) => format!("range={}", preset.as_str()),
+            None => {
+                let encode = |value: &str| RawStr::new(value.trim()).percent_encode().to_string();
+                let start = self.start.as_deref().unwrap_or_default();
+                let mut query = format!("start={}", encode(start));
+                if let Some(end) = self.end.as_deref().filter(|end| !end.is_empty()) {
+                    query.push_str(&format!("&end={}", encode(end)));
+                }
+                query
+            }
+        }
+    }
+
+    /// Graph labels show dates instead of times for anything longer than a day
+    pub fn is_long(&self) -> bool {
+        let offset = self.offset();
+        offset.end - offset.start > Duration::days(1)
+    }
+}
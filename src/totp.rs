@@ -0,0 +1,249 @@
+use crate::{api_token::hash_token, auth::User, time::PrettyPrint, utils};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::http::{Cookie, CookieJar, SameSite};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+pub const ISSUER: &str = "uptime-rs";
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Holds the key of a login that passed the password check but still has to
+/// provide a second factor
+const PENDING_LOGIN_COOKIE: &str = "pending_login";
+const PENDING_LOGIN_TTL_SECS: i64 = 5 * 60;
+/// Wrong codes a pending login gets before the password has to be entered again
+const MAX_FAILED_CODES: u32 = 5;
+
+/// Holds a freshly generated secret until the user confirms enrollment
+const ENROLLMENT_COOKIE: &str = "totp_enrollment";
+
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+pub fn totp(secret: &str, username: &str) -> Option<TOTP> {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(ISSUER.to_string()),
+        username.replace(':', "_"),
+    )
+    .ok()
+}
+
+pub fn check_code(secret: &str, username: &str, code: &str) -> bool {
+    match totp(secret, username) {
+        Some(totp) => totp.check_current(code.trim()).unwrap_or(false),
+        None => false,
+    }
+}
+
+/// The time step `code` belongs to, the previous and next step are accepted
+/// like `check_code` does for clock drift
+pub fn code_step(secret: &str, username: &str, code: &str) -> Option<i64> {
+    let totp = totp(secret, username)?;
+    let step = totp.step as i64;
+    let current = chrono::Utc::now().timestamp() / step;
+
+    (current - 1..=current + 1)
+        .find(|candidate| totp.generate((candidate * step) as u64) == code.trim())
+}
+
+/// Base64 encoded png of the `otpauth://` url for authenticator apps
+pub fn qr_code(secret: &str, username: &str) -> Option<String> {
+    totp(secret, username)?.get_qr_base64().ok()
+}
+
+struct PendingLogin {
+    user_id: i64,
+    started_at: i64,
+    failed_codes: u32,
+}
+
+/// Logins waiting for their second factor. The cookie only holds a random
+/// key, so the failed codes are counted where the client can't reset them
+#[derive(Default)]
+pub struct PendingLogins(Mutex<HashMap<String, PendingLogin>>);
+
+impl PendingLogins {
+    pub fn start(&self, user: &User, cookies: &CookieJar<'_>) {
+        let key: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+        let now = chrono::Local::now().timestamp();
+
+        let mut logins = self.0.lock().unwrap();
+        logins.retain(|_, login| now - login.started_at <= PENDING_LOGIN_TTL_SECS);
+        logins.insert(
+            key.clone(),
+            PendingLogin {
+                user_id: user.id,
+                started_at: now,
+                failed_codes: 0,
+            },
+        );
+
+        cookies.add_private(
+            Cookie::build((PENDING_LOGIN_COOKIE, key))
+                .same_site(SameSite::Lax)
+                .http_only(true),
+        );
+    }
+
+    pub fn user_id(&self, cookies: &CookieJar<'_>) -> Option<i64> {
+        let cookie = cookies.get_private(PENDING_LOGIN_COOKIE)?;
+        let logins = self.0.lock().unwrap();
+        let login = logins.get(cookie.value())?;
+
+        if chrono::Local::now().timestamp() - login.started_at > PENDING_LOGIN_TTL_SECS {
+            return None;
+        }
+
+        Some(login.user_id)
+    }
+
+    /// Counts a wrong code, false once the login is dropped for too many
+    pub fn fail(&self, cookies: &CookieJar<'_>) -> bool {
+        let Some(cookie) = cookies.get_private(PENDING_LOGIN_COOKIE) else {
+            return false;
+        };
+        let mut logins = self.0.lock().unwrap();
+        let Some(login) = logins.get_mut(cookie.value()) else {
+            return false;
+        };
+
+        login.failed_codes += 1;
+        if login.failed_codes < MAX_FAILED_CODES {
+            return true;
+        }
+
+        logins.remove(cookie.value());
+        cookies.remove_private(Cookie::from(PENDING_LOGIN_COOKIE));
+        false
+    }
+
+    pub fn clear(&self, cookies: &CookieJar<'_>) {
+        if let Some(cookie) = cookies.get_private(PENDING_LOGIN_COOKIE) {
+            self.0.lock().unwrap().remove(cookie.value());
+        }
+        cookies.remove_private(Cookie::from(PENDING_LOGIN_COOKIE));
+    }
+}
+
+/// Returns the secret currently being enrolled, generating one if needed
+pub fn enrollment_secret(cookies: &CookieJar<'_>) -> String {
+    if let Some(cookie) = cookies.get_private(ENROLLMENT_COOKIE) {
+        return cookie.value().to_string();
+    }
+
+    let secret = generate_secret();
+    cookies.add_private(
+        Cookie::build((ENROLLMENT_COOKIE, secret.clone()))
+            .same_site(SameSite::Strict)
+            .http_only(true),
+    );
+    secret
+}
+
+pub fn clear_enrollment(cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::from(ENROLLMENT_COOKIE));
+}
+
+pub struct RecoveryCode;
+
+impl RecoveryCode {
+    pub async fn initialize(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let schema = utils::parse_sql_file("src/schemas/recovery_code.sql").await?;
+        sqlx::query(&schema).execute(pool).await?;
+
+        Ok(())
+    }
+
+    fn generate_code() -> String {
+        let code: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(10)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect();
+        format!("{}-{}", &code[..5], &code[5..])
+    }
+
+    /// Replaces every recovery code of a user, returns the new plaintext codes
+    pub async fn regenerate(user_id: i64, pool: &Pool<Sqlite>) -> Result<Vec<String>, sqlx::Error> {
+        RecoveryCode::delete_for_user(user_id, pool).await?;
+
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| RecoveryCode::generate_code())
+            .collect();
+
+        for code in codes.iter() {
+            let code_hash = hash_token(code);
+            sqlx::query!(
+                r#"
+                INSERT INTO recovery_code (user_id, code_hash) VALUES (?, ?)
+                "#,
+                user_id,
+                code_hash
+            )
+            .execute(pool)
+            .await?;
+        }
+
+        Ok(codes)
+    }
+
+    /// Marks a matching unused code as used, a code only works once
+    pub async fn redeem(user_id: i64, code: &str, pool: &Pool<Sqlite>) -> bool {
+        let code_hash = hash_token(&code.trim().to_ascii_lowercase());
+        let used_at = chrono::Local::now().pretty_string();
+        let query_result = sqlx::query!(
+            r#"
+            UPDATE recovery_code SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL
+            "#,
+            used_at,
+            user_id,
+            code_hash
+        )
+        .execute(pool)
+        .await;
+
+        match query_result {
+            Ok(result) => result.rows_affected() > 0,
+            Err(_) => false,
+        }
+    }
+
+    pub async fn remaining(user_id: i64, pool: &Pool<Sqlite>) -> Result<i64, sqlx::Error> {
+        let query_result = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS count FROM recovery_code WHERE user_id = ? AND used_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(query_result.count as i64)
+    }
+
+    pub async fn delete_for_user(user_id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM recovery_code WHERE user_id = ?
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
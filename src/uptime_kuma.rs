@@ -0,0 +1,237 @@
+use crate::{
+    audit::{Actor, AuditEntry},
+    database::{DatabaseModel, Monitor},
+    ping::{self, PingerManager},
+    tag::MonitorTag,
+};
+use rocket::{fs::TempFile, FromForm};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+
+fn default_interval() -> i64 {
+    60
+}
+
+/// The parts of an Uptime Kuma JSON backup (Settings > Backup > Export) we read
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KumaBackup {
+    #[serde(default)]
+    pub notification_list: Vec<KumaNotification>,
+    #[serde(default)]
+    pub monitor_list: Vec<KumaMonitor>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KumaNotification {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KumaMonitor {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: Option<String>,
+    #[serde(default = "default_interval")]
+    pub interval: i64,
+    #[serde(default)]
+    pub maxretries: i64,
+    /// Older backups store this as 0/1, newer ones as a bool
+    #[serde(default)]
+    pub active: serde_json::Value,
+    #[serde(default)]
+    pub tags: Vec<KumaTag>,
+    #[serde(rename = "notificationIDList", default)]
+    pub notification_id_list: HashMap<String, bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KumaTag {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+#[derive(FromForm)]
+pub struct KumaImportForm<'r> {
+    pub file: TempFile<'r>,
+}
+
+/// Something from the backup that has no equivalent in uptime-rs
+#[derive(Debug, Serialize)]
+pub struct ImportIssue {
+    pub monitor: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct KumaImportReport {
+    pub created: Vec<Monitor>,
+    pub issues: Vec<ImportIssue>,
+}
+
+impl KumaImportReport {
+    fn issue(&mut self, monitor: &KumaMonitor, message: impl Into<String>) {
+        self.issues.push(ImportIssue {
+            monitor: monitor.name.clone(),
+            message: message.into(),
+        });
+    }
+}
+
+impl KumaMonitor {
+    fn is_active(&self) -> bool {
+        match self.active {
+            serde_json::Value::Bool(active) => active,
+            serde_json::Value::Number(ref active) => active.as_i64() != Some(0),
+            _ => true,
+        }
+    }
+
+    /// Host and port to ping, `None` when the monitor can't be checked over http
+    fn target(&self, report: &mut KumaImportReport) -> Option<(String, Option<i64>)> {
+        match self.kind.as_str() {
+            "http" | "keyword" => {
+                let url = self
+                    .url
+                    .as_deref()
+                    .and_then(|url| reqwest::Url::parse(url).ok())
+                    .filter(|url| url.host_str().is_some());
+                let Some(url) = url else {
+                    report.issue(self, "skipped, the url is missing or invalid");
+                    return None;
+                };
+                let host = url.host_str().unwrap_or_default().to_string();
+                let port = url.port().map(i64::from);
+
+                if url.scheme() == "https" {
+                    report.issue(self, "https urls are checked over plain http");
+                }
+                if self.kind == "keyword" {
+                    report.issue(
+                        self,
+                        "imported as a plain http check, keywords aren't checked",
+                    );
+                }
+
+                let path = url.path().trim_end_matches('/');
+                match (path.is_empty(), port) {
+                    (true, _) => Some((host, port)),
+                    (false, None) => Some((format!("{}{}", host, path), None)),
+                    (false, Some(_)) => {
+                        let message = format!("path `{}` dropped, only the host is checked", path);
+                        report.issue(self, message);
+                        Some((host, port))
+                    }
+                }
+            }
+            // a tcp service checked over http would only ever be down
+            "port" | "dns" | "push" => {
+                report.issue(
+                    self,
+                    format!("skipped, {} monitors aren't supported", self.kind),
+                );
+                None
+            }
+            kind => {
+                report.issue(self, format!("skipped, unknown monitor type `{}`", kind));
+                None
+            }
+        }
+    }
+}
+
+/// Maps a single monitor, noting every setting that gets lost on the way
+fn map_monitor(
+    monitor: &KumaMonitor,
+    notifications: &HashMap<String, String>,
+    report: &mut KumaImportReport,
+) -> Option<Monitor> {
+    let (ip, port) = monitor.target(report)?;
+
+    if monitor.maxretries > 0 {
+        report.issue(
+            monitor,
+            format!(
+                "{} retries dropped, every failed ping counts",
+                monitor.maxretries
+            ),
+        );
+    }
+    for (id, _) in monitor.notification_id_list.iter().filter(|(_, on)| **on) {
+        let name = notifications.get(id).map(String::as_str).unwrap_or(id);
+        report.issue(monitor, format!("notification `{}` dropped", name));
+    }
+
+    Some(Monitor {
+        id: 0, // field ignored, this is an autoincrement field
+        name: monitor.name.clone(),
+        ip,
+        port,
+        protocol: ping::Protocol::HTTP,
+        interval: monitor.interval.max(1),
+        paused: !monitor.is_active(),
+        created_by: None,
+        updated_by: None,
+        managed_key: None,
+        group_id: None,
+        degraded_threshold_ms: None,
+        ws_message: None,
+        ws_expect: None,
+        ws_timeout_ms: None,
+        icmp_count: None,
+        icmp_max_loss: None,
+    })
+}
+
+/// Creates a new monitor for every mappable monitor in the backup, ids from
+/// Uptime Kuma are never reused
+pub async fn import(
+    backup: KumaBackup,
+    actor: &Actor,
+    pool: &Pool<Sqlite>,
+    manager: &PingerManager,
+) -> Result<KumaImportReport, sqlx::Error> {
+    let notifications: HashMap<String, String> = backup
+        .notification_list
+        .into_iter()
+        .map(|notification| (notification.id.to_string(), notification.name))
+        .collect();
+
+    let mut report = KumaImportReport::default();
+    for kuma_monitor in backup.monitor_list.iter() {
+        let Some(mut monitor) = map_monitor(kuma_monitor, &notifications, &mut report) else {
+            continue;
+        };
+        monitor.created_by = actor.id;
+
+        let monitor = monitor.create(pool).await?;
+        let tags: Vec<(String, String)> = kuma_monitor
+            .tags
+            .iter()
+            .map(|tag| (tag.name.clone(), tag.value.clone().unwrap_or_default()))
+            .collect();
+        MonitorTag::set_for_monitor(monitor.id, &tags, pool).await?;
+        let interval = monitor.interval;
+        manager
+            .add_pinger(ping::Pinger::new(monitor.clone(), interval, || {}))
+            .await;
+
+        AuditEntry::record(
+            pool,
+            actor,
+            "monitor.create",
+            "monitor",
+            Some(monitor.id),
+            None,
+            Some(&monitor),
+        )
+        .await;
+
+        report.created.push(monitor);
+    }
+
+    Ok(report)
+}
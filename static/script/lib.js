@@ -49,3 +49,51 @@ function dattributes(element, attribute_names) {
 
   return result;
 }
+
+/**
+ * Recolors a status badge from a `ping` event
+ * @param {Element} badge - Element wrapping monitor_status_badge.html
+ * @param {{up: boolean, degraded: boolean, uptime_percentage: number}} ping
+ */
+function updateStatusBadge(badge, ping) {
+  const pill = badge.firstElementChild;
+  if (!pill) return;
+
+  pill.classList.remove("bg-pine", "bg-rose", "bg-love");
+  pill.classList.add(!ping.up ? "bg-love" : ping.degraded ? "bg-rose" : "bg-pine");
+  pill.title = ping.up && ping.degraded ? "Slower than usual" : "";
+  pill.querySelector("p").textContent = `${ping.uptime_percentage}%`;
+}
+
+/**
+ * One stream of server-sent events from `/events` per page. Status badges
+ * marked with `data-live-badge` are updated in place, everything else
+ * listens for the `live-ping` and `live-state` events triggered on the body
+ */
+const LiveUpdates = {
+  /** @type {EventSource | null} */
+  source: null,
+
+  start() {
+    if (this.source) return;
+
+    this.source = new EventSource("/events");
+    this.source.addEventListener("ping", (event) => {
+      const ping = JSON.parse(event.data);
+      const badges = document.querySelectorAll(
+        `[data-live-badge="${ping.monitor_id}"]`,
+      );
+      for (const badge of badges) updateStatusBadge(badge, ping);
+      htmx.trigger(document.body, "live-ping", ping);
+    });
+    this.source.addEventListener("state", (event) => {
+      htmx.trigger(document.body, "live-state", JSON.parse(event.data));
+    });
+  },
+};
+
+document.addEventListener("DOMContentLoaded", () => {
+  if (document.querySelector("[data-live], [data-live-badge]")) {
+    LiveUpdates.start();
+  }
+});